@@ -1,12 +1,17 @@
 use js_sys::Uint8Array;
-use rustendo_lib::cartridge::Cartridge;
-use rustendo_lib::nes::Nes;
-use std::cell::RefCell;
+use rustendo_lib::cartridge::{Cartridge, CartridgeInfo};
+use rustendo_lib::controller::{Button, Controller};
+use rustendo_lib::nes::{Nes, Overscan};
+use rustendo_lib::nsf::Nsf;
+use rustendo_lib::palette;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::{Clamped, JsCast};
 use web_sys::{
-    CanvasRenderingContext2d, Event, HtmlCanvasElement, ImageData, KeyboardEvent, Window,
+    CanvasRenderingContext2d, Event, Gamepad, GamepadButton, HtmlCanvasElement, ImageData,
+    KeyboardEvent, Navigator, Window,
 };
 
 // Leaving this import here to make it easier to use the macro when debugging.
@@ -14,9 +19,69 @@ use web_sys::{
 use rustendo_lib::log;
 
 mod utils;
+#[cfg(feature = "webgl")]
+mod webgl;
+mod zip;
 
 const NES_WIDTH: u32 = 256;
 const NES_HEIGHT: u32 = 240;
+const BUTTONS: [Button; 8] = [
+    Button::A,
+    Button::B,
+    Button::Select,
+    Button::Start,
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+];
+// How far a stick has to be pushed to press a direction.
+const AXIS_THRESHOLD: f64 = 0.5;
+// The NTSC NES runs at 60.0988 frames per second.
+const FRAME_DURATION_MS: f64 = 1000.0 / 60.0988;
+// After a long pause (e.g. a background tab), don't try to catch up on
+// more than this much time at once.
+const MAX_ELAPSED_MS: f64 = 250.0;
+// The slowest and fastest speeds `set_speed` allows.
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 8.0;
+// Holding this key runs the game backwards, if rewind is enabled.
+const REWIND_KEY: &str = "Backspace";
+// CPU cycles in an NTSC frame, 1789773Hz / 60.0988Hz, for playing NSFs.
+const NSF_CYCLES_PER_FRAME: u32 = 29780;
+
+thread_local! {
+    static KEY_MAPPING: Rc<RefCell<HashMap<String, Button>>> =
+        Rc::new(RefCell::new(default_key_mapping()));
+    static GAMEPAD_MAPPING: Rc<RefCell<HashMap<u32, Button>>> =
+        Rc::new(RefCell::new(default_gamepad_mapping()));
+    static NES: RefCell<Option<Rc<RefCell<Nes>>>> = const { RefCell::new(None) };
+    static ROM: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
+    static NSF: RefCell<Option<Nsf>> = const { RefCell::new(None) };
+    static PALETTE: RefCell<Option<Vec<(u8, u8, u8)>>> = const { RefCell::new(None) };
+    static RUNNING: Rc<Cell<bool>> = Rc::new(Cell::new(true));
+    static STEP_FRAME: Rc<Cell<bool>> = Rc::new(Cell::new(false));
+    static SPEED: Rc<Cell<f64>> = Rc::new(Cell::new(1.0));
+    static REWIND_SECONDS: Cell<u32> = const { Cell::new(0) };
+    static REWINDING: Rc<Cell<bool>> = Rc::new(Cell::new(false));
+    static CANVAS_ID: RefCell<String> = RefCell::new(String::from("rustendo-canvas"));
+    static SCALE: Cell<Option<u32>> = const { Cell::new(None) };
+    static ASPECT_CORRECTION: Cell<bool> = const { Cell::new(false) };
+    // Top, bottom, left and right.
+    static OVERSCAN: Cell<(u32, u32, u32, u32)> = const { Cell::new((0, 0, 0, 0)) };
+    static FPS_CALLBACK: Rc<RefCell<Option<js_sys::Function>>> = Rc::new(RefCell::new(None));
+    static SESSION: RefCell<Option<Session>> = const { RefCell::new(None) };
+}
+
+/// The animation loop and event listeners set up by `render` or
+/// `load_nsf`, kept so that `shutdown` can tear them down again. An NSF
+/// takes no input, so it has no key handlers.
+struct Session {
+    animation_frame: Rc<Cell<i32>>,
+    animation: Animation,
+    keydown_handler: Option<Closure<dyn FnMut(KeyboardEvent)>>,
+    keyup_handler: Option<Closure<dyn FnMut(KeyboardEvent)>>,
+}
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -28,10 +93,10 @@ fn window() -> Window {
     web_sys::window().expect("no global `window` exists")
 }
 
-fn request_animation_frame(f: &Closure<dyn FnMut()>) {
+fn request_animation_frame(f: &Closure<dyn FnMut()>) -> i32 {
     window()
         .request_animation_frame(f.as_ref().unchecked_ref())
-        .unwrap();
+        .unwrap()
 }
 
 fn add_event_listener<T>(event: &str, f: &Closure<dyn FnMut(T)>)
@@ -43,6 +108,15 @@ where
         .unwrap();
 }
 
+fn remove_event_listener<T>(event: &str, f: &Closure<dyn FnMut(T)>)
+where
+    T: AsRef<Event>,
+{
+    window()
+        .remove_event_listener_with_callback(event, f.as_ref().unchecked_ref())
+        .unwrap();
+}
+
 fn get_viewport_size() -> (i32, i32) {
     let document_element = window().document().unwrap().document_element().unwrap();
     (
@@ -51,41 +125,291 @@ fn get_viewport_size() -> (i32, i32) {
     )
 }
 
+fn default_key_mapping() -> HashMap<String, Button> {
+    [
+        ("a", Button::A),
+        ("s", Button::B),
+        ("z", Button::Select),
+        ("x", Button::Start),
+        ("ArrowUp", Button::Up),
+        ("ArrowDown", Button::Down),
+        ("ArrowLeft", Button::Left),
+        ("ArrowRight", Button::Right),
+    ]
+    .iter()
+    .map(|(key, button)| (key.to_string(), *button))
+    .collect()
+}
+
+// Single character keys are case insensitive, so that holding shift
+// doesn't change which button a key presses.
+fn normalize_key(key: &str) -> String {
+    if key.chars().count() == 1 {
+        key.to_lowercase()
+    } else {
+        key.to_string()
+    }
+}
+
+fn parse_button(button: &str) -> Option<Button> {
+    match button.to_lowercase().as_str() {
+        "a" => Some(Button::A),
+        "b" => Some(Button::B),
+        "select" => Some(Button::Select),
+        "start" => Some(Button::Start),
+        "up" => Some(Button::Up),
+        "down" => Some(Button::Down),
+        "left" => Some(Button::Left),
+        "right" => Some(Button::Right),
+        _ => None,
+    }
+}
+
+fn map_key(key_mapping: &mut HashMap<String, Button>, button: Button, key: &str) {
+    key_mapping.retain(|_, mapped| *mapped != button);
+    key_mapping.insert(normalize_key(key), button);
+}
+
+fn lookup_key(key_mapping: &HashMap<String, Button>, key: &str) -> Option<Button> {
+    key_mapping.get(&normalize_key(key)).copied()
+}
+
+/// Binds a key (as reported by `KeyboardEvent.key`) to a controller button,
+/// replacing any keys previously bound to that button.
+#[wasm_bindgen]
+pub fn set_key_mapping(button: &str, key: &str) -> Result<(), JsValue> {
+    let button = parse_button(button)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown button: {}", button)))?;
+    KEY_MAPPING.with(|key_mapping| map_key(&mut key_mapping.borrow_mut(), button, key));
+    Ok(())
+}
+
+#[wasm_bindgen]
+pub fn reset_key_mapping() {
+    KEY_MAPPING.with(|key_mapping| *key_mapping.borrow_mut() = default_key_mapping());
+}
+
+// Indexes of the buttons in the standard gamepad layout, with B on the
+// left face button and A on the bottom one, where they sit on an NES pad.
+fn default_gamepad_mapping() -> HashMap<u32, Button> {
+    [
+        (0, Button::A),
+        (2, Button::B),
+        (8, Button::Select),
+        (9, Button::Start),
+        (12, Button::Up),
+        (13, Button::Down),
+        (14, Button::Left),
+        (15, Button::Right),
+    ]
+    .iter()
+    .copied()
+    .collect()
+}
+
+/// Binds a gamepad button, by its index in the standard layout, to a
+/// controller button, replacing any gamepad buttons previously bound to it.
+#[wasm_bindgen]
+pub fn set_gamepad_mapping(button: &str, index: u32) -> Result<(), JsValue> {
+    let button = parse_button(button)
+        .ok_or_else(|| JsValue::from_str(&format!("unknown button: {}", button)))?;
+    GAMEPAD_MAPPING.with(|gamepad_mapping| {
+        let mut gamepad_mapping = gamepad_mapping.borrow_mut();
+        gamepad_mapping.retain(|_, mapped| *mapped != button);
+        gamepad_mapping.insert(index, button);
+    });
+    Ok(())
+}
+
+/// The buttons held on a gamepad, as a controller bitmask. The left stick
+/// works as the D-pad.
+fn gamepad_state(gamepad_mapping: &HashMap<u32, Button>, pressed: &[bool], axes: &[f64]) -> u8 {
+    let mut state = 0;
+
+    for (&index, &button) in gamepad_mapping {
+        if pressed.get(index as usize).copied().unwrap_or(false) {
+            state |= button;
+        }
+    }
+
+    let axis = |index: usize| axes.get(index).copied().unwrap_or(0.0);
+    if axis(0) <= -AXIS_THRESHOLD {
+        state |= Button::Left;
+    }
+    if axis(0) >= AXIS_THRESHOLD {
+        state |= Button::Right;
+    }
+    if axis(1) <= -AXIS_THRESHOLD {
+        state |= Button::Up;
+    }
+    if axis(1) >= AXIS_THRESHOLD {
+        state |= Button::Down;
+    }
+
+    state
+}
+
+/// The buttons held across all connected gamepads. Empty if there are none
+/// or the browser doesn't support gamepads.
+fn poll_gamepads(navigator: &Navigator, gamepad_mapping: &HashMap<u32, Button>) -> u8 {
+    let gamepads = match navigator.get_gamepads() {
+        Ok(gamepads) => gamepads,
+        Err(_) => return 0,
+    };
+
+    // Disconnected slots are null.
+    gamepads
+        .iter()
+        .filter_map(|gamepad| gamepad.dyn_into::<Gamepad>().ok())
+        .filter(|gamepad| gamepad.connected())
+        .map(|gamepad| {
+            let pressed: Vec<bool> = gamepad
+                .buttons()
+                .iter()
+                .map(|button| {
+                    button
+                        .dyn_into::<GamepadButton>()
+                        .map(|button| button.pressed())
+                        .unwrap_or(false)
+                })
+                .collect();
+            let axes: Vec<f64> = gamepad
+                .axes()
+                .iter()
+                .map(|axis| axis.as_f64().unwrap_or(0.0))
+                .collect();
+            gamepad_state(gamepad_mapping, &pressed, &axes)
+        })
+        .fold(0, |state, gamepad| state | gamepad)
+}
+
+/// Presses or lifts only the buttons whose gamepad state changed since the
+/// last poll, so a button held on the keyboard isn't lifted by an idle
+/// gamepad and vice versa.
+fn apply_gamepad_state(controller: &mut Controller, previous: u8, current: u8) {
+    for &button in BUTTONS.iter() {
+        let mask = button as u8;
+        if (previous ^ current) & mask == 0 {
+            continue;
+        }
+
+        if current & mask == mask {
+            controller.press(button);
+        } else {
+            controller.lift(button);
+        }
+    }
+}
+
 #[wasm_bindgen(start)]
 pub fn startup() {
     utils::set_panic_hook();
-    setup_canvas();
 }
 
-fn setup_canvas() {
-    let canvas = get_canvas();
+/// Sizes the canvas with the given id to fit the viewport and draws to it
+/// from then on. Call this once the canvas is in the DOM, before `render`.
+///
+/// Pages written before this existed relied on the module sizing
+/// `#rustendo-canvas` on load, and should now call
+/// `init_canvas("rustendo-canvas")` themselves.
+///
+/// Fails if there is no canvas with that id.
+#[wasm_bindgen]
+pub fn init_canvas(canvas_id: &str) -> Result<(), JsValue> {
+    CANVAS_ID.with(|id| *id.borrow_mut() = canvas_id.to_string());
+    setup_canvas()
+}
+
+/// Sets the canvas to `scale` times the size of the NES screen, or back to
+/// the largest multiple that fits the viewport if `scale` is 0.
+#[wasm_bindgen]
+pub fn set_scale(scale: u32) -> Result<(), JsValue> {
+    SCALE.with(|current| current.set(if scale == 0 { None } else { Some(scale) }));
+    setup_canvas()
+}
+
+/// Stretches the canvas horizontally by 8:7 to match the shape of the pixels
+/// on a TV, instead of drawing them square.
+#[wasm_bindgen]
+pub fn set_aspect_correction(enabled: bool) -> Result<(), JsValue> {
+    ASPECT_CORRECTION.with(|aspect_correction| aspect_correction.set(enabled));
+    setup_canvas()
+}
+
+/// Hides `top`, `bottom`, `left` and `right` pixels at the edges of the
+/// screen, as TVs did, and shrinks the canvas to match. Games loaded
+/// afterwards are cropped the same way.
+#[wasm_bindgen]
+pub fn set_overscan(top: u32, bottom: u32, left: u32, right: u32) -> Result<(), JsValue> {
+    OVERSCAN.with(|overscan| overscan.set((top, bottom, left, right)));
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            nes.borrow_mut().set_overscan(top, bottom, left, right);
+        }
+    });
+    setup_canvas()
+}
+
+fn setup_canvas() -> Result<(), JsValue> {
+    let canvas = get_canvas()?;
+    let aspect_correction = ASPECT_CORRECTION.with(|aspect_correction| aspect_correction.get());
+    let (top, bottom, left, right) = OVERSCAN.with(|overscan| overscan.get());
+    let screen = Overscan::new(top, bottom, left, right).visible_size();
+    let scale = match SCALE.with(|scale| scale.get()) {
+        Some(scale) => scale,
+        None => fit_scale(get_viewport_size(), aspect_correction, screen),
+    };
+
+    let (width, height) = canvas_size(scale, aspect_correction, screen);
+    canvas.set_width(width);
+    canvas.set_height(height);
+    Ok(())
+}
+
+/// The largest multiple of the screen size that fits the viewport, at least 1.
+fn fit_scale(
+    (viewport_width, viewport_height): (i32, i32),
+    aspect_correction: bool,
+    (screen_width, screen_height): (u32, u32),
+) -> u32 {
+    let viewport_width = viewport_width.max(0) as u32;
+    let viewport_height = viewport_height.max(0) as u32;
 
-    let (viewport_width, viewport_height) = get_viewport_size();
-    let multiples_of_width = viewport_width as u32 / NES_WIDTH;
-    let multiples_of_height = viewport_height as u32 / NES_HEIGHT;
-    let smallest_multiple = if multiples_of_width < multiples_of_height {
-        multiples_of_width
+    let multiples_of_width = if aspect_correction {
+        viewport_width * 7 / (screen_width * 8)
     } else {
-        multiples_of_height
+        viewport_width / screen_width
     };
+    let multiples_of_height = viewport_height / screen_height;
 
-    let smallest_multiple = if smallest_multiple == 0 {
-        1
+    multiples_of_width.min(multiples_of_height).max(1)
+}
+
+fn canvas_size(
+    scale: u32,
+    aspect_correction: bool,
+    (screen_width, screen_height): (u32, u32),
+) -> (u32, u32) {
+    let width = screen_width * scale;
+    let width = if aspect_correction {
+        // Round to the nearest pixel.
+        (width * 8 + 3) / 7
     } else {
-        smallest_multiple
+        width
     };
 
-    canvas.set_width(NES_WIDTH * smallest_multiple);
-    canvas.set_height(NES_HEIGHT * smallest_multiple);
+    (width, screen_height * scale)
 }
 
-fn get_canvas() -> HtmlCanvasElement {
+fn get_canvas() -> Result<HtmlCanvasElement, JsValue> {
     let document = web_sys::window().unwrap().document().unwrap();
-    let canvas = document.get_element_by_id("rustendo-canvas").unwrap();
+    let canvas_id = CANVAS_ID.with(|id| id.borrow().clone());
+    let canvas = document.get_element_by_id(&canvas_id).ok_or_else(|| {
+        JsValue::from_str(&format!("no element with id \"{}\" was found", canvas_id))
+    })?;
     canvas
         .dyn_into::<HtmlCanvasElement>()
-        .map_err(|_| ())
-        .unwrap()
+        .map_err(|_| JsValue::from_str(&format!("element \"{}\" is not a canvas", canvas_id)))
 }
 
 fn get_canvas_rendering_context(canvas: &HtmlCanvasElement) -> CanvasRenderingContext2d {
@@ -97,6 +421,35 @@ fn get_canvas_rendering_context(canvas: &HtmlCanvasElement) -> CanvasRenderingCo
         .unwrap()
 }
 
+enum Renderer {
+    /// Puts each frame on an offscreen canvas, then scales it onto the page.
+    Canvas2d {
+        context: CanvasRenderingContext2d,
+        renderer: HtmlCanvasElement,
+        renderer_context: CanvasRenderingContext2d,
+    },
+    #[cfg(feature = "webgl")]
+    WebGl(webgl::WebGlRenderer),
+}
+
+fn create_renderer(canvas: &HtmlCanvasElement) -> Renderer {
+    #[cfg(feature = "webgl")]
+    match webgl::WebGlRenderer::new(canvas) {
+        Ok(renderer) => return Renderer::WebGl(renderer),
+        Err(error) => {
+            web_sys::console::warn_2(&"WebGL unavailable, using a 2D canvas:".into(), &error)
+        }
+    }
+
+    let context = get_canvas_rendering_context(canvas);
+    let (renderer, renderer_context) = create_canvas_and_rendering_context();
+    Renderer::Canvas2d {
+        context,
+        renderer,
+        renderer_context,
+    }
+}
+
 fn create_canvas_and_rendering_context() -> (HtmlCanvasElement, CanvasRenderingContext2d) {
     let canvas = window()
         .document()
@@ -115,129 +468,578 @@ fn create_canvas_and_rendering_context() -> (HtmlCanvasElement, CanvasRenderingC
     (canvas, context)
 }
 
+/// Loads a ROM and starts running it, replacing any game already running.
+/// Fails if the canvas can't be found or the ROM is invalid, leaving any
+/// running game untouched.
+///
+/// The lifecycle of the module is: `init_canvas` once the canvas is in the
+/// DOM, then `render` for each game, and `shutdown` when the emulator is no
+/// longer needed, e.g. when a single page app navigates away from it.
+#[wasm_bindgen]
+pub fn render(byte_array: Uint8Array) -> Result<(), JsValue> {
+    start_rom(byte_array.to_vec())
+}
+
+/// Like `render`, but also takes a ZIP archive, in which case the first
+/// `.nes` file in it is run. Anything else is loaded as a plain ROM.
+#[wasm_bindgen]
+pub fn render_zip(byte_array: Uint8Array) -> Result<(), JsValue> {
+    start_rom(unzip_rom(byte_array.to_vec())?)
+}
+
+/// Loads an NSF music rip and starts playing its first track, replacing
+/// any game or tune already loaded. Fails if the file isn't a valid NSF.
+#[wasm_bindgen]
+pub fn load_nsf(byte_array: Uint8Array) -> Result<(), JsValue> {
+    let error = |error| JsValue::from_str(&format!("could not load NSF: {}", error));
+
+    let mut nsf = Nsf::new(&byte_array.to_vec()).map_err(error)?;
+    nsf.play_track(nsf.starting_song()).map_err(error)?;
+
+    // Don't leave the previous game's or tune's loop and listeners running.
+    shutdown();
+
+    NSF.with(|loaded| *loaded.borrow_mut() = Some(nsf));
+    let (animation_frame, animation) = setup_nsf_animation();
+
+    SESSION.with(|session| {
+        *session.borrow_mut() = Some(Session {
+            animation_frame,
+            animation,
+            keydown_handler: None,
+            keyup_handler: None,
+        })
+    });
+    Ok(())
+}
+
+/// Reads the memory of the loaded game or tune without side effects, for
+/// debuggers. Returns `undefined` if nothing is loaded.
 #[wasm_bindgen]
-pub fn render(byte_array: Uint8Array) {
-    let nes = load_cartridge(byte_array);
+pub fn peek(address: u16) -> Option<u8> {
+    let nes = NES.with(|nes| nes.borrow().as_ref().map(|nes| nes.borrow().peek(address)));
+    nes.or_else(|| NSF.with(|nsf| nsf.borrow().as_ref().map(|nsf| nsf.peek(address))))
+}
+
+fn unzip_rom(data: Vec<u8>) -> Result<Vec<u8>, JsValue> {
+    if zip::is_zip(&data) {
+        zip::extract_rom(&data)
+            .map_err(|error| JsValue::from_str(&format!("could not unzip ROM: {}", error)))
+    } else {
+        Ok(data)
+    }
+}
+
+fn start_rom(rom: Vec<u8>) -> Result<(), JsValue> {
+    // Look the canvas up first so nothing is left half set up on failure.
+    let canvas = get_canvas()?;
+
+    let nes = load_cartridge(rom.clone())?;
+
+    // Don't leave the previous game's loop and listeners running.
+    shutdown();
+
+    ROM.with(|loaded| *loaded.borrow_mut() = Some(rom));
     let nes = Rc::new(RefCell::new(nes));
+    NES.with(|loaded| *loaded.borrow_mut() = Some(Rc::clone(&nes)));
+
+    let keydown_handler = setup_keydown_handler(&nes);
+    let keyup_handler = setup_keyup_handler(&nes);
+    let (animation_frame, animation) = setup_animation(&nes, canvas);
+
+    SESSION.with(|session| {
+        *session.borrow_mut() = Some(Session {
+            animation_frame,
+            animation,
+            keydown_handler: Some(keydown_handler),
+            keyup_handler: Some(keyup_handler),
+        })
+    });
+    Ok(())
+}
+
+/// Stops the animation loop, removes the event listeners and drops the
+/// loaded game. Call `render` to start again.
+#[wasm_bindgen]
+pub fn shutdown() {
+    if let Some(session) = SESSION.with(|session| session.borrow_mut().take()) {
+        window()
+            .cancel_animation_frame(session.animation_frame.get())
+            .unwrap();
+        // The animation closure holds a reference to itself so it can
+        // request the next frame, so it has to be dropped explicitly.
+        session.animation.borrow_mut().take();
+        if let Some(keydown_handler) = &session.keydown_handler {
+            remove_event_listener("keydown", keydown_handler);
+        }
+        if let Some(keyup_handler) = &session.keyup_handler {
+            remove_event_listener("keyup", keyup_handler);
+        }
+    }
+
+    NES.with(|nes| nes.borrow_mut().take());
+    ROM.with(|rom| rom.borrow_mut().take());
+    NSF.with(|nsf| nsf.borrow_mut().take());
+    RUNNING.with(|running| running.set(true));
+    STEP_FRAME.with(|step_frame| step_frame.set(false));
+    REWINDING.with(|rewinding| rewinding.set(false));
+}
+
+/// Ejects the loaded game, stopping the animation loop and freeing the ROM.
+/// The canvas stays set up, so `render` can load another game afterwards.
+#[wasm_bindgen]
+pub fn unload() {
+    // Eject the cartridge first, so its ROM is freed even if something
+    // still holds on to the `Nes`.
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            nes.borrow_mut().unload_cartridge();
+        }
+    });
+    shutdown();
+}
+
+/// Soft resets the loaded game, like pressing the reset button. RAM is kept.
+#[wasm_bindgen]
+pub fn reset() {
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            nes.borrow_mut().reset();
+        }
+    });
+}
+
+/// Turns the console off and on again, rebuilding it from the loaded ROM
+/// so that RAM is cleared.
+#[wasm_bindgen]
+pub fn power_cycle() {
+    let rom = match ROM.with(|rom| rom.borrow().clone()) {
+        Some(rom) => rom,
+        None => return,
+    };
+
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            // The ROM already loaded once, so it can't fail now.
+            let mut new_nes = load_cartridge(rom).expect("loaded ROM became invalid");
+            new_nes.reset();
+            *nes.borrow_mut() = new_nes;
+        }
+    });
+}
+
+/// Returns the current frame as a 256x240 PNG, e.g. to wrap in a `Blob` and
+/// download. Fails if no ROM is loaded.
+#[wasm_bindgen]
+pub fn capture_screenshot() -> Result<Vec<u8>, JsValue> {
+    NES.with(|nes| match nes.borrow().as_ref() {
+        Some(nes) => Ok(nes.borrow().screenshot_png()),
+        None => Err(JsValue::from_str("no ROM is loaded")),
+    })
+}
+
+/// Describes the loaded cartridge as a JSON object, for showing ROM info.
+/// Returns `undefined` if no ROM is loaded.
+#[wasm_bindgen]
+pub fn cartridge_info() -> Option<String> {
+    NES.with(|nes| {
+        nes.borrow()
+            .as_ref()
+            .and_then(|nes| nes.borrow().cartridge_info())
+            .map(|info| cartridge_info_json(&info))
+    })
+}
+
+fn cartridge_info_json(info: &CartridgeInfo) -> String {
+    format!(
+        concat!(
+            r#"{{"format":"{:?}","mapper":{},"submapper":{},"#,
+            r#""prg_rom_size":{},"chr_rom_size":{},"prg_ram_size":{},"chr_ram_size":{},"#,
+            r#""mirroring":"{:?}","battery":{},"console_type":"{:?}","timing_mode":"{:?}"}}"#
+        ),
+        info.format,
+        info.mapper,
+        info.submapper,
+        info.prg_rom_size,
+        info.chr_rom_size,
+        info.prg_ram_size,
+        info.chr_ram_size,
+        info.mirroring,
+        info.has_battery,
+        info.console_type,
+        info.timing_mode
+    )
+}
+
+/// Replaces the colors the NES outputs with those from a 192 byte `.pal`
+/// file, such as the ones FCEUX uses. The palette is kept for games loaded
+/// afterwards.
+#[wasm_bindgen]
+pub fn set_palette_from_bytes(bytes: &[u8]) -> Result<(), JsValue> {
+    let palette = palette::parse_pal(bytes)
+        .map_err(|error| JsValue::from_str(&format!("could not load palette: {}", error)))?;
+
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            nes.borrow_mut().set_palette(&palette).unwrap();
+        }
+    });
+    PALETTE.with(|loaded| *loaded.borrow_mut() = Some(palette));
+    Ok(())
+}
+
+fn is_loaded() -> bool {
+    NES.with(|nes| nes.borrow().is_some())
+}
+
+/// Stops the emulator from advancing. Does nothing if no ROM is loaded.
+#[wasm_bindgen]
+pub fn pause() {
+    if is_loaded() {
+        RUNNING.with(|running| running.set(false));
+    }
+}
+
+#[wasm_bindgen]
+pub fn resume() {
+    RUNNING.with(|running| running.set(true));
+}
+
+/// Advances exactly one frame on the next animation frame while paused.
+#[wasm_bindgen]
+pub fn step_frame() {
+    if is_loaded() && !RUNNING.with(|running| running.get()) {
+        STEP_FRAME.with(|step_frame| step_frame.set(true));
+    }
+}
+
+/// Runs the emulator at `multiplier` times its normal speed, e.g. 2.0 to
+/// fast-forward or 0.5 for slow motion. The multiplier is clamped to between
+/// 0.25 and 8.
+#[wasm_bindgen]
+pub fn set_speed(multiplier: f32) {
+    SPEED.with(|speed| speed.set(clamp_speed(multiplier)));
+}
+
+fn clamp_speed(multiplier: f32) -> f64 {
+    if multiplier.is_nan() {
+        return 1.0;
+    }
+    f64::from(multiplier).clamp(MIN_SPEED, MAX_SPEED)
+}
+
+/// Keeps the last `max_seconds` seconds of play so that holding Backspace
+/// runs the game backwards. Zero, the default, turns rewind off. Each second
+/// takes around 600KB, more for cartridges with extra RAM.
+#[wasm_bindgen]
+pub fn enable_rewind(max_seconds: u32) {
+    REWIND_SECONDS.with(|rewind_seconds| rewind_seconds.set(max_seconds));
+    NES.with(|nes| {
+        if let Some(nes) = nes.borrow().as_ref() {
+            nes.borrow_mut().enable_rewind(max_seconds);
+        }
+    });
+}
+
+fn should_advance(running: &Cell<bool>, step_frame: &Cell<bool>) -> bool {
+    // Always consume a pending step so it can't carry over to after a resume.
+    let step = step_frame.replace(false);
+    running.get() || step
+}
 
-    setup_keydown_handler(&nes);
-    setup_keyup_handler(&nes);
-    setup_animation(&nes);
+/// Calls `callback` with the measured emulation speed, in frames per second,
+/// about once a second while a game is running.
+#[wasm_bindgen]
+pub fn set_fps_callback(callback: js_sys::Function) {
+    FPS_CALLBACK.with(|fps_callback| *fps_callback.borrow_mut() = Some(callback));
+}
+
+/// Decides how many frames to emulate on each animation frame so that the
+/// game runs at NES speed whatever the display's refresh rate.
+struct FramePacer {
+    last_time: Option<f64>,
+    unemulated_time: f64,
+    speed: f64,
+    fps_start: Option<f64>,
+    fps_frames: u32,
 }
 
-fn load_cartridge(byte_array: Uint8Array) -> Nes {
-    let vec = byte_array.to_vec();
-    let cartridge = Cartridge::new(vec);
+impl FramePacer {
+    fn new() -> Self {
+        FramePacer {
+            last_time: None,
+            unemulated_time: 0.0,
+            speed: 1.0,
+            fps_start: None,
+            fps_frames: 0,
+        }
+    }
+
+    /// Makes each millisecond that passes worth `speed` milliseconds of
+    /// emulation.
+    fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+    }
+
+    /// The number of frames to run at time `now`, in milliseconds.
+    fn frames_due(&mut self, now: f64) -> u32 {
+        if let Some(last_time) = self.last_time {
+            self.unemulated_time += (now - last_time).clamp(0.0, MAX_ELAPSED_MS) * self.speed;
+        }
+        self.last_time = Some(now);
+
+        let frames = (self.unemulated_time / FRAME_DURATION_MS) as u32;
+        self.unemulated_time -= f64::from(frames) * FRAME_DURATION_MS;
+        frames
+    }
+
+    /// Forgets the time spent paused, so it isn't caught up on afterwards.
+    fn pause(&mut self) {
+        self.last_time = None;
+        self.unemulated_time = 0.0;
+        self.fps_start = None;
+        self.fps_frames = 0;
+    }
+
+    /// Counts `frames` run at time `now`, returning the frames per second
+    /// once a second has passed since the last measurement.
+    fn measure_fps(&mut self, now: f64, frames: u32) -> Option<f64> {
+        let start = *self.fps_start.get_or_insert(now);
+        self.fps_frames += frames;
+
+        let elapsed = now - start;
+        if elapsed < 1000.0 {
+            return None;
+        }
+
+        let fps = f64::from(self.fps_frames) * 1000.0 / elapsed;
+        self.fps_start = Some(now);
+        self.fps_frames = 0;
+        Some(fps)
+    }
+}
+
+fn load_cartridge(rom: Vec<u8>) -> Result<Nes, JsValue> {
+    let cartridge = Cartridge::new(rom)
+        .map_err(|error| JsValue::from_str(&format!("could not load ROM: {}", error)))?;
     let mut nes = Nes::new();
     nes.load_cartridge(cartridge);
-    nes
+
+    if let Some(palette) = PALETTE.with(|palette| palette.borrow().clone()) {
+        // Only valid palettes are stored.
+        nes.set_palette(&palette).unwrap();
+    }
+
+    let (top, bottom, left, right) = OVERSCAN.with(|overscan| overscan.get());
+    nes.set_overscan(top, bottom, left, right);
+
+    let rewind_seconds = REWIND_SECONDS.with(|rewind_seconds| rewind_seconds.get());
+    if rewind_seconds > 0 {
+        nes.enable_rewind(rewind_seconds);
+    }
+
+    Ok(nes)
 }
 
-fn setup_animation(nes: &Rc<RefCell<Nes>>) {
-    let canvas = get_canvas();
-    let context = get_canvas_rendering_context(&canvas);
-    let (renderer, renderer_context) = create_canvas_and_rendering_context();
+type Animation = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+fn setup_animation(
+    nes: &Rc<RefCell<Nes>>,
+    canvas: HtmlCanvasElement,
+) -> (Rc<Cell<i32>>, Animation) {
+    let renderer = create_renderer(&canvas);
     let moved_nes = Rc::clone(nes);
     let nes = Rc::clone(&moved_nes);
+    let running = RUNNING.with(Rc::clone);
+    let step_frame = STEP_FRAME.with(Rc::clone);
+    let speed = SPEED.with(Rc::clone);
+    let rewinding = REWINDING.with(Rc::clone);
 
-    let mut screen = [0; (NES_WIDTH * NES_HEIGHT * 4) as usize];
+    let moved_animation_frame = Rc::new(Cell::new(0));
+    let animation_frame = Rc::clone(&moved_animation_frame);
 
-    let moved_closure = Rc::new(RefCell::new(None));
+    let moved_closure: Animation = Rc::new(RefCell::new(None));
     let closure = Rc::clone(&moved_closure);
 
-    *closure.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        request_animation_frame(moved_closure.borrow().as_ref().unwrap());
+    let fps_callback = FPS_CALLBACK.with(Rc::clone);
+    let performance = window().performance().expect("no `performance` exists");
+    let mut frame_pacer = FramePacer::new();
+    let navigator = window().navigator();
+    let gamepad_mapping = GAMEPAD_MAPPING.with(Rc::clone);
+    let mut gamepad_buttons = 0;
 
-        while !moved_nes.borrow_mut().clock() {}
+    *closure.borrow_mut() = Some(Closure::wrap(Box::new(move || {
+        moved_animation_frame.set(request_animation_frame(
+            moved_closure.borrow().as_ref().unwrap(),
+        ));
 
-        draw(
-            &mut screen,
-            &context,
-            &canvas,
-            &renderer_context,
-            &renderer,
-            &moved_nes.borrow(),
+        let new_gamepad_buttons = poll_gamepads(&navigator, &gamepad_mapping.borrow());
+        apply_gamepad_state(
+            moved_nes.borrow_mut().controller(0),
+            gamepad_buttons,
+            new_gamepad_buttons,
         );
+        gamepad_buttons = new_gamepad_buttons;
+
+        if !should_advance(&running, &step_frame) {
+            frame_pacer.pause();
+            return;
+        }
+
+        let now = performance.now();
+        let frames = frames_to_run(&mut frame_pacer, &running, &speed, now);
+
+        for _ in 0..frames {
+            if rewinding.get() {
+                moved_nes.borrow_mut().rewind_frame();
+            } else {
+                moved_nes.borrow_mut().run_frame();
+                moved_nes.borrow_mut().controller(0).tick_turbo();
+            }
+        }
+
+        if let Some(fps) = frame_pacer.measure_fps(now, frames) {
+            if let Some(callback) = fps_callback.borrow().as_ref() {
+                if let Err(error) = callback.call1(&JsValue::NULL, &JsValue::from_f64(fps)) {
+                    web_sys::console::error_2(&"FPS callback failed:".into(), &error);
+                }
+            }
+        }
+
+        if frames > 0 {
+            draw(&renderer, &canvas, &moved_nes.borrow());
+        }
     }) as Box<dyn FnMut()>));
 
     nes.borrow_mut().reset();
-    request_animation_frame(closure.borrow().as_ref().unwrap());
+    animation_frame.set(request_animation_frame(closure.borrow().as_ref().unwrap()));
+    (animation_frame, closure)
+}
+
+/// Like `setup_animation`, but clocks the loaded NSF instead of an NES.
+/// Nothing is drawn.
+fn setup_nsf_animation() -> (Rc<Cell<i32>>, Animation) {
+    let running = RUNNING.with(Rc::clone);
+    let step_frame = STEP_FRAME.with(Rc::clone);
+    let speed = SPEED.with(Rc::clone);
+
+    let moved_animation_frame = Rc::new(Cell::new(0));
+    let animation_frame = Rc::clone(&moved_animation_frame);
+
+    let moved_closure: Animation = Rc::new(RefCell::new(None));
+    let closure = Rc::clone(&moved_closure);
+
+    let performance = window().performance().expect("no `performance` exists");
+    let mut frame_pacer = FramePacer::new();
+
+    *closure.borrow_mut() = Some(Closure::wrap(Box::new(move || {
+        moved_animation_frame.set(request_animation_frame(
+            moved_closure.borrow().as_ref().unwrap(),
+        ));
+
+        if !should_advance(&running, &step_frame) {
+            frame_pacer.pause();
+            return;
+        }
+
+        let frames = frames_to_run(&mut frame_pacer, &running, &speed, performance.now());
+        NSF.with(|nsf| {
+            if let Some(nsf) = nsf.borrow_mut().as_mut() {
+                for _ in 0..frames * NSF_CYCLES_PER_FRAME {
+                    nsf.clock();
+                }
+            }
+        });
+    }) as Box<dyn FnMut()>));
+
+    animation_frame.set(request_animation_frame(closure.borrow().as_ref().unwrap()));
+    (animation_frame, closure)
+}
+
+/// Stepping runs exactly one frame, otherwise run as many frames as the
+/// NES would have in the time since the last animation frame.
+fn frames_to_run(
+    frame_pacer: &mut FramePacer,
+    running: &Cell<bool>,
+    speed: &Cell<f64>,
+    now: f64,
+) -> u32 {
+    if running.get() {
+        frame_pacer.set_speed(speed.get());
+        frame_pacer.frames_due(now)
+    } else {
+        frame_pacer.pause();
+        1
+    }
 }
 
-fn setup_keydown_handler(nes: &Rc<RefCell<Nes>>) {
+fn setup_keydown_handler(nes: &Rc<RefCell<Nes>>) -> Closure<dyn FnMut(KeyboardEvent)> {
     let nes = Rc::clone(nes);
+    let key_mapping = KEY_MAPPING.with(Rc::clone);
+    let rewinding = REWINDING.with(Rc::clone);
 
     let keydown_handler = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
-        let mut nes = nes.borrow_mut();
-        let controller = nes.controller();
-
-        match event.key().as_str() {
-            "a" | "A" => controller.press_a(),
-            "s" | "S" => controller.press_b(),
-            "ArrowLeft" => controller.press_left(),
-            "ArrowRight" => controller.press_right(),
-            "ArrowUp" => controller.press_up(),
-            "ArrowDown" => controller.press_down(),
-            "x" | "X" => controller.press_start(),
-            "z" | "Z" => controller.press_select(),
-            _ => return,
-        };
+        if event.key() == REWIND_KEY {
+            rewinding.set(true);
+        }
+
+        if let Some(button) = lookup_key(&key_mapping.borrow(), &event.key()) {
+            nes.borrow_mut().controller(0).press(button);
+        }
     }) as Box<dyn FnMut(KeyboardEvent)>);
 
     add_event_listener::<KeyboardEvent>("keydown", &keydown_handler);
-    keydown_handler.forget();
+    keydown_handler
 }
 
-fn setup_keyup_handler(nes: &Rc<RefCell<Nes>>) {
+fn setup_keyup_handler(nes: &Rc<RefCell<Nes>>) -> Closure<dyn FnMut(KeyboardEvent)> {
     let nes = Rc::clone(nes);
+    let key_mapping = KEY_MAPPING.with(Rc::clone);
+    let rewinding = REWINDING.with(Rc::clone);
 
     let keyup_handler = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
-        let mut nes = nes.borrow_mut();
-        let controller = nes.controller();
-
-        match event.key().as_str() {
-            "a" | "A" => controller.lift_a(),
-            "s" | "S" => controller.lift_b(),
-            "ArrowLeft" => controller.lift_left(),
-            "ArrowRight" => controller.lift_right(),
-            "ArrowUp" => controller.lift_up(),
-            "ArrowDown" => controller.lift_down(),
-            "x" | "X" => controller.lift_start(),
-            "z" | "Z" => controller.lift_select(),
-            _ => return,
-        };
+        if event.key() == REWIND_KEY {
+            rewinding.set(false);
+        }
+
+        if let Some(button) = lookup_key(&key_mapping.borrow(), &event.key()) {
+            nes.borrow_mut().controller(0).lift(button);
+        }
     }) as Box<dyn FnMut(KeyboardEvent)>);
 
     add_event_listener::<KeyboardEvent>("keyup", &keyup_handler);
-    keyup_handler.forget();
-}
-
-fn draw(
-    data: &mut [u8],
-    context: &CanvasRenderingContext2d,
-    canvas: &HtmlCanvasElement,
-    renderer_context: &CanvasRenderingContext2d,
-    renderer: &HtmlCanvasElement,
-    nes: &Nes,
-) {
-    let screen = nes.get_screen();
-
-    for y in 0..NES_HEIGHT {
-        for x in 0..NES_WIDTH {
-            set_color_at_coord(data, x, y, screen[y as usize][x as usize])
-        }
-    }
+    keyup_handler
+}
+
+fn draw(renderer: &Renderer, canvas: &HtmlCanvasElement, nes: &Nes) {
+    let data = nes.get_visible_framebuffer_rgba();
+    let (width, height) = nes.overscan().visible_size();
 
-    let image_data = ImageData::new_with_u8_clamped_array(Clamped(data), NES_WIDTH)
+    let (context, renderer, renderer_context) = match renderer {
+        Renderer::Canvas2d {
+            context,
+            renderer,
+            renderer_context,
+        } => (context, renderer, renderer_context),
+        #[cfg(feature = "webgl")]
+        Renderer::WebGl(renderer) => return renderer.draw(canvas, &data, width, height),
+    };
+
+    let image_data = ImageData::new_with_u8_clamped_array(Clamped(&data), width)
         .expect("could not create image data");
 
     renderer_context
         .put_image_data(&image_data, 0.0, 0.0)
         .expect("could not put image data");
 
+    // The offscreen canvas is full size, so only copy the cropped corner.
     context
-        .draw_image_with_html_canvas_element_and_dw_and_dh(
-            &renderer,
+        .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+            renderer,
+            0.0,
+            0.0,
+            width.into(),
+            height.into(),
             0.0,
             0.0,
             canvas.width().into(),
@@ -246,14 +1048,226 @@ fn draw(
         .expect("could not draw canvas onto context");
 }
 
-fn set_color_at_coord(data: &mut [u8], x: u32, y: u32, color: (u8, u8, u8)) {
-    let x = x as usize;
-    let y = y as usize;
-    let width = NES_WIDTH as usize;
-    let red_index = y * (width * 4) + x * 4;
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_gamepad_state, canvas_size, cartridge_info_json, clamp_speed,
+        default_gamepad_mapping, default_key_mapping, fit_scale, gamepad_state, lookup_key,
+        map_key, parse_button, should_advance, FramePacer,
+    };
+    use rustendo_lib::cartridge::Cartridge;
+    use rustendo_lib::controller::Button;
+    use rustendo_lib::nes::{Nes, Overscan};
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn frame_step() {
+        let running = Cell::new(true);
+        let step_frame = Cell::new(false);
+        assert!(
+            should_advance(&running, &step_frame),
+            "advances while running"
+        );
+
+        running.set(false);
+        assert!(!should_advance(&running, &step_frame), "paused");
+
+        step_frame.set(true);
+        assert!(should_advance(&running, &step_frame), "steps one frame");
+        assert!(!should_advance(&running, &step_frame), "only one frame");
+    }
 
-    data[red_index] = color.0;
-    data[red_index + 1] = color.1;
-    data[red_index + 2] = color.2;
-    data[red_index + 3] = 0xFF;
+    fn run_display(refresh_rate: f64, seconds: u32) -> u32 {
+        let mut frame_pacer = FramePacer::new();
+        let ticks = refresh_rate as u32 * seconds;
+        (0..=ticks)
+            .map(|tick| frame_pacer.frames_due(f64::from(tick) * 1000.0 / refresh_rate))
+            .sum()
+    }
+
+    #[test]
+    fn frame_pacing() {
+        assert_eq!(run_display(60.0, 10), 600, "60 Hz");
+        assert_eq!(run_display(144.0, 10), 600, "144 Hz");
+        assert_eq!(run_display(120.0, 10), 600, "120 Hz");
+        assert_eq!(run_display(30.0, 10), 600, "30 Hz");
+
+        let mut frame_pacer = FramePacer::new();
+        assert_eq!(frame_pacer.frames_due(0.0), 0);
+        assert_eq!(
+            frame_pacer.frames_due(60_000.0),
+            15,
+            "long gaps aren't caught up on"
+        );
+
+        frame_pacer.pause();
+        assert_eq!(
+            frame_pacer.frames_due(120_000.0),
+            0,
+            "time paused is skipped"
+        );
+    }
+
+    #[test]
+    fn run_speed() {
+        let mut nes = Nes::new();
+        let mut frame_pacer = FramePacer::new();
+        let mut frames_at_speed = |speed: f64| {
+            frame_pacer.set_speed(speed);
+            let start = nes.frame_count();
+            // A second of a 60 Hz display.
+            for tick in 0..60 {
+                let frames = frame_pacer.frames_due(f64::from(tick) * 1000.0 / 60.0);
+                nes.run_frames(frames);
+            }
+            frame_pacer.pause();
+            nes.frame_count() - start
+        };
+
+        assert_eq!(frames_at_speed(1.0), 59, "normal speed");
+        assert_eq!(frames_at_speed(2.0), 118, "fast-forward");
+        assert_eq!(frames_at_speed(0.5), 29, "slow motion");
+
+        assert_eq!(clamp_speed(100.0), 8.0);
+        assert_eq!(clamp_speed(0.0), 0.25);
+        assert_eq!(clamp_speed(f32::NAN), 1.0);
+    }
+
+    #[test]
+    fn fps_measurement() {
+        let mut frame_pacer = FramePacer::new();
+        let mut measurements = vec![];
+
+        for tick in 0..=288 {
+            let now = f64::from(tick) * 1000.0 / 144.0;
+            let frames = frame_pacer.frames_due(now);
+            measurements.extend(frame_pacer.measure_fps(now, frames));
+        }
+
+        assert_eq!(measurements.len(), 2, "measured once a second");
+        for fps in measurements {
+            assert!((fps - 60.0988).abs() < 1.0, "{} FPS", fps);
+        }
+    }
+
+    fn held_buttons(nes: &mut Nes) -> Vec<u8> {
+        let controller = nes.controller(0);
+        controller.latch();
+        (0..8).map(|_| controller.read_button()).collect()
+    }
+
+    #[test]
+    fn gamepad_buttons() {
+        let gamepad_mapping = default_gamepad_mapping();
+        let mut pressed = vec![false; 17];
+        pressed[0] = true;
+        pressed[9] = true;
+        assert_eq!(
+            gamepad_state(&gamepad_mapping, &pressed, &[0.0, 0.0]),
+            Button::A as u8 | Button::Start as u8
+        );
+        assert_eq!(
+            gamepad_state(&gamepad_mapping, &[], &[-1.0, 0.7]),
+            Button::Left as u8 | Button::Down as u8,
+            "left stick works as the D-pad"
+        );
+        assert_eq!(
+            gamepad_state(&gamepad_mapping, &[], &[0.2, -0.3]),
+            0,
+            "small stick movements ignored"
+        );
+    }
+
+    #[test]
+    fn gamepad_and_keyboard_merge() {
+        let mut nes = Nes::new();
+
+        // Held on the keyboard, then the idle gamepad is polled.
+        nes.controller(0).press(Button::A);
+        apply_gamepad_state(nes.controller(0), 0, 0);
+        assert_eq!(held_buttons(&mut nes), vec![1, 0, 0, 0, 0, 0, 0, 0]);
+
+        // Start pressed on the gamepad and A released on the keyboard.
+        apply_gamepad_state(nes.controller(0), 0, Button::Start as u8);
+        nes.controller(0).lift(Button::A);
+        assert_eq!(held_buttons(&mut nes), vec![0, 0, 0, 1, 0, 0, 0, 0]);
+
+        // The gamepad is disconnected while Start is held.
+        apply_gamepad_state(nes.controller(0), Button::Start as u8, 0);
+        assert_eq!(held_buttons(&mut nes), vec![0; 8]);
+    }
+
+    #[test]
+    fn canvas_scale() {
+        let screen = (256, 240);
+        assert_eq!(canvas_size(1, false, screen), (256, 240));
+        assert_eq!(canvas_size(3, false, screen), (768, 720));
+        assert_eq!(canvas_size(1, true, screen), (293, 240), "8:7 wide");
+        assert_eq!(canvas_size(3, true, screen), (878, 720), "8:7 wide");
+
+        assert_eq!(fit_scale((1920, 1080), false, screen), 4);
+        assert_eq!(
+            fit_scale((1000, 1080), false, screen),
+            3,
+            "limited by width"
+        );
+        assert_eq!(fit_scale((1000, 1080), true, screen), 3);
+        assert_eq!(fit_scale((1100, 1080), false, screen), 4);
+        assert_eq!(
+            fit_scale((1100, 1080), true, screen),
+            3,
+            "stretched to 1171 wide"
+        );
+        assert_eq!(
+            fit_scale((100, 100), false, screen),
+            1,
+            "never smaller than 1x"
+        );
+
+        // 8 lines cropped from the top and bottom.
+        let cropped = Overscan::new(8, 8, 0, 0).visible_size();
+        assert_eq!(canvas_size(3, false, cropped), (768, 672));
+        assert_eq!(fit_scale((1920, 900), false, screen), 3);
+        assert_eq!(fit_scale((1920, 900), false, cropped), 4, "cropped fits 4x");
+    }
+
+    #[test]
+    fn remapped_key_presses_button() {
+        let mut key_mapping = default_key_mapping();
+        let button = parse_button("start").unwrap();
+        map_key(&mut key_mapping, button, "Enter");
+
+        assert_eq!(lookup_key(&key_mapping, "Enter"), Some(Button::Start));
+        assert_eq!(lookup_key(&key_mapping, "x"), None, "old key unbound");
+        assert_eq!(
+            lookup_key(&key_mapping, "A"),
+            Some(Button::A),
+            "case insensitive"
+        );
+
+        let mut nes = Nes::new();
+        let controller = nes.controller(0);
+        controller.press(lookup_key(&key_mapping, "Enter").unwrap());
+        controller.latch();
+
+        // Start is the fourth button read out.
+        let buttons: Vec<u8> = (0..8).map(|_| controller.read_button()).collect();
+        assert_eq!(buttons, vec![0, 0, 0, 1, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn cartridge_info() {
+        let rom = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("../nestest.nes")).unwrap();
+        let info = Cartridge::new(rom).unwrap().info();
+        assert_eq!(
+            cartridge_info_json(&info),
+            concat!(
+                r#"{"format":"INes","mapper":0,"submapper":0,"#,
+                r#""prg_rom_size":16384,"chr_rom_size":8192,"prg_ram_size":8192,"chr_ram_size":0,"#,
+                r#""mirroring":"Horizontal","battery":false,"console_type":"NES","timing_mode":"NtscNes"}"#
+            )
+        );
+    }
 }
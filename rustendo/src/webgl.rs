@@ -0,0 +1,149 @@
+use js_sys::Float32Array;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    HtmlCanvasElement, WebGlProgram, WebGlRenderingContext as Gl, WebGlShader, WebGlTexture,
+};
+
+const VERTEX_SHADER: &str = r#"
+    attribute vec2 position;
+    varying vec2 texture_coordinate;
+
+    void main() {
+        // Texture rows run top to bottom, clip space runs bottom to top.
+        texture_coordinate = vec2(position.x + 1.0, 1.0 - position.y) / 2.0;
+        gl_Position = vec4(position, 0.0, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+    precision mediump float;
+    varying vec2 texture_coordinate;
+    uniform sampler2D frame;
+
+    void main() {
+        gl_FragColor = texture2D(frame, texture_coordinate);
+    }
+"#;
+
+/// Draws frames by uploading the framebuffer to a texture and stretching it
+/// over a full-canvas quad, so scaling happens on the GPU.
+pub struct WebGlRenderer {
+    context: Gl,
+    texture: WebGlTexture,
+}
+
+impl WebGlRenderer {
+    /// Fails if the canvas doesn't support WebGL or the shaders don't build.
+    pub fn new(canvas: &HtmlCanvasElement) -> Result<Self, JsValue> {
+        let context = canvas
+            .get_context("webgl")?
+            .ok_or_else(|| JsValue::from_str("WebGL is not supported"))?
+            .dyn_into::<Gl>()?;
+
+        let vertex_shader = compile_shader(&context, Gl::VERTEX_SHADER, VERTEX_SHADER)?;
+        let fragment_shader = compile_shader(&context, Gl::FRAGMENT_SHADER, FRAGMENT_SHADER)?;
+        let program = link_program(&context, &vertex_shader, &fragment_shader)?;
+        context.use_program(Some(&program));
+
+        // Two triangles covering the whole canvas.
+        let vertices: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+        let buffer = context
+            .create_buffer()
+            .ok_or_else(|| JsValue::from_str("could not create buffer"))?;
+        context.bind_buffer(Gl::ARRAY_BUFFER, Some(&buffer));
+        context.buffer_data_with_array_buffer_view(
+            Gl::ARRAY_BUFFER,
+            &Float32Array::from(&vertices[..]),
+            Gl::STATIC_DRAW,
+        );
+
+        let position = context.get_attrib_location(&program, "position") as u32;
+        context.vertex_attrib_pointer_with_i32(position, 2, Gl::FLOAT, false, 0, 0);
+        context.enable_vertex_attrib_array(position);
+
+        let texture = context
+            .create_texture()
+            .ok_or_else(|| JsValue::from_str("could not create texture"))?;
+        context.bind_texture(Gl::TEXTURE_2D, Some(&texture));
+        // The screen isn't a power of two, so WebGL 1 needs clamping and
+        // no mipmaps. Nearest filtering keeps the pixels sharp.
+        context.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_WRAP_S, Gl::CLAMP_TO_EDGE as i32);
+        context.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_WRAP_T, Gl::CLAMP_TO_EDGE as i32);
+        context.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_MIN_FILTER, Gl::NEAREST as i32);
+        context.tex_parameteri(Gl::TEXTURE_2D, Gl::TEXTURE_MAG_FILTER, Gl::NEAREST as i32);
+
+        Ok(WebGlRenderer { context, texture })
+    }
+
+    /// Draws a `width` by `height` RGBA framebuffer scaled to the canvas.
+    pub fn draw(&self, canvas: &HtmlCanvasElement, framebuffer: &[u8], width: u32, height: u32) {
+        self.context
+            .bind_texture(Gl::TEXTURE_2D, Some(&self.texture));
+        self.context
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                Gl::TEXTURE_2D,
+                0,
+                Gl::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                Gl::RGBA,
+                Gl::UNSIGNED_BYTE,
+                Some(framebuffer),
+            )
+            .expect("could not upload framebuffer");
+
+        self.context
+            .viewport(0, 0, canvas.width() as i32, canvas.height() as i32);
+        self.context.draw_arrays(Gl::TRIANGLE_STRIP, 0, 4);
+    }
+}
+
+fn compile_shader(context: &Gl, shader_type: u32, source: &str) -> Result<WebGlShader, JsValue> {
+    let shader = context
+        .create_shader(shader_type)
+        .ok_or_else(|| JsValue::from_str("could not create shader"))?;
+    context.shader_source(&shader, source);
+    context.compile_shader(&shader);
+
+    if context
+        .get_shader_parameter(&shader, Gl::COMPILE_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(shader)
+    } else {
+        Err(JsValue::from_str(
+            &context
+                .get_shader_info_log(&shader)
+                .unwrap_or_else(|| String::from("could not compile shader")),
+        ))
+    }
+}
+
+fn link_program(
+    context: &Gl,
+    vertex_shader: &WebGlShader,
+    fragment_shader: &WebGlShader,
+) -> Result<WebGlProgram, JsValue> {
+    let program = context
+        .create_program()
+        .ok_or_else(|| JsValue::from_str("could not create program"))?;
+    context.attach_shader(&program, vertex_shader);
+    context.attach_shader(&program, fragment_shader);
+    context.link_program(&program);
+
+    if context
+        .get_program_parameter(&program, Gl::LINK_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(program)
+    } else {
+        Err(JsValue::from_str(
+            &context
+                .get_program_info_log(&program)
+                .unwrap_or_else(|| String::from("could not link program")),
+        ))
+    }
+}
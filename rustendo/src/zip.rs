@@ -0,0 +1,454 @@
+// Record signatures, as they appear in the file.
+const LOCAL_HEADER_SIGNATURE: &[u8; 4] = b"PK\x03\x04";
+const CENTRAL_HEADER_SIGNATURE: &[u8; 4] = b"PK\x01\x02";
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: &[u8; 4] = b"PK\x05\x06";
+
+// No NES ROM comes close to this, so anything bigger is a zip bomb.
+const MAX_ROM_SIZE: usize = 0x1000000;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+// Length and distance codes' base values and extra bits, from RFC 1951.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are sent in, in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+#[derive(Debug, PartialEq)]
+pub enum ZipError {
+    /// The archive's records are cut short or point outside it.
+    Truncated,
+    /// There's no file ending in `.nes` in the archive.
+    NoRom,
+    /// The ROM is compressed with something other than deflate.
+    UnsupportedMethod(u16),
+    /// The deflate stream is malformed.
+    InvalidDeflate,
+    /// The ROM is larger than the archive says, or than any real ROM.
+    TooLarge,
+}
+
+impl std::fmt::Display for ZipError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ZipError::Truncated => write!(f, "the archive is truncated"),
+            ZipError::NoRom => write!(f, "there is no .nes file in the archive"),
+            ZipError::UnsupportedMethod(method) => {
+                write!(f, "compression method {} is not supported", method)
+            }
+            ZipError::InvalidDeflate => write!(f, "the compressed data is invalid"),
+            ZipError::TooLarge => write!(f, "the ROM is too large"),
+        }
+    }
+}
+
+impl std::error::Error for ZipError {}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(LOCAL_HEADER_SIGNATURE)
+}
+
+/// Extracts the first `.nes` file from a ZIP archive, going by the central
+/// directory at the end. Only stored and deflated files can be extracted,
+/// which is what every ZIP tool writes by default.
+pub fn extract_rom(data: &[u8]) -> Result<Vec<u8>, ZipError> {
+    // Offsets come from the file, so adding them up can overflow, which
+    // can only mean they point outside it.
+    let add = |a: usize, b: usize| a.checked_add(b).ok_or(ZipError::Truncated);
+    let bytes_at = |offset: usize, length: usize| -> Result<&[u8], ZipError> {
+        data.get(offset..add(offset, length)?)
+            .ok_or(ZipError::Truncated)
+    };
+    let u16_at = |offset: usize| -> Result<u16, ZipError> {
+        bytes_at(offset, 2).map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    };
+    let u32_at = |offset: usize| -> Result<usize, ZipError> {
+        bytes_at(offset, 4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    };
+
+    // The end record is last, but may be followed by a comment.
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .find(|&offset| data[offset..].starts_with(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+        .ok_or(ZipError::Truncated)?;
+    let entries = u16_at(end + 10)?;
+    let mut offset = u32_at(end + 16)?;
+
+    for _ in 0..entries {
+        if !data[offset.min(data.len())..].starts_with(CENTRAL_HEADER_SIGNATURE) {
+            return Err(ZipError::Truncated);
+        }
+
+        let method = u16_at(add(offset, 10)?)?;
+        let compressed_size = u32_at(add(offset, 20)?)?;
+        let uncompressed_size = u32_at(add(offset, 24)?)?;
+        let name_length = u16_at(add(offset, 28)?)? as usize;
+        let extra_length = u16_at(add(offset, 30)?)? as usize;
+        let comment_length = u16_at(add(offset, 32)?)? as usize;
+        let local_header = u32_at(add(offset, 42)?)?;
+        let name = bytes_at(add(offset, 46)?, name_length)?;
+
+        if name.to_ascii_lowercase().ends_with(b".nes") {
+            // The local header's name and extra field can differ in length
+            // from the central directory's.
+            let start = add(
+                add(local_header, 30)?,
+                u16_at(add(local_header, 26)?)? as usize + u16_at(add(local_header, 28)?)? as usize,
+            )?;
+            let contents = bytes_at(start, compressed_size)?;
+
+            if uncompressed_size > MAX_ROM_SIZE {
+                return Err(ZipError::TooLarge);
+            }
+
+            return match method {
+                STORED => Ok(contents.to_vec()),
+                DEFLATED => inflate(contents, uncompressed_size),
+                method => Err(ZipError::UnsupportedMethod(method)),
+            };
+        }
+
+        offset = add(offset, 46 + name_length + extra_length + comment_length)?;
+    }
+
+    Err(ZipError::NoRom)
+}
+
+/// Reads a deflate stream a bit at a time, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    buffer: u32,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, ZipError> {
+        while self.count < n {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(ZipError::InvalidDeflate)?;
+            self.buffer |= (byte as u32) << self.count;
+            self.position += 1;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1 << n) - 1);
+        self.buffer >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Drops the rest of the current byte, before a stored block.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, as the number of codes of each length and the
+/// symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols = vec![];
+        for length in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|(_, &l)| l == length) {
+                symbols.push(symbol as u16);
+            }
+        }
+
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, ZipError> {
+        // The first code of each length follows on from the last code of
+        // the length before, so a code is found by counting through them.
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(ZipError::InvalidDeflate)
+    }
+}
+
+/// Decompresses a raw deflate stream, as ZIP files store it, failing if it
+/// inflates to more than `max_size` bytes.
+pub fn inflate(data: &[u8], max_size: usize) -> Result<Vec<u8>, ZipError> {
+    let mut reader = BitReader::new(data);
+    let mut output = vec![];
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let length = reader.bits(16)?;
+                let complement = reader.bits(16)?;
+                if length != !complement & 0xFFFF {
+                    return Err(ZipError::InvalidDeflate);
+                }
+                if output.len() + length as usize > max_size {
+                    return Err(ZipError::TooLarge);
+                }
+                for _ in 0..length {
+                    output.push(reader.bits(8)? as u8);
+                }
+            }
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                inflate_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            2 => {
+                let (lengths, distances) = dynamic_codes(&mut reader)?;
+                inflate_block(&mut reader, &mut output, max_size, &lengths, &distances)?;
+            }
+            _ => return Err(ZipError::InvalidDeflate),
+        }
+
+        if last {
+            return Ok(output);
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8; 288];
+    lengths[144..256].iter_mut().for_each(|length| *length = 9);
+    lengths[256..280].iter_mut().for_each(|length| *length = 7);
+
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), ZipError> {
+    let literal_codes = reader.bits(5)? as usize + 257;
+    let distance_codes = reader.bits(5)? as usize + 1;
+    let code_length_codes = reader.bits(4)? as usize + 4;
+
+    let mut code_length_lengths = [0; 19];
+    for &symbol in CODE_LENGTH_ORDER.iter().take(code_length_codes) {
+        code_length_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_length_lengths);
+
+    // Literal/length and distance code lengths run on from each other, so
+    // a repeat can cross from one into the other.
+    let mut lengths = vec![];
+    while lengths.len() < literal_codes + distance_codes {
+        let (length, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (
+                *lengths.last().ok_or(ZipError::InvalidDeflate)?,
+                3 + reader.bits(2)?,
+            ),
+            17 => (0, 3 + reader.bits(3)?),
+            18 => (0, 11 + reader.bits(7)?),
+            _ => return Err(ZipError::InvalidDeflate),
+        };
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    if lengths.len() > literal_codes + distance_codes {
+        return Err(ZipError::InvalidDeflate);
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_codes]),
+        Huffman::new(&lengths[literal_codes..]),
+    ))
+}
+
+fn inflate_block(
+    reader: &mut BitReader,
+    output: &mut Vec<u8>,
+    max_size: usize,
+    lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), ZipError> {
+    loop {
+        let symbol = lengths.decode(reader)? as usize;
+
+        match symbol {
+            0..=255 if output.len() == max_size => return Err(ZipError::TooLarge),
+            0..=255 => output.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let symbol = symbol - 257;
+                let length = LENGTH_BASE[symbol] as usize
+                    + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+
+                let symbol = distances.decode(reader)? as usize;
+                if symbol >= DISTANCE_BASE.len() {
+                    return Err(ZipError::InvalidDeflate);
+                }
+                let distance = DISTANCE_BASE[symbol] as usize
+                    + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                if distance > output.len() {
+                    return Err(ZipError::InvalidDeflate);
+                }
+                if output.len() + length > max_size {
+                    return Err(ZipError::TooLarge);
+                }
+
+                // The copy can overlap what it's writing, to repeat a run.
+                for _ in 0..length {
+                    output.push(output[output.len() - distance]);
+                }
+            }
+            _ => return Err(ZipError::InvalidDeflate),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_rom, inflate, is_zip, ZipError};
+    use rustendo_lib::cartridge::{Cartridge, CartridgeFormat};
+    use std::fs;
+    use std::path::Path;
+
+    /// A ZIP archive holding `files`, each stored uncompressed.
+    fn stored_zip(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = vec![];
+        let mut central_directory = vec![];
+
+        for (name, contents) in files {
+            let local_header = zip.len() as u32;
+            let size = (contents.len() as u32).to_le_bytes();
+
+            zip.extend(b"PK\x03\x04");
+            zip.extend(&[20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            zip.extend(&size);
+            zip.extend(&size);
+            zip.extend(&(name.len() as u16).to_le_bytes());
+            zip.extend(&[0, 0]);
+            zip.extend(name.as_bytes());
+            zip.extend(*contents);
+
+            central_directory.extend(b"PK\x01\x02");
+            central_directory.extend(&[20, 0, 20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+            central_directory.extend(&size);
+            central_directory.extend(&size);
+            central_directory.extend(&(name.len() as u16).to_le_bytes());
+            central_directory.extend(&[0; 12]);
+            central_directory.extend(&local_header.to_le_bytes());
+            central_directory.extend(name.as_bytes());
+        }
+
+        let central_directory_offset = zip.len() as u32;
+        zip.extend(&central_directory);
+        zip.extend(b"PK\x05\x06");
+        zip.extend(&[0, 0, 0, 0]);
+        zip.extend(&(files.len() as u16).to_le_bytes());
+        zip.extend(&(files.len() as u16).to_le_bytes());
+        zip.extend(&(central_directory.len() as u32).to_le_bytes());
+        zip.extend(&central_directory_offset.to_le_bytes());
+        zip.extend(&[0, 0]);
+        zip
+    }
+
+    #[test]
+    fn stored() {
+        let zip = stored_zip(&[("readme.txt", b"hello"), ("game.nes", b"NES\x1A")]);
+        assert!(is_zip(&zip));
+        assert!(!is_zip(b"NES\x1A"));
+        assert_eq!(extract_rom(&zip), Ok(b"NES\x1A".to_vec()));
+
+        let zip = stored_zip(&[("readme.txt", b"hello")]);
+        assert_eq!(extract_rom(&zip), Err(ZipError::NoRom));
+        assert_eq!(extract_rom(&zip[..20]), Err(ZipError::Truncated));
+
+        // A local header offset far past the end of the archive.
+        let mut zip = stored_zip(&[("game.nes", b"NES\x1A")]);
+        let local_header = zip.len() - 22 - 8 - 4;
+        zip[local_header..local_header + 4].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(extract_rom(&zip), Err(ZipError::Truncated));
+
+        // An uncompressed size bigger than any ROM.
+        let mut zip = stored_zip(&[("game.nes", b"NES\x1A")]);
+        let uncompressed_size = zip.len() - 22 - 8 - 22;
+        zip[uncompressed_size..uncompressed_size + 4].copy_from_slice(&[0xFF; 4]);
+        assert_eq!(extract_rom(&zip), Err(ZipError::TooLarge));
+    }
+
+    #[test]
+    fn deflated() {
+        // nestest.zip has a stored readme.txt, then nestest.nes deflated
+        // with dynamic Huffman codes.
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+        let zip = fs::read(root.join("nestest.zip")).expect("nestest.zip");
+        let expected = fs::read(root.join("nestest.nes")).expect("nestest.nes");
+
+        let rom = extract_rom(&zip).expect("ROM in archive");
+        assert_eq!(rom, expected);
+
+        let cartridge = Cartridge::new(rom).expect("valid ROM");
+        assert_eq!(cartridge.mapper(), 0);
+        assert_eq!(cartridge.format(), CartridgeFormat::INes);
+    }
+
+    #[test]
+    fn fixed_and_stored_blocks() {
+        // "abc" repeated, with fixed codes and a back reference.
+        let abc = [0x4B, 0x4C, 0x4A, 0x4E, 0x04, 0x23, 0x00];
+        assert_eq!(inflate(&abc, 9), Ok(b"abcabcabc".to_vec()));
+        // "hi" in a single stored block.
+        let hi = [0x01, 0x02, 0x00, 0xFD, 0xFF, b'h', b'i'];
+        assert_eq!(inflate(&hi, 2), Ok(b"hi".to_vec()));
+        assert_eq!(inflate(&[0x07], 0x100), Err(ZipError::InvalidDeflate));
+
+        // Inflating past the size the archive gives stops early.
+        assert_eq!(inflate(&abc, 8), Err(ZipError::TooLarge));
+        assert_eq!(inflate(&abc, 2), Err(ZipError::TooLarge));
+        assert_eq!(inflate(&hi, 1), Err(ZipError::TooLarge));
+    }
+}
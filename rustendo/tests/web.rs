@@ -0,0 +1,84 @@
+//! Tests that need a browser, run with `wasm-pack test --headless --firefox`.
+
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::Uint8Array;
+use wasm_bindgen_test::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+fn add_canvas() {
+    let document = web_sys::window().unwrap().document().unwrap();
+    if document.get_element_by_id("rustendo-canvas").is_none() {
+        let canvas = document.create_element("canvas").unwrap();
+        canvas.set_id("rustendo-canvas");
+        document
+            .document_element()
+            .unwrap()
+            .append_child(&canvas)
+            .unwrap();
+    }
+}
+
+#[wasm_bindgen_test]
+fn render_rejects_bad_roms() {
+    add_canvas();
+
+    // NES 2.0 header declaring ROM sizes far larger than memory.
+    let huge = [
+        0x4E, 0x45, 0x53, 0x1A, 0xFF, 0xFF, 0x00, 0x08, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    for rom in [&[0xDE, 0xAD, 0xBE, 0xEF][..], &huge[..]].iter() {
+        let error = rustendo::render(Uint8Array::from(*rom)).unwrap_err();
+        assert!(error.as_string().unwrap().starts_with("could not load ROM"));
+    }
+}
+
+/// Resolves after `ms` milliseconds, letting animation frames run.
+async fn sleep(ms: i32) {
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms)
+            .unwrap();
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await.unwrap();
+}
+
+#[wasm_bindgen_test]
+async fn load_nsf() {
+    // One track loaded at $8000, with PLAY counting its calls in $10 and
+    // INIT doing nothing.
+    let mut nsf = vec![0; 0x80];
+    nsf[0..5].copy_from_slice(b"NESM\x1A");
+    nsf[0x05] = 1;
+    nsf[0x06] = 1;
+    nsf[0x07] = 1;
+    nsf[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+    nsf[0x0A..0x0C].copy_from_slice(&0x8003u16.to_le_bytes());
+    nsf[0x0C..0x0E].copy_from_slice(&0x8000u16.to_le_bytes());
+    nsf[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+    nsf.extend_from_slice(&[
+        0xE6, 0x10, // PLAY: INC $10
+        0x60, // RTS
+        0x60, // INIT: RTS
+    ]);
+
+    rustendo::load_nsf(Uint8Array::from(&nsf[..])).unwrap();
+    assert_eq!(rustendo::peek(0x10), Some(0), "PLAY not called yet");
+
+    sleep(250).await;
+    assert!(
+        rustendo::peek(0x10).unwrap() > 0,
+        "the animation loop calls PLAY"
+    );
+
+    rustendo::shutdown();
+    assert_eq!(rustendo::peek(0x10), None, "tune dropped");
+
+    nsf[0] = b'X';
+    let error = rustendo::load_nsf(Uint8Array::from(&nsf[..])).unwrap_err();
+    assert!(error.as_string().unwrap().starts_with("could not load NSF"));
+}
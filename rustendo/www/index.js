@@ -1,5 +1,16 @@
 import * as wasm from "rustendo";
 
+function showError(error) {
+    console.error(error);
+    alert(`Rustendo: ${error}`);
+}
+
+try {
+    wasm.init_canvas('rustendo-canvas');
+} catch (error) {
+    showError(error);
+}
+
 const loadCartridgeButton = document.getElementById('load-cartridge-button');
 const cartridgeFile = document.getElementById('cartridge-file');
 
@@ -17,6 +28,10 @@ cartridgeFile.addEventListener('change', function() {
 
     cartridge.arrayBuffer().then(function(arrayBuffer) {
         const byteArray = new Uint8Array(arrayBuffer);
-        wasm.render(byteArray);
+        try {
+            wasm.render_zip(byteArray);
+        } catch (error) {
+            showError(error);
+        }
     });
 }, false);
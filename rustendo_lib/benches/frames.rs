@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use rustendo_lib::cartridge::Cartridge;
+use rustendo_lib::nes::Nes;
+use std::fs;
+use std::path::Path;
+
+fn load_nestest() -> Nes {
+    let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let nes_test = current_dir.parent().unwrap().join("nestest.nes");
+    let cartridge = Cartridge::new(fs::read(nes_test).unwrap()).unwrap();
+
+    let mut nes = Nes::new();
+    nes.load_cartridge(cartridge);
+    nes.reset();
+    nes
+}
+
+// Criterion reports the throughput in frames per second.
+fn frames(c: &mut Criterion) {
+    let mut group = c.benchmark_group("nestest");
+    group.throughput(Throughput::Elements(1));
+
+    let mut nes = load_nestest();
+    group.bench_function("frame", |b| b.iter(|| nes.run_frame()));
+
+    // The PPU keeps its timing but skips drawing, as when fast-forwarding.
+    let mut nes = load_nestest();
+    nes.set_rendering_enabled(false);
+    group.bench_function("frame without rendering", |b| b.iter(|| nes.run_frame()));
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
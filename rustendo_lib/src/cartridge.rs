@@ -1,15 +1,33 @@
 use crate::mappers::mapper_000::Mapper000;
 use crate::mappers::mapper_001::Mapper001;
+use crate::mappers::mapper_009::Mapper009;
+use crate::mappers::mapper_024::Mapper024;
+use crate::mappers::mapper_026::Mapper026;
+use crate::mappers::mapper_066::Mapper066;
 use crate::mappers::Mapper;
+use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum MirroringType {
     Vertical,
     Horizontal,
     OneScreen
 }
 
-#[derive(Debug)]
+/// Where a mapper sends an access to one 1K nametable page.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum NametableSource {
+    /// The first of the console's two nametables.
+    CiramA,
+    /// The second of the console's two nametables.
+    CiramB,
+    /// A tile and attribute the mapper fills the whole page with.
+    FillMode,
+    /// The mapper's own memory, at this offset.
+    Cart(usize),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ConsoleType {
     NES,
     NintendoVsSystem,
@@ -17,7 +35,7 @@ pub enum ConsoleType {
     ExtendedConsoleType,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TimingMode {
     NtscNes,
     PalNes,
@@ -25,30 +43,212 @@ pub enum TimingMode {
     Dendy,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum CartridgeFormat {
     INes,
     Nes2,
 }
 
+/// What the header says about a cartridge, for showing to the user.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CartridgeInfo {
+    pub format: CartridgeFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub chr_ram_size: usize,
+    pub mirroring: MirroringType,
+    pub has_battery: bool,
+    pub console_type: ConsoleType,
+    pub timing_mode: TimingMode,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CartridgeError {
+    /// The file doesn't start with `NES\x1A`.
+    BadMagic,
+    /// The file is shorter than the 16 byte header.
+    TruncatedHeader,
+    UnsupportedMapper(u16),
+    /// The file is shorter than the trainer, PRG ROM and CHR ROM the
+    /// header declares, or `from_parts` was given more than a header can
+    /// declare.
+    SizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+}
+
+impl std::fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CartridgeError::BadMagic => write!(f, "not an iNES file"),
+            CartridgeError::TruncatedHeader => write!(f, "header is truncated"),
+            CartridgeError::UnsupportedMapper(mapper) => {
+                write!(f, "mapper {} is not supported", mapper)
+            }
+            CartridgeError::SizeMismatch { expected, actual } => write!(
+                f,
+                "header declares {} bytes but the file is {} bytes",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// The mapper numbers `Cartridge::new` can load, in ascending order.
+pub fn supported_mappers() -> &'static [u16] {
+    // Kept in step with the match in `Cartridge::new` by a test.
+    &[0, 1, 9, 24, 26, 66]
+}
+
+pub fn is_mapper_supported(mapper: u16) -> bool {
+    supported_mappers().contains(&mapper)
+}
+
+#[derive(Clone)]
 pub struct Cartridge {
-    raw: Vec<u8>,
+    // Shared between clones, since the ROM never changes.
+    raw: Rc<Vec<u8>>,
     mapper: Box<dyn Mapper>,
 }
 
 impl Cartridge {
-    pub fn new(raw: Vec<u8>) -> Self {
+    /// Parses an iNES or NES 2.0 file, checking that the header is valid,
+    /// the mapper is supported and the file is as large as the header says.
+    pub fn new(raw: Vec<u8>) -> Result<Self, CartridgeError> {
+        if raw.len() < 0x10 {
+            return Err(CartridgeError::TruncatedHeader);
+        }
+
         let header = Self::_header(&raw);
+
+        if header[0..4] != *b"NES\x1A" {
+            return Err(CartridgeError::BadMagic);
+        }
+
+        let trainer_size: usize = if header[6] & 0x4 == 0x4 { 0x200 } else { 0 };
+        // NES 2.0 sizes can be larger than any real file, so don't overflow.
+        let expected = (0x10 + trainer_size)
+            .saturating_add(Self::_prg_rom_size(header))
+            .saturating_add(Self::_chr_rom_size(header));
+
+        if raw.len() < expected {
+            return Err(CartridgeError::SizeMismatch {
+                expected,
+                actual: raw.len(),
+            });
+        }
+
         let mapper = match Self::_mapper(&header) {
             0 => Box::new(Mapper000::new(
                 Self::_prg_rom_size(&header),
                 Self::_chr_ram_size(&header),
             )) as Box<dyn Mapper>,
-            1 => Box::new(Mapper001::new(Self::_chr_ram_size(&header))) as Box<dyn Mapper>,
-            _ => unimplemented!(),
+            1 => Box::new(Mapper001::new(
+                Self::_prg_rom_size(&header),
+                Self::_chr_ram_size(&header),
+            )) as Box<dyn Mapper>,
+            9 => Box::new(Mapper009::new(
+                Self::_prg_rom_size(&header),
+                Self::_chr_rom_size(&header),
+                Self::_chr_ram_size(&header),
+            )) as Box<dyn Mapper>,
+            24 => Box::new(Mapper024::new(
+                Self::_prg_rom_size(&header),
+                Self::_chr_rom_size(&header),
+                Self::_chr_ram_size(&header),
+            )) as Box<dyn Mapper>,
+            26 => Box::new(Mapper026::new(
+                Self::_prg_rom_size(&header),
+                Self::_chr_rom_size(&header),
+                Self::_chr_ram_size(&header),
+            )) as Box<dyn Mapper>,
+            // Submapper 1 is a board that keeps the ROM off the bus.
+            66 => Box::new(
+                Mapper066::new(
+                    Self::_prg_rom_size(&header),
+                    Self::_chr_rom_size(&header),
+                    Self::_chr_ram_size(&header),
+                )
+                .with_bus_conflicts(Self::_submapper(&header) != 1),
+            ) as Box<dyn Mapper>,
+            mapper => return Err(CartridgeError::UnsupportedMapper(mapper)),
         };
 
-        Cartridge { raw, mapper }
+        Ok(Cartridge {
+            raw: Rc::new(raw),
+            mapper,
+        })
+    }
+
+    /// Builds a cartridge from separate PRG and CHR data by synthesizing a header.
+    /// PRG is padded to a multiple of 16 KiB and CHR to a multiple of 8 KiB. If there
+    /// is no CHR data, the cartridge gets 8 KiB of CHR RAM instead.
+    ///
+    /// The header can only describe horizontal or vertical mirroring, so one-screen
+    /// mirroring is left up to the mapper. Mappers above 255 and more than 255 banks
+    /// of PRG or CHR get a NES 2.0 header.
+    ///
+    /// Fails if the mapper isn't supported, or with `SizeMismatch` if PRG or CHR is
+    /// too large for even a NES 2.0 header to describe.
+    pub fn from_parts(
+        prg: Vec<u8>,
+        chr: Vec<u8>,
+        mapper: u16,
+        mirroring: MirroringType,
+    ) -> Result<Self, CartridgeError> {
+        let prg_banks = prg.len().div_ceil(0x4000);
+        let chr_banks = chr.len().div_ceil(0x2000);
+
+        // NES 2.0 bank counts are 12 bits, and from $F00 up they're an
+        // exponent instead, which can't describe arbitrary sizes.
+        if prg_banks > 0xEFF {
+            return Err(CartridgeError::SizeMismatch {
+                expected: 0xEFF * 0x4000,
+                actual: prg.len(),
+            });
+        }
+        if chr_banks > 0xEFF {
+            return Err(CartridgeError::SizeMismatch {
+                expected: 0xEFF * 0x2000,
+                actual: chr.len(),
+            });
+        }
+
+        let mut raw = vec![0; 0x10];
+        raw[0..4].copy_from_slice(b"NES\x1A");
+        raw[4] = prg_banks as u8;
+        raw[5] = chr_banks as u8;
+        raw[6] = ((mapper as u8) & 0x0F) << 4;
+        raw[7] = (mapper as u8) & 0xF0;
+
+        if mirroring == MirroringType::Vertical {
+            raw[6] |= 0x1;
+        }
+
+        // Mappers and bank counts above 255 only fit in a NES 2.0 header.
+        if mapper > 0xFF || prg_banks > 0xFF || chr_banks > 0xFF {
+            raw[7] |= 0x08;
+            raw[8] = ((mapper >> 8) as u8) & 0x0F;
+            raw[9] = (((chr_banks >> 8) << 4) | (prg_banks >> 8)) as u8;
+            // 8 KiB of PRG RAM, plus 8 KiB of CHR RAM if there's no CHR ROM.
+            raw[10] = 0x07;
+            if chr_banks == 0 {
+                raw[11] = 0x07;
+            }
+        }
+
+        raw.extend_from_slice(&prg);
+        raw.resize(0x10 + prg_banks * 0x4000, 0);
+        raw.extend_from_slice(&chr);
+        raw.resize(0x10 + prg_banks * 0x4000 + chr_banks * 0x2000, 0);
+
+        Self::new(raw)
     }
 
     pub fn header(&self) -> &[u8] {
@@ -70,12 +270,15 @@ impl Cartridge {
         }
     }
 
-    fn rom_size(size: u16, units: u16) -> u16 {
+    fn rom_size(size: usize, units: usize) -> usize {
         match size & 0xF00 {
             0xF00 => {
                 let multiplier = size & 0x3;
                 let exponent = (size & 0xFC) >> 2;
-                2u16.pow(exponent as u32) * (multiplier * 2 + 1)
+                2usize
+                    .checked_pow(exponent as u32)
+                    .and_then(|size| size.checked_mul(multiplier * 2 + 1))
+                    .unwrap_or(usize::MAX)
             }
             _ => size * units,
         }
@@ -89,11 +292,11 @@ impl Cartridge {
         match Self::_format(header) {
             CartridgeFormat::INes => ((header[4] as usize) * 0x4000),
             CartridgeFormat::Nes2 => {
-                let lsb = header[4] as u16;
-                let msb = ((header[9] as u16) & 0xF) << 4;
+                let lsb = header[4] as usize;
+                let msb = ((header[9] as usize) & 0xF) << 8;
                 let size = msb | lsb;
 
-                Self::rom_size(size, 0x4000) as usize
+                Self::rom_size(size, 0x4000)
             }
         }
     }
@@ -112,11 +315,11 @@ impl Cartridge {
         match Self::_format(header) {
             CartridgeFormat::INes => (header[5] as usize) * 0x2000,
             CartridgeFormat::Nes2 => {
-                let lsb = header[5] as u16;
-                let msb = (header[9] as u16) & 0xF0;
+                let lsb = header[5] as usize;
+                let msb = (header[9] as usize) & 0xF0;
                 let size = msb << 4 | lsb;
 
-                Self::rom_size(size, 0x2000) as usize
+                Self::rom_size(size, 0x2000)
             }
         }
     }
@@ -147,7 +350,7 @@ impl Cartridge {
     }
 
     pub fn prg_nvram_size(&self) -> usize {
-        let shift_count = self.header()[10] & 0xF0;
+        let shift_count = self.header()[10] >> 4;
         match shift_count {
             0 => 0,
             _ => 64 << shift_count,
@@ -156,8 +359,9 @@ impl Cartridge {
 
     fn _chr_ram_size(header: &[u8]) -> usize {
         match Self::_format(header) {
+            // iNES cartridges without CHR ROM have 8 KiB of CHR RAM instead.
             CartridgeFormat::INes => match header[5] {
-                0 => 0x1FE000,
+                0 => 0x2000,
                 _ => 0,
             },
             CartridgeFormat::Nes2 => match header[11] & 0x0F {
@@ -172,7 +376,7 @@ impl Cartridge {
     }
 
     pub fn chr_nvram_size(&self) -> usize {
-        let shift_count = self.header()[11] & 0xF0;
+        let shift_count = self.header()[11] >> 4;
         match shift_count {
             0 => 0,
             _ => 64 << shift_count,
@@ -197,19 +401,23 @@ impl Cartridge {
         }
     }
 
+    /// The mirroring currently in effect, which the mapper may override.
     pub fn mirroring_type(&self) -> MirroringType {
         match self.mapper.mirroring_type() {
-            None => {
-                if self.header()[6] & 0x1 == 0x1 {
-                    MirroringType::Vertical
-                } else {
-                    MirroringType::Horizontal
-                }
-            },
+            None => self.header_mirroring(),
             Some(mirroring) => mirroring
         }
     }
 
+    /// The mirroring declared in the header, regardless of the mapper.
+    pub fn header_mirroring(&self) -> MirroringType {
+        if self.header()[6] & 0x1 == 0x1 {
+            MirroringType::Vertical
+        } else {
+            MirroringType::Horizontal
+        }
+    }
+
     pub fn has_battery(&self) -> bool {
         self.header()[6] & 0x2 == 0x2
     }
@@ -229,12 +437,20 @@ impl Cartridge {
         }
     }
 
+    fn _submapper(header: &[u8]) -> u8 {
+        match Self::_format(header) {
+            CartridgeFormat::INes => 0,
+            CartridgeFormat::Nes2 => (header[8] & 0xF0) >> 4,
+        }
+    }
+
     pub fn mapper(&self) -> u16 {
         Self::_mapper(self.header())
     }
 
+    /// The submapper number, which only NES 2.0 headers have.
     pub fn submapper(&self) -> u8 {
-        self.header()[8] & 0xF0 >> 4
+        Self::_submapper(self.header())
     }
 
     pub fn console_type(&self) -> ConsoleType {
@@ -248,7 +464,7 @@ impl Cartridge {
     }
 
     pub fn timing_mode(&self) -> TimingMode {
-        match self.header()[12] & 0x2 {
+        match self.header()[12] & 0x3 {
             0x0 => TimingMode::NtscNes,
             0x1 => TimingMode::PalNes,
             0x2 => TimingMode::MultipleRegion,
@@ -257,6 +473,22 @@ impl Cartridge {
         }
     }
 
+    pub fn info(&self) -> CartridgeInfo {
+        CartridgeInfo {
+            format: self.format(),
+            mapper: self.mapper(),
+            submapper: self.submapper(),
+            prg_rom_size: self.prg_rom_size(),
+            chr_rom_size: self.chr_rom_size(),
+            prg_ram_size: self.prg_ram_size(),
+            chr_ram_size: self.chr_ram_size(),
+            mirroring: self.header_mirroring(),
+            has_battery: self.has_battery(),
+            console_type: self.console_type(),
+            timing_mode: self.timing_mode(),
+        }
+    }
+
     pub fn cpu_read(&self, address: u16) -> u8 {
         match self.mapper.cpu_read(address) {
             (Some(address), _) => self.prg_rom()[address],
@@ -266,7 +498,13 @@ impl Cartridge {
     }
 
     pub fn cpu_write(&mut self, address: u16, data: u8) {
+        // The ROM drives the bus along with the CPU, and the 0s win.
+        let data = match address {
+            0x8000..=0xFFFF if self.mapper.has_bus_conflicts() => data & self.cpu_read(address),
+            _ => data,
+        };
         self.mapper.cpu_write(address, data);
+        self.mapper.audio_cpu_write(address, data);
     }
 
     pub fn ppu_read(&self, address: u16) -> u8 {
@@ -280,20 +518,257 @@ impl Cartridge {
     pub fn ppu_write(&mut self, address: u16, data: u8) {
         self.mapper.ppu_write(address, data);
     }
+
+    pub fn irq_pending(&self) -> bool {
+        self.mapper.irq_pending()
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.mapper.clear_irq();
+    }
+
+    pub fn on_a12_rising(&mut self) {
+        self.mapper.on_a12_rising();
+    }
+
+    pub fn ppu_fetch_notify(&mut self, address: u16) {
+        self.mapper.ppu_fetch_notify(address);
+    }
+
+    /// Where the mapper sends a nametable access, if it overrides
+    /// `mirroring_type` for it.
+    pub fn map_nametable(&self, address: u16) -> Option<NametableSource> {
+        self.mapper.map_nametable(address)
+    }
+
+    pub fn nametable_read(&self, source: NametableSource, address: u16) -> u8 {
+        self.mapper.nametable_read(source, address)
+    }
+
+    pub fn nametable_write(&mut self, source: NametableSource, address: u16, data: u8) {
+        self.mapper.nametable_write(source, address, data);
+    }
+
+    /// Whether the PRG RAM has been written since `clear_sram_dirty`.
+    pub fn sram_dirty(&self) -> bool {
+        self.mapper.sram_dirty()
+    }
+
+    pub fn clear_sram_dirty(&mut self) {
+        self.mapper.clear_sram_dirty();
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+    }
+
+    /// The cartridge's expansion audio sample, or silence if it has none.
+    pub fn audio_sample(&mut self) -> f32 {
+        self.mapper.audio_sample()
+    }
+
+    /// A cartridge with a blank iNES header around `mapper`, for mappers
+    /// that aren't tied to an iNES file, like the NSF player's and tests'.
+    pub(crate) fn with_mapper(mapper: Box<dyn Mapper>) -> Self {
+        let mut raw = b"NES\x1A".to_vec();
+        raw.resize(0x10, 0);
+        Cartridge {
+            raw: Rc::new(raw),
+            mapper,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Cartridge, CartridgeFormat};
+    use super::{
+        is_mapper_supported, supported_mappers, Cartridge, CartridgeError, CartridgeFormat,
+        MirroringType, TimingMode,
+    };
+    use crate::mappers::Mapper;
     use std::fs;
     use std::path::Path;
 
+    /// A mapper whose expansion audio outputs the last value written to it.
+    #[derive(Clone)]
+    struct AudioMapper {
+        level: u8,
+    }
+
+    impl Mapper for AudioMapper {
+        fn cpu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn cpu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn mirroring_type(&self) -> Option<MirroringType> {
+            None
+        }
+
+        fn audio_cpu_write(&mut self, address: u16, data: u8) {
+            if address == 0x9000 {
+                self.level = data;
+            }
+        }
+
+        fn audio_sample(&mut self) -> f32 {
+            self.level as f32 / 255.0
+        }
+    }
+
+    /// A mapper with 8K of WRAM, which bit 6 of $A001 write-protects like
+    /// the MMC3's.
+    #[derive(Clone)]
+    struct WramMapper {
+        wram: Vec<u8>,
+        protected: bool,
+    }
+
+    impl Mapper for WramMapper {
+        fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+            match address {
+                0x6000..=0x7FFF => (None, Some(self.wram[(address & 0x1FFF) as usize])),
+                _ => (None, None),
+            }
+        }
+
+        fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+            match address {
+                0x6000..=0x7FFF if !self.protected => self.wram[(address & 0x1FFF) as usize] = data,
+                0xA001 => self.protected = data & 0x40 == 0x40,
+                _ => (),
+            }
+            None
+        }
+
+        fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn mirroring_type(&self) -> Option<MirroringType> {
+            None
+        }
+    }
+
     fn get_cartridge() -> Cartridge {
         let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
         let nes_test = current_dir.parent().unwrap().join("nestest.nes");
         let buffer = fs::read(nes_test).unwrap();
 
-        Cartridge::new(buffer)
+        Cartridge::new(buffer).unwrap()
+    }
+
+    #[test]
+    fn validation() {
+        assert!(Cartridge::from_parts(vec![0; 0x4000], vec![], 0, MirroringType::Vertical).is_ok());
+
+        let mut bad_magic = vec![0x4E, 0x45, 0x53, 0x00, 0x01, 0x00];
+        bad_magic.resize(0x10 + 0x4000, 0);
+        assert_eq!(
+            Cartridge::new(bad_magic).err(),
+            Some(CartridgeError::BadMagic)
+        );
+
+        let truncated_header = vec![0x4E, 0x45, 0x53, 0x1A, 0x01];
+        assert_eq!(
+            Cartridge::new(truncated_header).err(),
+            Some(CartridgeError::TruncatedHeader)
+        );
+
+        // Declares one PRG bank and one CHR bank but only has the PRG bank.
+        let mut too_short = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x01];
+        too_short.resize(0x10 + 0x4000, 0);
+        assert_eq!(
+            Cartridge::new(too_short).err(),
+            Some(CartridgeError::SizeMismatch {
+                expected: 0x10 + 0x4000 + 0x2000,
+                actual: 0x10 + 0x4000
+            })
+        );
+
+        assert_eq!(
+            Cartridge::from_parts(vec![0; 0x4000], vec![], 4, MirroringType::Vertical).err(),
+            Some(CartridgeError::UnsupportedMapper(4))
+        );
+
+        // NES 2.0 exponent sizes far larger than memory.
+        let huge = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0xFF, 0xFF, 0x00, 0x08, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00,
+        ];
+        assert_eq!(
+            Cartridge::new(huge).err(),
+            Some(CartridgeError::SizeMismatch {
+                expected: usize::MAX,
+                actual: 0x10
+            })
+        );
+    }
+
+    #[test]
+    fn nes2_sizes() {
+        // NES 2.0 header with 0x102 PRG banks, 3 * 2^2 bytes of CHR ROM and
+        // 8 KiB of battery-backed PRG and CHR RAM.
+        let mut raw = vec![
+            0x4E, 0x45, 0x53, 0x1A, 0x02, 0x09, 0x00, 0x08, 0x00, 0xF1, 0x70, 0x70,
+        ];
+        raw.resize(0x10 + 0x102 * 0x4000 + 12, 0);
+        let cartridge = Cartridge::new(raw).unwrap();
+
+        assert_eq!(cartridge.prg_rom_size(), 0x102 * 0x4000);
+        assert_eq!(cartridge.chr_rom_size(), 12);
+        assert_eq!(cartridge.prg_nvram_size(), 0x2000);
+        assert_eq!(cartridge.chr_nvram_size(), 0x2000);
+    }
+
+    #[test]
+    fn submapper() {
+        // NES 2.0 header for mapper 0, submapper 5.
+        let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x08, 0x50];
+        raw.resize(0x10 + 0x4000, 0);
+        let cartridge = Cartridge::new(raw).unwrap();
+        assert_eq!(cartridge.format(), CartridgeFormat::Nes2);
+        assert_eq!(cartridge.mapper(), 0);
+        assert_eq!(cartridge.submapper(), 5, "high nibble of byte 8");
+
+        let cartridge = get_cartridge();
+        assert_eq!(cartridge.submapper(), 0, "iNES has no submapper");
+    }
+
+    #[test]
+    fn timing_mode() {
+        let modes = [
+            TimingMode::NtscNes,
+            TimingMode::PalNes,
+            TimingMode::MultipleRegion,
+            TimingMode::Dendy,
+        ];
+
+        for (bits, mode) in modes.iter().enumerate() {
+            // NES 2.0 header for mapper 0 with the timing in byte 12.
+            let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x00, 0x08];
+            raw.resize(0x10 + 0x4000, 0);
+            // The upper bits are unused and should be ignored.
+            raw[12] = 0xFC | bits as u8;
+
+            let cartridge = Cartridge::new(raw).unwrap();
+            assert_eq!(cartridge.timing_mode(), *mode);
+        }
     }
 
     #[test]
@@ -313,4 +788,164 @@ mod tests {
         let cartridge = get_cartridge();
         assert_eq!(cartridge.mapper(), 0);
     }
+
+    #[test]
+    fn from_parts() {
+        let prg: Vec<u8> = (0..0x8000).map(|i| i as u8).collect();
+        let chr: Vec<u8> = (0..0x2000).map(|i| (i >> 8) as u8).collect();
+        let cartridge =
+            Cartridge::from_parts(prg.clone(), chr.clone(), 1, MirroringType::Vertical).unwrap();
+
+        assert_eq!(cartridge.format(), CartridgeFormat::INes);
+        assert_eq!(cartridge.prg_rom(), &prg[..], "PRG ROM matches");
+        assert_eq!(cartridge.chr_rom(), &chr[..], "CHR ROM matches");
+        assert_eq!(cartridge.mapper(), 1);
+        assert_eq!(
+            cartridge.header()[6] & 0x1,
+            0x1,
+            "vertical mirroring in header"
+        );
+
+        let cartridge =
+            Cartridge::from_parts(vec![0xEA; 0x10], vec![], 0, MirroringType::Horizontal).unwrap();
+        assert_eq!(cartridge.prg_rom().len(), 0x4000, "PRG ROM padded");
+        assert_eq!(cartridge.chr_rom().len(), 0, "no CHR ROM");
+        assert_eq!(cartridge.mapper(), 0);
+        assert_eq!(cartridge.mirroring_type(), MirroringType::Horizontal);
+
+        // More banks than an iNES header can count.
+        let prg = vec![0; 0x101 * 0x4000];
+        let chr = vec![0; 0x102 * 0x2000];
+        let cartridge = Cartridge::from_parts(prg, chr, 0, MirroringType::Vertical).unwrap();
+        assert_eq!(cartridge.format(), CartridgeFormat::Nes2);
+        assert_eq!(cartridge.prg_rom().len(), 0x101 * 0x4000);
+        assert_eq!(cartridge.chr_rom().len(), 0x102 * 0x2000);
+
+        let chr = vec![0; 0xF00 * 0x2000];
+        assert_eq!(
+            Cartridge::from_parts(vec![0; 0x4000], chr, 0, MirroringType::Vertical).err(),
+            Some(CartridgeError::SizeMismatch {
+                expected: 0xEFF * 0x2000,
+                actual: 0xF00 * 0x2000,
+            })
+        );
+    }
+
+    #[test]
+    fn supported() {
+        assert_eq!(supported_mappers()[..2], [0, 1]);
+        assert!(is_mapper_supported(0));
+        assert!(is_mapper_supported(1));
+        assert!(!is_mapper_supported(4));
+
+        for mapper in 0..0x100 {
+            let cartridge =
+                Cartridge::from_parts(vec![0; 0x8000], vec![], mapper, MirroringType::Vertical);
+            assert_eq!(
+                cartridge.is_ok(),
+                is_mapper_supported(mapper),
+                "mapper {}",
+                mapper
+            );
+        }
+    }
+
+    #[test]
+    fn bus_conflicts() {
+        // GxROM, with $01 at $8000 and each CHR bank filled with its number.
+        let mut prg = vec![0x00; 0x8000];
+        prg[0] = 0x01;
+        let chr: Vec<u8> = (0..0x8000).map(|i| (i / 0x2000) as u8).collect();
+        let mut cartridge = Cartridge::from_parts(prg, chr, 66, MirroringType::Vertical).unwrap();
+
+        cartridge.cpu_write(0x8000, 0x03);
+        assert_eq!(cartridge.ppu_read(0x0000), 0x01, "ANDed with $01");
+
+        // The same cartridge as NES 2.0 submapper 1, without bus conflicts.
+        let mut raw = cartridge.raw.to_vec();
+        raw[7] |= 0x08;
+        raw[8] = 0x10;
+        let mut cartridge = Cartridge::new(raw).unwrap();
+
+        cartridge.cpu_write(0x8000, 0x03);
+        assert_eq!(cartridge.ppu_read(0x0000), 0x03);
+    }
+
+    #[test]
+    fn chr_rom_is_read_only() {
+        let chr: Vec<u8> = vec![0x55; 0x2000];
+        let mut cartridge =
+            Cartridge::from_parts(vec![0; 0x4000], chr, 0, MirroringType::Horizontal).unwrap();
+        assert_eq!(cartridge.chr_ram_size(), 0, "no CHR RAM with CHR ROM");
+
+        cartridge.ppu_write(0x0010, 0xAA);
+        assert_eq!(cartridge.ppu_read(0x0010), 0x55, "CHR ROM write ignored");
+
+        let mut cartridge =
+            Cartridge::from_parts(vec![0; 0x4000], vec![], 0, MirroringType::Horizontal).unwrap();
+        assert_eq!(cartridge.chr_ram_size(), 0x2000, "8 KiB of CHR RAM");
+
+        cartridge.ppu_write(0x1FFF, 0xAA);
+        assert_eq!(cartridge.ppu_read(0x1FFF), 0xAA, "CHR RAM write kept");
+    }
+
+    #[test]
+    fn header_mirroring() {
+        let mut cartridge =
+            Cartridge::from_parts(vec![0; 0x8000], vec![0; 0x2000], 1, MirroringType::Vertical)
+                .unwrap();
+
+        // Write 0b00011 to the MMC1 control register, one bit at a time.
+        for bit in [1, 1, 0, 0, 0].iter() {
+            cartridge.cpu_write(0x8000, *bit);
+        }
+
+        assert_eq!(cartridge.header_mirroring(), MirroringType::Vertical);
+        assert_eq!(cartridge.mirroring_type(), MirroringType::Horizontal);
+    }
+
+    #[test]
+    fn wram_write_protect() {
+        let mut cartridge = Cartridge::with_mapper(Box::new(WramMapper {
+            wram: vec![0; 0x2000],
+            protected: false,
+        }));
+
+        cartridge.cpu_write(0x6000, 0x42);
+        cartridge.cpu_write(0x7FFF, 0x24);
+        assert_eq!(cartridge.cpu_read(0x6000), 0x42);
+        assert_eq!(cartridge.cpu_read(0x7FFF), 0x24);
+
+        cartridge.cpu_write(0xA001, 0x40);
+        cartridge.cpu_write(0x6000, 0x99);
+        assert_eq!(cartridge.cpu_read(0x6000), 0x42, "protected");
+
+        cartridge.cpu_write(0xA001, 0x00);
+        cartridge.cpu_write(0x6000, 0x99);
+        assert_eq!(cartridge.cpu_read(0x6000), 0x99, "unprotected");
+    }
+
+    #[test]
+    fn sram_dirty() {
+        let mut cartridge =
+            Cartridge::from_parts(vec![0; 0x8000], vec![], 1, MirroringType::Vertical).unwrap();
+        assert!(!cartridge.sram_dirty());
+
+        cartridge.cpu_write(0x7FFF, 0x42);
+        assert!(cartridge.sram_dirty(), "dirty after a WRAM write");
+
+        cartridge.clear_sram_dirty();
+        assert!(!cartridge.sram_dirty());
+        assert_eq!(cartridge.cpu_read(0x7FFF), 0x42, "clearing keeps the data");
+    }
+
+    #[test]
+    fn expansion_audio() {
+        assert_eq!(get_cartridge().audio_sample(), 0.0, "mapper 0 is silent");
+
+        let mut cartridge = Cartridge::with_mapper(Box::new(AudioMapper { level: 0 }));
+        assert_eq!(cartridge.audio_sample(), 0.0);
+        cartridge.cpu_write(0x9000, 0xFF);
+        assert_eq!(cartridge.audio_sample(), 1.0);
+    }
 }
@@ -0,0 +1,172 @@
+// The Game Genie's letters, in the order of the nibbles they stand for.
+const GAME_GENIE_LETTERS: &[u8; 16] = b"APZLGITYEOXUKSVN";
+
+#[derive(Debug, PartialEq)]
+pub enum CheatError {
+    /// Game Genie codes are 6 or 8 letters long.
+    InvalidLength(usize),
+    /// A letter that isn't on the Game Genie's keypad.
+    InvalidLetter(char),
+    /// A raw code that isn't of the form `AAAA:VV`.
+    InvalidRawCode,
+}
+
+impl std::fmt::Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidLength(length) => {
+                write!(f, "Game Genie codes have 6 or 8 letters, not {}", length)
+            }
+            CheatError::InvalidLetter(letter) => {
+                write!(f, "{} is not a Game Genie letter", letter)
+            }
+            CheatError::InvalidRawCode => write!(f, "raw codes look like AAAA:VV"),
+        }
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+/// Makes CPU reads of `address` return `value` instead of what's there,
+/// but only when what's there is `compare`, if there is one.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+}
+
+impl Cheat {
+    /// Parses a 6 or 8 letter Game Genie code, or a raw `AAAA:VV` code
+    /// with the address and value in hex, which pins a byte of RAM.
+    pub fn parse(code: &str) -> Result<Self, CheatError> {
+        let code = code.trim();
+
+        match code.split_once(':') {
+            Some((address, value)) => Self::parse_raw(address, value),
+            None => Self::parse_game_genie(code),
+        }
+    }
+
+    fn parse_raw(address: &str, value: &str) -> Result<Self, CheatError> {
+        match (
+            u16::from_str_radix(address, 16),
+            u8::from_str_radix(value, 16),
+        ) {
+            (Ok(address), Ok(value)) => Ok(Cheat {
+                address,
+                value,
+                compare: None,
+            }),
+            _ => Err(CheatError::InvalidRawCode),
+        }
+    }
+
+    fn parse_game_genie(code: &str) -> Result<Self, CheatError> {
+        let n = code
+            .chars()
+            .map(|letter| {
+                GAME_GENIE_LETTERS
+                    .iter()
+                    .position(|&l| l as char == letter.to_ascii_uppercase())
+                    .map(|nibble| nibble as u16)
+                    .ok_or(CheatError::InvalidLetter(letter))
+            })
+            .collect::<Result<Vec<u16>, CheatError>>()?;
+
+        if n.len() != 6 && n.len() != 8 {
+            return Err(CheatError::InvalidLength(n.len()));
+        }
+
+        // Each letter's high bit belongs to a different field than its
+        // other three, so the fields are spread across the letters.
+        let address = 0x8000
+            | ((n[3] & 7) << 12)
+            | ((n[5] & 7) << 8)
+            | ((n[4] & 8) << 8)
+            | ((n[2] & 7) << 4)
+            | ((n[1] & 8) << 4)
+            | (n[4] & 7)
+            | (n[3] & 8);
+        let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+
+        if n.len() == 6 {
+            Ok(Cheat {
+                address,
+                value: (value | (n[5] & 8)) as u8,
+                compare: None,
+            })
+        } else {
+            let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+            Ok(Cheat {
+                address,
+                value: (value | (n[7] & 8)) as u8,
+                compare: Some(compare as u8),
+            })
+        }
+    }
+
+    /// What a read of `address` returns with the cheat on, given that
+    /// `data` is what it would have returned without it.
+    pub fn apply(&self, address: u16, data: u8) -> u8 {
+        if address != self.address {
+            return data;
+        }
+
+        match self.compare {
+            Some(compare) if compare != data => data,
+            _ => self.value,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cheat, CheatError};
+
+    #[test]
+    fn game_genie() {
+        // Infinite lives in Super Mario Bros.
+        assert_eq!(
+            Cheat::parse("SXIOPO"),
+            Ok(Cheat {
+                address: 0x91D9,
+                value: 0xAD,
+                compare: None,
+            })
+        );
+        assert_eq!(Cheat::parse("sxiopo"), Cheat::parse("SXIOPO"));
+
+        let cheat = Cheat::parse("YEUZUGAA").expect("valid code");
+        assert_eq!(cheat.address, 0xACB3);
+        assert_eq!(cheat.value, 0x07);
+        assert_eq!(cheat.compare, Some(0x00));
+
+        assert_eq!(Cheat::parse("SXIOP"), Err(CheatError::InvalidLength(5)));
+        assert_eq!(Cheat::parse("SXIOPB"), Err(CheatError::InvalidLetter('B')));
+    }
+
+    #[test]
+    fn raw() {
+        assert_eq!(
+            Cheat::parse("0075:09"),
+            Ok(Cheat {
+                address: 0x0075,
+                value: 0x09,
+                compare: None,
+            })
+        );
+        assert_eq!(Cheat::parse("75:XY"), Err(CheatError::InvalidRawCode));
+    }
+
+    #[test]
+    fn apply() {
+        let cheat = Cheat::parse("SXIOPO").unwrap();
+        assert_eq!(cheat.apply(0x91D9, 0x12), 0xAD);
+        assert_eq!(cheat.apply(0x91DA, 0x12), 0x12, "other addresses untouched");
+
+        let cheat = Cheat::parse("YEUZUGAA").unwrap();
+        assert_eq!(cheat.apply(0xACB3, 0x00), 0x07, "compare matches");
+        assert_eq!(cheat.apply(0xACB3, 0x01), 0x01, "compare doesn't match");
+    }
+}
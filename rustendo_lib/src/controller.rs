@@ -1,6 +1,6 @@
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 #[repr(u8)]
-enum Button {
+pub enum Button {
     A = 0b00000001,
     B = 0b00000010,
     Select = 0b00000100,
@@ -33,9 +33,33 @@ impl std::ops::BitOrAssign<Button> for u8 {
     }
 }
 
+// Reads from $4016 and $4017 only drive the low bits of the data bus. The
+// rest are left over from the high byte of the address, $40.
+pub(crate) const OPEN_BUS: u8 = 0x40;
+
+/// What's plugged into the second controller port.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Port2 {
+    Controller,
+    Zapper,
+}
+
+#[derive(Clone)]
 pub struct Controller {
     controller: u8,
     latched_controller: u8,
+    turbo: u8,
+    turbo_rate: u8,
+    turbo_ticks: u8,
+    turbo_pressed: bool,
+    strobe: bool,
+    forced: Option<u8>,
+}
+
+impl Default for Controller {
+    fn default() -> Self {
+        Controller::new()
+    }
 }
 
 impl Controller {
@@ -43,11 +67,89 @@ impl Controller {
         Controller {
             controller: 0,
             latched_controller: 0,
+            turbo: 0,
+            turbo_rate: 1,
+            turbo_ticks: 0,
+            turbo_pressed: false,
+            strobe: false,
+            forced: None,
         }
     }
 
     pub fn latch(&mut self) {
-        self.latched_controller = self.controller;
+        self.latched_controller = self.buttons();
+    }
+
+    /// Handles a write to $4016. While bit 0 is set the buttons are
+    /// reloaded continuously, so every read returns A. Clearing it keeps
+    /// the last reload to be read out one button at a time.
+    pub fn write_strobe(&mut self, data: u8) {
+        let strobe = data & 0x01 == 0x01;
+
+        if strobe || self.strobe {
+            self.latch();
+        }
+
+        self.strobe = strobe;
+    }
+
+    /// The buttons the console sees held, as `Button` bits.
+    pub fn buttons(&self) -> u8 {
+        if let Some(buttons) = self.forced {
+            return buttons;
+        }
+
+        if self.turbo_pressed {
+            self.controller | self.turbo
+        } else {
+            self.controller
+        }
+    }
+
+    /// Makes the console see `buttons` held whatever is pressed, as when
+    /// playing back a movie, until it's given `None`.
+    pub fn force_buttons(&mut self, buttons: Option<u8>) {
+        self.forced = buttons;
+    }
+
+    pub fn set_turbo_a(&mut self, enabled: bool) {
+        self.set_turbo(Button::A, enabled);
+    }
+
+    pub fn set_turbo_b(&mut self, enabled: bool) {
+        self.set_turbo(Button::B, enabled);
+    }
+
+    fn set_turbo(&mut self, button: Button, enabled: bool) {
+        if enabled {
+            self.turbo |= button;
+        } else {
+            self.turbo &= !button;
+        }
+    }
+
+    /// Sets how many calls to `tick_turbo` pass between each toggle
+    /// of the turbo buttons.
+    pub fn set_turbo_rate(&mut self, ticks: u8) {
+        self.turbo_rate = ticks.max(1);
+    }
+
+    /// Advances the turbo buttons. Should be called once per frame.
+    pub fn tick_turbo(&mut self) {
+        self.turbo_ticks += 1;
+
+        if self.turbo_ticks >= self.turbo_rate {
+            self.turbo_ticks = 0;
+            self.turbo_pressed = !self.turbo_pressed;
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.controller |= button;
+    }
+
+    pub fn lift(&mut self, button: Button) {
+        self.controller &= !button;
     }
 
     pub fn press_a(&mut self) {
@@ -114,9 +216,242 @@ impl Controller {
         self.controller &= !Button::Right;
     }
 
+    /// The value read from $4016: the next button in bit 0 and open bus
+    /// above it.
+    pub(crate) fn read(&mut self) -> u8 {
+        OPEN_BUS | self.read_button()
+    }
+
+    /// Returns what `read` would, without shifting.
+    pub(crate) fn peek(&self) -> u8 {
+        OPEN_BUS | self.peek_button()
+    }
+
+    /// Returns the bit the next `read_button` will, without shifting.
+    pub fn peek_button(&self) -> u8 {
+        if self.strobe {
+            return self.buttons() & 0x01;
+        }
+
+        self.latched_controller & 0x01
+    }
+
     pub fn read_button(&mut self) -> u8 {
+        if self.strobe {
+            self.latch();
+            return self.latched_controller & 0x01;
+        }
+
         let bit = self.latched_controller & 0x01 == 0x01;
         self.latched_controller >>= 1;
         bit as u8
     }
 }
+
+// What the Four Score sends on each port after the two controllers, most
+// significant bit first.
+const FOUR_SCORE_SIGNATURES: [u8; 2] = [0x10, 0x20];
+
+/// The Four Score adapter, which reads out four controllers through the two
+/// ports: players 1 and 3 then a signature on $4016, and players 2 and 4
+/// then another signature on $4017.
+#[derive(Clone)]
+pub(crate) struct FourScore {
+    reads: [u8; 2],
+    strobe: bool,
+}
+
+impl FourScore {
+    pub fn new() -> Self {
+        FourScore {
+            reads: [0, 0],
+            strobe: false,
+        }
+    }
+
+    /// Handles a write to $4016, which the controllers see too.
+    pub fn write_strobe(&mut self, data: u8) {
+        let strobe = data & 0x01 == 0x01;
+
+        if strobe || self.strobe {
+            self.reads = [0, 0];
+        }
+
+        self.strobe = strobe;
+    }
+
+    /// The value read from `port`, 0 for $4016 or 1 for $4017.
+    pub fn read(&mut self, port: usize, controllers: &mut [Controller; 4]) -> u8 {
+        let reads = self.reads[port];
+        let bit = match reads {
+            0..=7 => controllers[port].read_button(),
+            8..=15 => controllers[port + 2].read_button(),
+            16..=23 => FOUR_SCORE_SIGNATURES[port] >> (23 - reads) & 0x01,
+            _ => 0,
+        };
+
+        // While strobing, the controllers keep returning A.
+        if !self.strobe {
+            self.reads[port] = reads.saturating_add(1);
+        }
+
+        OPEN_BUS | bit
+    }
+
+    /// Returns what `read` would, without advancing.
+    pub fn peek(&self, port: usize, controllers: &[Controller; 4]) -> u8 {
+        let reads = self.reads[port];
+        let bit = match reads {
+            0..=7 => controllers[port].peek_button(),
+            8..=15 => controllers[port + 2].peek_button(),
+            16..=23 => FOUR_SCORE_SIGNATURES[port] >> (23 - reads) & 0x01,
+            _ => 0,
+        };
+
+        OPEN_BUS | bit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Button, Controller, FourScore};
+
+    fn read_a(controller: &mut Controller) -> u8 {
+        controller.latch();
+        controller.read_button()
+    }
+
+    #[test]
+    fn strobe() {
+        let mut controller = Controller::new();
+        controller.press_a();
+        controller.press_start();
+
+        controller.write_strobe(1);
+        let held: Vec<u8> = (0..4).map(|_| controller.read_button()).collect();
+        assert_eq!(held, vec![1, 1, 1, 1], "A over and over while strobing");
+
+        controller.lift_a();
+        assert_eq!(controller.read_button(), 0, "reloaded on every read");
+        controller.press_a();
+
+        controller.write_strobe(0);
+        let serial: Vec<u8> = (0..8).map(|_| controller.read_button()).collect();
+        assert_eq!(serial, vec![1, 0, 0, 1, 0, 0, 0, 0], "A, then Start fourth");
+
+        controller.write_strobe(0);
+        assert_eq!(controller.read_button(), 0, "no reload without strobing");
+    }
+
+    #[test]
+    fn serial_order() {
+        let mut controller = Controller::new();
+        controller.press(Button::Right);
+        controller.latch();
+
+        let serial: Vec<u8> = (0..8).map(|_| controller.read_button()).collect();
+        assert_eq!(serial, vec![0, 0, 0, 0, 0, 0, 0, 1], "Right read last");
+
+        let order = [
+            Button::A,
+            Button::B,
+            Button::Select,
+            Button::Start,
+            Button::Up,
+            Button::Down,
+            Button::Left,
+            Button::Right,
+        ];
+        for (position, &button) in order.iter().enumerate() {
+            let mut controller = Controller::new();
+            controller.press(button);
+            controller.latch();
+
+            let serial: Vec<u8> = (0..8).map(|_| controller.read_button()).collect();
+            let expected: Vec<u8> = (0..8).map(|i| (i == position) as u8).collect();
+            assert_eq!(serial, expected, "{:?} read {}", button, position + 1);
+        }
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut controller = Controller::new();
+        controller.press_b();
+        controller.latch();
+
+        assert_eq!(controller.peek(), 0x40);
+        assert_eq!(controller.read(), 0x40, "A released");
+        assert_eq!(controller.read(), 0x41, "B pressed");
+        assert_eq!(controller.read(), 0x40, "Select released");
+    }
+
+    #[test]
+    fn four_score() {
+        let mut controllers = [
+            Controller::new(),
+            Controller::new(),
+            Controller::new(),
+            Controller::new(),
+        ];
+        controllers[0].press(Button::A);
+        controllers[1].press(Button::B);
+        controllers[2].press(Button::Start);
+        controllers[3].press(Button::Right);
+
+        let mut four_score = FourScore::new();
+        for data in &[1, 0] {
+            four_score.write_strobe(*data);
+            for controller in controllers.iter_mut() {
+                controller.write_strobe(*data);
+            }
+        }
+
+        assert_eq!(four_score.peek(0, &controllers), 0x41, "peek player 1's A");
+
+        let mut read_port = |port: usize| -> Vec<u8> {
+            (0..24)
+                .map(|_| four_score.read(port, &mut controllers) & 0x01)
+                .collect()
+        };
+
+        let port_1 = read_port(0);
+        assert_eq!(&port_1[..8], &[1, 0, 0, 0, 0, 0, 0, 0], "player 1");
+        assert_eq!(&port_1[8..16], &[0, 0, 0, 1, 0, 0, 0, 0], "player 3");
+        assert_eq!(&port_1[16..], &[0, 0, 0, 1, 0, 0, 0, 0], "signature");
+
+        let port_2 = read_port(1);
+        assert_eq!(&port_2[..8], &[0, 1, 0, 0, 0, 0, 0, 0], "player 2");
+        assert_eq!(&port_2[8..16], &[0, 0, 0, 0, 0, 0, 0, 1], "player 4");
+        assert_eq!(&port_2[16..], &[0, 0, 1, 0, 0, 0, 0, 0], "signature");
+    }
+
+    #[test]
+    fn turbo() {
+        let mut controller = Controller::new();
+        controller.set_turbo_a(true);
+
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 1, "A pressed");
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 0, "A released");
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 1, "A pressed again");
+
+        controller.set_turbo_rate(2);
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 1, "A still pressed");
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 0, "A released after two ticks");
+
+        // A normal press holds A down regardless of turbo.
+        controller.set_turbo_a(false);
+        controller.press_a();
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 1, "A held");
+        controller.tick_turbo();
+        controller.tick_turbo();
+        assert_eq!(read_a(&mut controller), 1, "A still held");
+        controller.lift_a();
+        assert_eq!(read_a(&mut controller), 0, "A lifted");
+    }
+}
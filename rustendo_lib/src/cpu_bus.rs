@@ -1,16 +1,68 @@
 use crate::cartridge::Cartridge;
-use crate::controller::Controller;
-#[cfg(not(test))]
+use crate::cheats::Cheat;
+use crate::controller::{Controller, FourScore, Port2};
 use crate::cpu_ram::Ram;
-use crate::ricoh2c02::Ricoh2c02;
+use crate::ricoh2c02::{PpuMemory, Ricoh2c02};
+use crate::zapper::Zapper;
 
+/// The CPU's RAM along with the PPU's memory, moved out of the bus so the
+/// rest of the machine can be copied cheaply.
+pub struct BusMemory {
+    #[cfg(not(test))]
+    ram: Ram,
+    #[cfg(test)]
+    test_ram: Vec<u8>,
+    // Only moved out so it isn't copied. It isn't saved.
+    #[cfg(test)]
+    test_writes: Vec<(u16, u8)>,
+    ppu: PpuMemory,
+}
+
+impl BusMemory {
+    /// Appends the CPU's RAM and then the PPU's memory to `image`.
+    pub fn save(&self, image: &mut Vec<u8>) {
+        #[cfg(not(test))]
+        image.extend_from_slice(self.ram.as_bytes());
+        #[cfg(test)]
+        image.extend_from_slice(&self.test_ram);
+        self.ppu.save(image);
+    }
+
+    /// Reads back what `save` wrote.
+    #[cfg(not(test))]
+    pub fn load(image: &[u8]) -> Self {
+        let (ram, mut rest) = image.split_at(0x800);
+        BusMemory {
+            ram: Ram::from_bytes(ram),
+            ppu: PpuMemory::load(&mut rest),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn load(image: &[u8]) -> Self {
+        let (ram, mut rest) = image.split_at(0x10000);
+        BusMemory {
+            test_ram: ram.to_vec(),
+            test_writes: vec![],
+            ppu: PpuMemory::load(&mut rest),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Bus {
     #[cfg(not(test))]
     ram: Ram,
     ppu: Ricoh2c02,
-    controller: Controller,
+    controllers: [Controller; 4],
+    four_score: Option<FourScore>,
+    port2: Port2,
+    zapper: Zapper,
+    cheats: Vec<Cheat>,
     #[cfg(test)]
     test_ram: Vec<u8>,
+    #[cfg(test)]
+    test_writes: Vec<(u16, u8)>,
     dma_transfer: Option<u8>,
 }
 
@@ -20,7 +72,11 @@ impl Bus {
         Bus {
             ram: Ram::new(),
             ppu: Ricoh2c02::new(),
-            controller: Controller::new(),
+            controllers: Self::new_controllers(),
+            four_score: None,
+            port2: Port2::Controller,
+            zapper: Zapper::new(),
+            cheats: vec![],
             dma_transfer: None,
         }
     }
@@ -29,16 +85,47 @@ impl Bus {
     pub fn new() -> Self {
         Bus {
             ppu: Ricoh2c02::new(),
-            controller: Controller::new(),
+            controllers: Self::new_controllers(),
+            four_score: None,
+            port2: Port2::Controller,
+            zapper: Zapper::new(),
+            cheats: vec![],
             dma_transfer: None,
             test_ram: vec![0; 0x10000],
+            test_writes: vec![],
         }
     }
 
+    fn new_controllers() -> [Controller; 4] {
+        [
+            Controller::new(),
+            Controller::new(),
+            Controller::new(),
+            Controller::new(),
+        ]
+    }
+
     pub fn load_cartridge(&mut self, cartridge: Cartridge) {
         self.ppu.load_cartridge(cartridge);
     }
 
+    pub fn unload_cartridge(&mut self) -> Option<Cartridge> {
+        self.ppu.unload_cartridge()
+    }
+
+    /// Replaces the 2K of internal RAM, e.g. with a power-on pattern.
+    #[cfg(not(test))]
+    pub fn set_ram(&mut self, ram: Ram) {
+        self.ram = ram;
+    }
+
+    #[cfg(test)]
+    pub fn set_ram(&mut self, ram: Ram) {
+        for address in 0..0x800 {
+            self.test_ram[address as usize] = ram.read(address);
+        }
+    }
+
     pub fn ppu_clock(&mut self, nmi_enable: &mut bool) -> bool {
         self.ppu.clock(nmi_enable)
     }
@@ -51,8 +138,72 @@ impl Bus {
         &mut self.ppu
     }
 
-    pub fn controller(&mut self) -> &mut Controller {
-        &mut self.controller
+    /// Player `player`'s controller, counting from 0. The third and fourth
+    /// are only read through the Four Score.
+    pub fn controller(&mut self, player: usize) -> &mut Controller {
+        &mut self.controllers[player]
+    }
+
+    /// Plugs in or removes the Four Score. While it's in, $4017 reads the
+    /// second and fourth controllers, whatever is plugged into port 2.
+    pub fn set_four_score(&mut self, enabled: bool) {
+        self.four_score = if enabled {
+            Some(FourScore::new())
+        } else {
+            None
+        };
+    }
+
+    pub fn set_port2_device(&mut self, device: Port2) {
+        self.port2 = device;
+    }
+
+    pub fn zapper(&mut self) -> &mut Zapper {
+        &mut self.zapper
+    }
+
+    /// Reads controller port `port`, 0 for $4016 or 1 for $4017.
+    fn read_port(&mut self, port: usize) -> u8 {
+        match &mut self.four_score {
+            Some(four_score) => four_score.read(port, &mut self.controllers),
+            None if port == 0 => self.controllers[0].read(),
+            None => match self.port2 {
+                Port2::Controller => self.controllers[1].read(),
+                Port2::Zapper => self.zapper.read(&self.ppu),
+            },
+        }
+    }
+
+    fn peek_port(&self, port: usize) -> u8 {
+        match &self.four_score {
+            Some(four_score) => four_score.peek(port, &self.controllers),
+            None if port == 0 => self.controllers[0].peek(),
+            None => match self.port2 {
+                Port2::Controller => self.controllers[1].peek(),
+                Port2::Zapper => self.zapper.read(&self.ppu),
+            },
+        }
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.push(cheat);
+    }
+
+    /// Turns off every copy of `cheat`, returning whether it was on.
+    pub fn remove_cheat(&mut self, cheat: Cheat) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|&c| c != cheat);
+        self.cheats.len() != count
+    }
+
+    /// Lets the cartridge count a CPU cycle.
+    pub fn clock_cartridge(&mut self) {
+        self.ppu.cartridge_cpu_clock();
+    }
+
+    /// Whether anything on the bus is asserting IRQ.
+    pub fn irq_pending(&self) -> bool {
+        self.ppu.cartridge_irq_pending()
     }
 
     pub fn get_dma_transfer(&self) -> Option<u8> {
@@ -63,35 +214,78 @@ impl Bus {
         self.dma_transfer = None;
     }
 
+    /// Reads a byte as the CPU would. Only the PPU registers and the
+    /// controller have side effects; everything else is a `cpu_peek`.
+    /// Cheats replace what's read.
     #[cfg(not(test))]
     pub fn cpu_read(&mut self, address: u16) -> u8 {
+        let data = match address {
+            0x2000..=0x3FFF => self.ppu.cpu_read(address & 0x2007),
+            0x4016 | 0x4017 => self.read_port((address - 0x4016) as usize),
+            _ => self.cpu_peek(address),
+        };
+        self.apply_cheats(address, data)
+    }
+
+    #[cfg(test)]
+    pub fn cpu_read(&mut self, address: u16) -> u8 {
+        self.apply_cheats(address, self.test_ram[address as usize])
+    }
+
+    fn apply_cheats(&self, address: u16, data: u8) -> u8 {
+        self.cheats
+            .iter()
+            .fold(data, |data, cheat| cheat.apply(address, data))
+    }
+
+    /// Reads a byte without any of the side effects a real read would have,
+    /// such as clearing the vertical blank flag or shifting the controller.
+    #[cfg(not(test))]
+    pub fn cpu_peek(&self, address: u16) -> u8 {
         match address {
             0x0..=0x1FFF => self.ram.read(address),
-            0x2000..=0x3FFF => self.ppu.cpu_read(address & 0x2007),
-            0x4016 => self.controller.read_button(),
-            0x4020..=0xFFFF => {
-                if self.ppu.has_cartridge() {
-                    self.ppu.cartridge_cpu_read(address)
-                } else {
-                    0
-                }
-            }
+            0x2000..=0x3FFF => self.ppu.cpu_peek(address & 0x2007),
+            0x4016 | 0x4017 => self.peek_port((address - 0x4016) as usize),
+            0x4020..=0xFFFF if self.ppu.has_cartridge() => self.ppu.cartridge_cpu_read(address),
             _ => 0,
         }
     }
 
     #[cfg(test)]
-    pub fn cpu_read(&mut self, address: u16) -> u8 {
+    pub fn cpu_peek(&self, address: u16) -> u8 {
         self.test_ram[address as usize]
     }
 
+    /// Writes a byte to RAM or cartridge RAM without any side effects.
+    /// Writes anywhere else, where they would hit a register, are dropped.
+    #[cfg(not(test))]
+    pub fn poke(&mut self, address: u16, data: u8) {
+        match address {
+            0x0..=0x1FFF => self.ram.write(address, data),
+            0x6000..=0x7FFF => self.ppu.cartridge_cpu_write(address, data),
+            _ => (),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn poke(&mut self, address: u16, data: u8) {
+        self.test_ram[address as usize] = data;
+    }
+
     #[cfg(not(test))]
     pub fn cpu_write(&mut self, address: u16, data: u8) {
         match address {
             0x0000..=0x1FFF => self.ram.write(address, data),
             0x2000..=0x3FFF => self.ppu.cpu_write(address & 0x2007, data),
             0x4014 => self.dma_transfer = Some(data),
-            0x4016 => self.controller.latch(),
+            0x4016 => {
+                for controller in self.controllers.iter_mut() {
+                    controller.write_strobe(data);
+                }
+                if let Some(four_score) = &mut self.four_score {
+                    four_score.write_strobe(data);
+                }
+            }
             0x4020..=0xFFFF => {
                 if self.ppu.has_cartridge() {
                     self.ppu.cartridge_cpu_write(address, data)
@@ -105,6 +299,71 @@ impl Bus {
 
     #[cfg(test)]
     pub fn cpu_write(&mut self, address: u16, data: u8) {
+        if address == 0x4014 {
+            self.dma_transfer = Some(data);
+        }
         self.test_ram[address as usize] = data;
+        self.test_writes.push((address, data));
+    }
+
+    /// Every CPU write since the last call, so tests can count them.
+    #[cfg(test)]
+    pub fn take_writes(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.test_writes)
+    }
+
+    /// Moves out RAM and the PPU's memory, leaving them empty until
+    /// `put_memory` puts them back.
+    #[cfg(not(test))]
+    pub fn take_memory(&mut self) -> BusMemory {
+        BusMemory {
+            ram: std::mem::take(&mut self.ram),
+            ppu: self.ppu.take_memory(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn take_memory(&mut self) -> BusMemory {
+        BusMemory {
+            test_ram: std::mem::take(&mut self.test_ram),
+            test_writes: std::mem::take(&mut self.test_writes),
+            ppu: self.ppu.take_memory(),
+        }
+    }
+
+    #[cfg(not(test))]
+    pub fn put_memory(&mut self, memory: BusMemory) {
+        self.ram = memory.ram;
+        self.ppu.put_memory(memory.ppu);
+    }
+
+    #[cfg(test)]
+    pub fn put_memory(&mut self, memory: BusMemory) {
+        self.test_ram = memory.test_ram;
+        self.test_writes = memory.test_writes;
+        self.ppu.put_memory(memory.ppu);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bus;
+    use crate::controller::{Button, Port2};
+
+    #[test]
+    fn port2_device() {
+        let mut bus = Bus::new();
+        bus.controller(1).press(Button::A);
+        bus.controller(1).press(Button::Start);
+        bus.controller(1).write_strobe(0x01);
+        bus.controller(1).write_strobe(0x00);
+
+        // A, B, Select, Start, Up, Down, Left, Right.
+        let bits: Vec<u8> = (0..8).map(|_| bus.read_port(1) & 0x01).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 0], "standard controller");
+
+        bus.set_port2_device(Port2::Zapper);
+        assert_eq!(bus.read_port(1), bus.zapper.read(&bus.ppu), "Zapper");
+        assert_eq!(bus.peek_port(1) & 0x08, 0x08, "no light sensed");
     }
 }
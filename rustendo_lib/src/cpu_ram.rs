@@ -1,3 +1,4 @@
+#[derive(Clone, Default)]
 pub struct Ram {
     ram: Vec<u8>,
 }
@@ -7,6 +8,30 @@ impl Ram {
         Ram { ram: vec![0; 0x800] }
     }
 
+    /// RAM whose power-on contents repeat `pattern` across all 2K, as some
+    /// consoles do. An empty pattern leaves it zeroed.
+    pub fn new_with_pattern(pattern: &[u8]) -> Self {
+        if pattern.is_empty() {
+            return Ram::new();
+        }
+
+        Ram {
+            ram: pattern.iter().copied().cycle().take(0x800).collect(),
+        }
+    }
+
+    /// All 2K, in address order.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// RAM holding `bytes`, which must be 2K, as from `as_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Ram {
+            ram: bytes.to_vec(),
+        }
+    }
+
     fn find_address(&self, address: u16) -> usize {
         (address as usize) & 0x7FF
     }
@@ -32,4 +57,20 @@ mod tests {
         ram.read(0);
         ram.write(0, 0);
     }
+
+    #[test]
+    fn pattern_is_tiled() {
+        let ram = Ram::new_with_pattern(&[0x00, 0x00, 0xFF, 0xFF, 0x12]);
+
+        for address in 0..0x800 {
+            let expected = [0x00, 0x00, 0xFF, 0xFF, 0x12][address as usize % 5];
+            assert_eq!(ram.read(address), expected, "address {:#06x}", address);
+        }
+        // The mirrors see the same pattern.
+        assert_eq!(ram.read(0x0802), 0xFF);
+        assert_eq!(ram.read(0x1FFF), ram.read(0x07FF));
+
+        let ram = Ram::new_with_pattern(&[]);
+        assert!((0..0x800).all(|address| ram.read(address) == 0));
+    }
 }
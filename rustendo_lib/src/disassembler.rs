@@ -0,0 +1,142 @@
+use crate::mos6502::{AddressingMode, Instruction};
+use crate::opcodes::opcode_info;
+
+/// Decodes `bytes` as code loaded at `base`, returning the address and text
+/// of each instruction, e.g. `(0x8000, "$8000: LDA #$42")`.
+///
+/// Opcodes that jam the CPU and instructions cut off by the end of `bytes`
+/// are emitted one byte at a time as `.DB` lines. Undocumented opcodes
+/// that do run, like the extra NOPs, are disassembled and marked illegal.
+pub fn disassemble(bytes: &[u8], base: u16) -> Vec<(u16, String)> {
+    let mut lines = vec![];
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u16);
+        let opcode = bytes[offset];
+        let instruction = Instruction::decode(opcode);
+
+        let text = match instruction.operands() {
+            Some((mode, length)) if offset + length as usize <= bytes.len() => {
+                let operand = &bytes[offset + 1..offset + length as usize];
+                offset += length as usize;
+                let text = format_instruction(&instruction, mode, operand, address);
+                match opcode_info(opcode) {
+                    Some(info) if !info.documented => format!("{} ; illegal opcode", text),
+                    _ => text,
+                }
+            }
+            Some(_) => {
+                offset += 1;
+                format!(".DB ${:02X} ; truncated {}", opcode, instruction)
+            }
+            None => {
+                offset += 1;
+                format!(".DB ${:02X} ; illegal opcode", opcode)
+            }
+        };
+
+        lines.push((address, format!("${:04X}: {}", address, text)));
+    }
+
+    lines
+}
+
+fn format_instruction(
+    instruction: &Instruction,
+    mode: AddressingMode,
+    operand: &[u8],
+    address: u16,
+) -> String {
+    let byte = || operand[0];
+    let word = || u16::from_le_bytes([operand[0], operand[1]]);
+
+    let operand = match mode {
+        AddressingMode::Implied => return instruction.to_string(),
+        AddressingMode::Accumulator => String::from("A"),
+        AddressingMode::Immediate => format!("#${:02X}", byte()),
+        AddressingMode::ZeroPage => format!("${:02X}", byte()),
+        AddressingMode::ZeroPageX => format!("${:02X},X", byte()),
+        AddressingMode::ZeroPageY => format!("${:02X},Y", byte()),
+        AddressingMode::Absolute => format!("${:04X}", word()),
+        AddressingMode::AbsoluteX => format!("${:04X},X", word()),
+        AddressingMode::AbsoluteY => format!("${:04X},Y", word()),
+        AddressingMode::Indirect => format!("(${:04X})", word()),
+        AddressingMode::IndirectX => format!("(${:02X},X)", byte()),
+        AddressingMode::IndirectY => format!("(${:02X}),Y", byte()),
+        AddressingMode::Relative => {
+            // Branches are relative to the address of the next instruction.
+            let target = address.wrapping_add(2).wrapping_add(byte() as i8 as u16);
+            format!("${:04X}", target)
+        }
+    };
+
+    format!("{} {}", instruction, operand)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::disassemble;
+
+    fn disassemble_one(bytes: &[u8]) -> String {
+        let lines = disassemble(bytes, 0x8000);
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        assert_eq!(lines[0].0, 0x8000);
+        lines[0].1.clone()
+    }
+
+    #[test]
+    fn addressing_modes() {
+        assert_eq!(disassemble_one(&[0xEA]), "$8000: NOP");
+        assert_eq!(disassemble_one(&[0x0A]), "$8000: ASL A");
+        assert_eq!(disassemble_one(&[0xA9, 0x42]), "$8000: LDA #$42");
+        assert_eq!(disassemble_one(&[0xA5, 0x42]), "$8000: LDA $42");
+        assert_eq!(disassemble_one(&[0xB5, 0x42]), "$8000: LDA $42,X");
+        assert_eq!(disassemble_one(&[0xB6, 0x42]), "$8000: LDX $42,Y");
+        assert_eq!(disassemble_one(&[0xAD, 0x34, 0x12]), "$8000: LDA $1234");
+        assert_eq!(disassemble_one(&[0xBD, 0x34, 0x12]), "$8000: LDA $1234,X");
+        assert_eq!(disassemble_one(&[0xB9, 0x34, 0x12]), "$8000: LDA $1234,Y");
+        assert_eq!(disassemble_one(&[0x6C, 0x34, 0x12]), "$8000: JMP ($1234)");
+        assert_eq!(disassemble_one(&[0xA1, 0x42]), "$8000: LDA ($42,X)");
+        assert_eq!(disassemble_one(&[0xB1, 0x42]), "$8000: LDA ($42),Y");
+        assert_eq!(disassemble_one(&[0xD0, 0x10]), "$8000: BNE $8012");
+        assert_eq!(disassemble_one(&[0xD0, 0xFE]), "$8000: BNE $8000");
+    }
+
+    #[test]
+    fn unofficial_nops() {
+        assert_eq!(disassemble_one(&[0x1A]), "$8000: NOP ; illegal opcode");
+        assert_eq!(
+            disassemble_one(&[0x04, 0x42]),
+            "$8000: NOP $42 ; illegal opcode"
+        );
+        assert_eq!(
+            disassemble_one(&[0x1C, 0x34, 0x12]),
+            "$8000: NOP $1234,X ; illegal opcode"
+        );
+        assert_eq!(
+            disassemble_one(&[0x80, 0x42]),
+            "$8000: NOP #$42 ; illegal opcode"
+        );
+    }
+
+    #[test]
+    fn instruction_lengths() {
+        let lines = disassemble(&[0xA9, 0x01, 0x8D, 0x00, 0x02, 0xE8, 0x02, 0x4C], 0xC000);
+        let lines: Vec<(u16, &str)> = lines
+            .iter()
+            .map(|(address, text)| (*address, text.as_str()))
+            .collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                (0xC000, "$C000: LDA #$01"),
+                (0xC002, "$C002: STA $0200"),
+                (0xC005, "$C005: INX"),
+                (0xC006, "$C006: .DB $02 ; illegal opcode"),
+                (0xC007, "$C007: .DB $4C ; truncated JMP"),
+            ]
+        );
+    }
+}
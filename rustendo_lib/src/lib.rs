@@ -14,6 +14,7 @@ macro_rules! log {
 
 macro_rules! bitfield {
     ($s:ident, $t:ty, $u:ty) => {
+        #[derive(Clone)]
         struct $s {
             register: $u,
         }
@@ -58,13 +59,21 @@ macro_rules! bitfield {
     };
 }
 
-mod assembler;
+#[cfg(feature = "assembler")]
+pub mod assembler;
 pub mod cartridge;
-mod controller;
+pub mod cheats;
+pub mod controller;
 mod cpu_bus;
 mod cpu_ram;
+pub mod disassembler;
 mod mappers;
 mod mos6502;
 pub mod nes;
+pub mod nsf;
+pub mod opcodes;
+pub mod palette;
+pub mod png;
 mod ppu_ram;
 mod ricoh2c02;
+pub mod zapper;
@@ -1,10 +1,12 @@
 use super::Mapper;
 use crate::cartridge::MirroringType;
 
+#[derive(Clone)]
 pub struct Mapper000 {
     prg_rom_size: usize,
     chr_ram: Vec<u8>,
-    prg_ram: [u8; 0x1FFF],
+    prg_ram: [u8; 0x2000],
+    sram_dirty: bool,
 }
 
 impl Mapper000 {
@@ -12,7 +14,8 @@ impl Mapper000 {
         Mapper000 {
             prg_rom_size,
             chr_ram: vec![0; chr_ram_size],
-            prg_ram: [0; 0x1FFF],
+            prg_ram: [0; 0x2000],
+            sram_dirty: false,
         }
     }
 }
@@ -39,6 +42,7 @@ impl Mapper for Mapper000 {
         match address {
             0x6000..=0x7FFF => {
                 self.prg_ram[(address & 0x1FFF) as usize] = data;
+                self.sram_dirty = true;
                 None
             }
             _ => None,
@@ -56,16 +60,28 @@ impl Mapper for Mapper000 {
     }
 
     fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize> {
-        match self.chr_ram.len() {
-            0 => None,
-            _ => {
-                self.chr_ram[address as usize] = data;
-                None
-            }
+        match address {
+            // CHR ROM can't be written to, only CHR RAM.
+            0x0000..=0x1FFF => match self.chr_ram.len() {
+                0 => None,
+                _ => {
+                    self.chr_ram[address as usize] = data;
+                    None
+                }
+            },
+            _ => None,
         }
     }
 
     fn mirroring_type(&self) -> Option<MirroringType> {
         None
     }
+
+    fn sram_dirty(&self) -> bool {
+        self.sram_dirty
+    }
+
+    fn clear_sram_dirty(&mut self) {
+        self.sram_dirty = false;
+    }
 }
@@ -9,12 +9,20 @@ enum ControlBits {
 
 bitfield!(Control, ControlBits, u8);
 
+#[derive(Clone)]
 pub struct Mapper001 {
+    prg_rom_size: usize,
     #[allow(dead_code)]
     chr_ram: Vec<u8>,
-    prg_ram: [u8; 0x1FFF],
+    prg_ram: [u8; 0x2000],
+    prg_ram_enabled: bool,
+    sram_dirty: bool,
     shift_register: u8,
     control: Control,
+    prg_bank: u8,
+    // SUROM and SXROM boards have 512 KiB of PRG ROM, and use bit 4 of the
+    // CHR bank 0 register to select which 256 KiB half is in use.
+    prg_page: usize,
     low_prg_space: usize,
     high_prg_space: usize,
     low_chr_space: usize,
@@ -27,58 +35,96 @@ enum Bank {
 }
 
 impl Mapper001 {
-    pub fn new(chr_ram_size: usize) -> Self {
-        Mapper001 {
-            prg_ram: [0; 0x1FFF],
+    pub fn new(prg_rom_size: usize, chr_ram_size: usize) -> Self {
+        let mut mapper = Mapper001 {
+            prg_rom_size,
+            prg_ram: [0; 0x2000],
+            prg_ram_enabled: true,
+            sram_dirty: false,
             chr_ram: vec![0; chr_ram_size],
             shift_register: 0x10,
             control: Control::new(),
+            prg_bank: 0,
+            prg_page: 0,
             high_prg_space: 0,
             low_prg_space: 0,
             high_chr_space: 0,
             low_chr_space: 0,
-        }
+        };
+
+        // Games expect to power up with the last bank fixed at $C000, so
+        // that the reset vector is found before any register is written.
+        *mapper.control = 0x0C;
+        mapper.update_prg_banks();
+        mapper
     }
 
     fn set_register(&mut self, address: u16, data: u8) {
         match (address & 0x6000) >> 13 {
-            0x0 => *self.control = data,
-            0x1 => match self.control.get_field(ControlBits::ChrRomBankMode) {
-                0x0 => {
-                    // The lower bit is unused in 8 KiB mode.
-                    let bank = ((data & 0x1E) >> 1) as usize;
-                    // Each bank is always 0x1000 bytes in size and there are two banks.
-                    // Therefore, in 8 KiB mode, the low CHR bank always starts every 0x2000
-                    // bytes and the high CHR bank starts 0x1000 bytes after that
-                    self.low_chr_space = bank * 0x2000;
-                    self.high_chr_space = bank * 0x2000 + 0x1000;
+            0x0 => {
+                *self.control = data;
+                self.update_prg_banks();
+            }
+            0x1 => {
+                if self.prg_rom_size > 0x40000 {
+                    self.prg_page = if data & 0x10 == 0x10 { 0x40000 } else { 0 };
+                    self.update_prg_banks();
                 }
-                0x1 => self.low_chr_space = ((data & 0x1F) as usize) * 0x1000,
-                _ => unreachable!(),
-            },
+                self.set_low_chr_bank(data);
+            }
             0x2 => match self.control.get_field(ControlBits::ChrRomBankMode) {
                 0x0 => (), // CHR bank 1 is ignored in 8KiB mode
                 0x1 => self.high_chr_space = ((data & 0x1F) as usize) * 0x1000,
                 _ => unreachable!(),
             },
-            0x3 => match self.control.get_field(ControlBits::PrgRomBankMode) {
-                0x0 | 0x1 => {
-                    // The lower bit is unused in 8 KiB mode
-                    let bank = ((data & 0xE) >> 1) as usize;
-                    // Each bank is always 0x4000 bytes in size and there are two banks.
-                    // Therefore, in 32 KiB mode, the low PRG bank always starts every 0x8000
-                    // bytes and the high PRG bank starts 0x4000 bytes after that
-                    self.low_prg_space = bank * 0x8000;
-                    self.high_prg_space = bank * 0x8000 + 0x4000;
-                }
-                0x2 => self.high_prg_space = ((data & 0xF) as usize) * 0x4000,
-                0x3 => self.low_prg_space = ((data & 0xF) as usize) * 0x4000,
-                _ => unreachable!(),
-            },
+            0x3 => {
+                // Bit 4 disables PRG RAM.
+                self.prg_ram_enabled = data & 0x10 == 0x00;
+                self.prg_bank = data & 0x0F;
+                self.update_prg_banks();
+            }
             _ => unreachable!(),
         }
     }
 
+    fn set_low_chr_bank(&mut self, data: u8) {
+        match self.control.get_field(ControlBits::ChrRomBankMode) {
+            0x0 => {
+                // The lower bit is unused in 8 KiB mode.
+                let bank = ((data & 0x1E) >> 1) as usize;
+                // Each bank is always 0x1000 bytes in size and there are two banks.
+                // Therefore, in 8 KiB mode, the low CHR bank always starts every 0x2000
+                // bytes and the high CHR bank starts 0x1000 bytes after that
+                self.low_chr_space = bank * 0x2000;
+                self.high_chr_space = bank * 0x2000 + 0x1000;
+            }
+            0x1 => self.low_chr_space = ((data & 0x1F) as usize) * 0x1000,
+            _ => unreachable!(),
+        }
+    }
+
+    // Recalculates where the PRG banks start from the bank mode, the PRG
+    // bank register and the 256 KiB page.
+    fn update_prg_banks(&mut self) {
+        let bank = self.prg_bank as usize;
+        // The fixed bank is the last one in the current 256 KiB page.
+        let last_bank = self.prg_rom_size.clamp(0x4000, 0x40000) / 0x4000 - 1;
+
+        let (low_bank, high_bank) = match self.control.get_field(ControlBits::PrgRomBankMode) {
+            // The lower bit is unused in 32 KiB mode, and the two 16 KiB banks
+            // are always next to each other.
+            0x0 | 0x1 => (bank & 0xE, (bank & 0xE) + 1),
+            // The first bank is fixed at $8000.
+            0x2 => (0, bank),
+            // The last bank is fixed at $C000.
+            0x3 => (bank, last_bank),
+            _ => unreachable!(),
+        };
+
+        self.low_prg_space = self.prg_page + low_bank * 0x4000;
+        self.high_prg_space = self.prg_page + high_bank * 0x4000;
+    }
+
     fn load_shift_register(&mut self, data: u8) {
         let mut data = data;
         // Make room for the new bit
@@ -112,7 +158,11 @@ impl Mapper for Mapper001 {
         match address {
             // Unused, but in the cartridge's address range
             0x4020..=0x5FFF => (None, None),
-            0x6000..=0x7FFF => (None, Some(self.prg_ram[(address & 0x1FFF) as usize])),
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                (None, Some(self.prg_ram[(address & 0x1FFF) as usize]))
+            }
+            // Open bus while PRG RAM is disabled
+            0x6000..=0x7FFF => (None, None),
             // First bank
             0x8000..=0xBFFF => (Some(self.get_prg_address(Bank::Low, address)), None),
             // Second bank
@@ -125,20 +175,22 @@ impl Mapper for Mapper001 {
             // Unused, but in the cartridge's address range
             0x4020..=0x5FFF => None,
             0x6000..=0x7FFF => {
-                self.prg_ram[(address & 0x1FFF) as usize] = data;
+                if self.prg_ram_enabled {
+                    self.prg_ram[(address & 0x1FFF) as usize] = data;
+                    self.sram_dirty = true;
+                }
                 None
             }
             0x8000..=0xFFFF => {
                 match data & 0x80 {
                     0x00 => {
-                        let data = (data & 0x01) << 7;
-
                         // On the fifth shift, bit 0 of the register will be 1
                         if self.shift_register & 0x01 == 0x01 {
                             // Bit 4 of the shift register is loaded just to set
-                            // the underlying register.
+                            // the underlying register. The five bits written
+                            // are now in bits 7-3.
                             self.load_shift_register(data);
-                            self.set_register(address, self.shift_register);
+                            self.set_register(address, self.shift_register >> 3);
                             // Reset the shift register
                             self.shift_register = 0x10;
                         } else {
@@ -176,4 +228,75 @@ impl Mapper for Mapper001 {
             _ => unreachable!(),
         }
     }
+
+    fn sram_dirty(&self) -> bool {
+        self.sram_dirty
+    }
+
+    fn clear_sram_dirty(&mut self) {
+        self.sram_dirty = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper001;
+    use crate::mappers::Mapper;
+
+    fn write_register(mapper: &mut Mapper001, address: u16, data: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(address, data >> bit & 0x01);
+        }
+    }
+
+    #[test]
+    fn power_up_banks() {
+        let mapper = Mapper001::new(0x20000, 0x2000);
+
+        assert_eq!(mapper.cpu_read(0xFFFC), (Some(0x1FFFC), None), "last bank");
+        assert_eq!(mapper.cpu_read(0x8000), (Some(0x00000), None), "first bank");
+    }
+
+    #[test]
+    fn prg_ram_enable() {
+        let mut mapper = Mapper001::new(0x20000, 0x2000);
+
+        mapper.cpu_write(0x6000, 0x42);
+        mapper.cpu_write(0x7FFF, 0x43);
+        assert_eq!(mapper.cpu_read(0x6000), (None, Some(0x42)), "enabled");
+        assert_eq!(mapper.cpu_read(0x7FFF), (None, Some(0x43)), "enabled");
+
+        write_register(&mut mapper, 0xE000, 0x10);
+        assert_eq!(mapper.cpu_read(0x6000), (None, None), "open bus");
+        mapper.cpu_write(0x6000, 0x99);
+
+        write_register(&mut mapper, 0xE000, 0x00);
+        assert_eq!(
+            mapper.cpu_read(0x6000),
+            (None, Some(0x42)),
+            "write dropped while disabled"
+        );
+    }
+
+    #[test]
+    fn prg_bank_512k() {
+        let mut mapper = Mapper001::new(0x80000, 0x2000);
+        // 16 KiB banks with the last one fixed at $C000, bank 2 at $8000.
+        write_register(&mut mapper, 0x8000, 0x0C);
+        write_register(&mut mapper, 0xE000, 0x02);
+
+        assert_eq!(mapper.cpu_read(0x8000), (Some(0x08000), None));
+        assert_eq!(mapper.cpu_read(0xC000), (Some(0x3C000), None));
+
+        // Switch to the second 256 KiB page.
+        write_register(&mut mapper, 0xA000, 0x10);
+        assert_eq!(mapper.cpu_read(0x8000), (Some(0x48000), None));
+        assert_eq!(mapper.cpu_read(0xFFFF), (Some(0x7FFFF), None));
+
+        // Smaller boards use the bit for CHR instead.
+        let mut mapper = Mapper001::new(0x40000, 0x2000);
+        write_register(&mut mapper, 0x8000, 0x0C);
+        write_register(&mut mapper, 0xA000, 0x10);
+        assert_eq!(mapper.cpu_read(0xC000), (Some(0x3C000), None));
+    }
 }
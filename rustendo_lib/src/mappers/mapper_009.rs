@@ -0,0 +1,187 @@
+use super::Mapper;
+use crate::cartridge::MirroringType;
+
+// The tiles whose fetches flip a pattern table's latch.
+const LATCH_FD: u8 = 0xFD;
+const LATCH_FE: u8 = 0xFE;
+
+/// MMC2, as used by Punch-Out!!. Each 4 KiB pattern table has two CHR banks
+/// and a latch that picks between them, which the PPU flips by fetching
+/// tile $FD or $FE from that table.
+#[derive(Clone)]
+pub struct Mapper009 {
+    prg_rom_size: usize,
+    chr_rom_size: usize,
+    chr_ram: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    sram_dirty: bool,
+    prg_bank: usize,
+    // Indexed by pattern table, then by latch: $FD, then $FE.
+    chr_banks: [[usize; 2]; 2],
+    latches: [u8; 2],
+    mirroring: MirroringType,
+}
+
+impl Mapper009 {
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize, chr_ram_size: usize) -> Self {
+        Mapper009 {
+            prg_rom_size,
+            chr_rom_size,
+            chr_ram: vec![0; chr_ram_size],
+            prg_ram: [0; 0x2000],
+            sram_dirty: false,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [LATCH_FE; 2],
+            mirroring: MirroringType::Vertical,
+        }
+    }
+
+    fn prg_address(&self, bank: usize, address: u16) -> usize {
+        let banks = (self.prg_rom_size / 0x2000).max(1);
+        (bank % banks) * 0x2000 + (address & 0x1FFF) as usize
+    }
+
+    fn chr_bank(&self, table: usize) -> usize {
+        let latch = (self.latches[table] == LATCH_FE) as usize;
+        self.chr_banks[table][latch]
+    }
+}
+
+impl Mapper for Mapper009 {
+    fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x6000..=0x7FFF => (None, Some(self.prg_ram[(address & 0x1FFF) as usize])),
+            0x8000..=0x9FFF => (Some(self.prg_address(self.prg_bank, address)), None),
+            // The last three 8 KiB banks are fixed at $A000.
+            0xA000..=0xFFFF => {
+                let banks = self.prg_rom_size / 0x2000;
+                let bank = (banks + ((address - 0xA000) / 0x2000) as usize).saturating_sub(3);
+                (Some(self.prg_address(bank, address)), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0x6000..=0x7FFF => {
+                self.prg_ram[(address & 0x1FFF) as usize] = data;
+                self.sram_dirty = true;
+            }
+            0xA000..=0xAFFF => self.prg_bank = (data & 0x0F) as usize,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = (data & 0x1F) as usize,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = (data & 0x1F) as usize,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = (data & 0x1F) as usize,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = (data & 0x1F) as usize,
+            0xF000..=0xFFFF => {
+                self.mirroring = match data & 0x01 {
+                    0 => MirroringType::Vertical,
+                    _ => MirroringType::Horizontal,
+                }
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    fn ppu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x0000..=0x1FFF if !self.chr_ram.is_empty() => (
+                None,
+                Some(self.chr_ram[address as usize % self.chr_ram.len()]),
+            ),
+            0x0000..=0x1FFF => {
+                let banks = (self.chr_rom_size / 0x1000).max(1);
+                let bank = self.chr_bank((address >> 12) as usize) % banks;
+                (Some(bank * 0x1000 + (address & 0x0FFF) as usize), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if let 0x0000..=0x1FFF = address {
+            if !self.chr_ram.is_empty() {
+                let index = address as usize % self.chr_ram.len();
+                self.chr_ram[index] = data;
+            }
+        }
+
+        None
+    }
+
+    fn mirroring_type(&self) -> Option<MirroringType> {
+        Some(self.mirroring)
+    }
+
+    fn sram_dirty(&self) -> bool {
+        self.sram_dirty
+    }
+
+    fn clear_sram_dirty(&mut self) {
+        self.sram_dirty = false;
+    }
+
+    // The latch flips after the fetch that triggers it, so the tile itself
+    // is still drawn from the old bank. The first pattern table only
+    // reacts to the first byte of the tile's high plane, the second to any.
+    fn ppu_fetch_notify(&mut self, address: u16) {
+        let latch = match address {
+            0x0FD8 | 0x1FD8..=0x1FDF => LATCH_FD,
+            0x0FE8 | 0x1FE8..=0x1FEF => LATCH_FE,
+            _ => return,
+        };
+        self.latches[(address >> 12) as usize] = latch;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper009;
+    use crate::cartridge::MirroringType;
+    use crate::mappers::Mapper;
+
+    #[test]
+    fn prg_banking() {
+        let mut mapper = Mapper009::new(0x20000, 0x20000, 0);
+
+        mapper.cpu_write(0xA000, 0x05);
+        assert_eq!(mapper.cpu_read(0x8123), (Some(0x0A123), None));
+        assert_eq!(mapper.cpu_read(0xA123), (Some(0x1A123), None), "fixed");
+        assert_eq!(mapper.cpu_read(0xC123), (Some(0x1C123), None), "fixed");
+        assert_eq!(mapper.cpu_read(0xFFFC), (Some(0x1FFFC), None), "fixed");
+
+        mapper.cpu_write(0xF000, 0x01);
+        assert_eq!(mapper.mirroring_type(), Some(MirroringType::Horizontal));
+    }
+
+    #[test]
+    fn chr_latches() {
+        let mut mapper = Mapper009::new(0x20000, 0x20000, 0);
+        mapper.cpu_write(0xB000, 0x01);
+        mapper.cpu_write(0xC000, 0x02);
+        mapper.cpu_write(0xD000, 0x03);
+        mapper.cpu_write(0xE000, 0x04);
+        assert_eq!(mapper.ppu_read(0x0010), (Some(0x2010), None), "power on");
+        assert_eq!(mapper.ppu_read(0x1010), (Some(0x4010), None), "power on");
+
+        // Fetching tile $FD from the first table switches only that table.
+        mapper.ppu_fetch_notify(0x0FD0);
+        assert_eq!(mapper.ppu_read(0x0010), (Some(0x2010), None), "low plane");
+        mapper.ppu_fetch_notify(0x0FD8);
+        assert_eq!(mapper.ppu_read(0x0010), (Some(0x1010), None));
+        assert_eq!(mapper.ppu_read(0x1010), (Some(0x4010), None));
+
+        // The second table reacts to any row of the tile.
+        mapper.ppu_fetch_notify(0x1FDB);
+        assert_eq!(mapper.ppu_read(0x1010), (Some(0x3010), None));
+        mapper.ppu_fetch_notify(0x0FE9);
+        assert_eq!(mapper.ppu_read(0x0010), (Some(0x1010), None), "only $0FE8");
+        mapper.ppu_fetch_notify(0x0FE8);
+        assert_eq!(mapper.ppu_read(0x0010), (Some(0x2010), None));
+        mapper.ppu_fetch_notify(0x1FEF);
+        assert_eq!(mapper.ppu_read(0x1010), (Some(0x4010), None));
+    }
+}
@@ -0,0 +1,497 @@
+use super::Mapper;
+use crate::cartridge::MirroringType;
+
+// The IRQ prescaler counts down by 3 every CPU cycle from 341, clocking the
+// counter once per scanline's worth of CPU cycles.
+const PRESCALER_PERIOD: i16 = 341;
+// The loudest the three channels can be together: 15 + 15 + 31.
+const MAX_OUTPUT: f32 = 61.0;
+
+/// One of the VRC6's two pulse channels.
+#[derive(Clone)]
+struct Pulse {
+    volume: u8,
+    duty: u8,
+    ignore_duty: bool,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+}
+
+impl Pulse {
+    fn new() -> Self {
+        Pulse {
+            volume: 0,
+            duty: 0,
+            ignore_duty: false,
+            period: 0,
+            enabled: false,
+            divider: 0,
+            step: 15,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.ignore_duty = data & 0x80 == 0x80;
+                self.duty = (data & 0x70) >> 4;
+                self.volume = data & 0x0F;
+            }
+            1 => self.period = self.period & 0x0F00 | data as u16,
+            2 => {
+                self.period = self.period & 0x00FF | ((data & 0x0F) as u16) << 8;
+                self.enabled = data & 0x80 == 0x80;
+                // Disabling the channel resets its duty cycle.
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.divider == 0 {
+            self.divider = self.period >> shift;
+            // The duty cycle steps from 15 down to 0, then starts again.
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.ignore_duty || self.step <= self.duty) {
+            self.volume
+        } else {
+            0
+        }
+    }
+}
+
+/// The VRC6's sawtooth channel.
+#[derive(Clone)]
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn new() -> Self {
+        Sawtooth {
+            rate: 0,
+            period: 0,
+            enabled: false,
+            divider: 0,
+            step: 0,
+            accumulator: 0,
+        }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = self.period & 0x0F00 | data as u16,
+            2 => {
+                self.period = self.period & 0x00FF | ((data & 0x0F) as u16) << 8;
+                self.enabled = data & 0x80 == 0x80;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+        self.divider = self.period >> shift;
+
+        // The rate is added on every other step, and the accumulator is
+        // cleared on the 14th, making 7 rising steps per cycle.
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step & 0x01 == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        // Only the top 5 bits of the accumulator are output.
+        self.accumulator >> 3
+    }
+}
+
+/// Konami's VRC6a, used by Akumajou Densetsu.
+///
+/// The VRC6b (mapper 26) is the same chip with address lines A0 and A1
+/// swapped, which `Mapper026` takes care of before calling in here.
+#[derive(Clone)]
+pub struct Mapper024 {
+    prg_rom_size: usize,
+    chr_rom_size: usize,
+    chr_ram: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    prg_ram_enabled: bool,
+    sram_dirty: bool,
+    prg_16k_bank: usize,
+    prg_8k_bank: usize,
+    chr_banks: [usize; 8],
+    mirroring: MirroringType,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_enabled: bool,
+    irq_enabled_after_ack: bool,
+    irq_cycle_mode: bool,
+    irq_prescaler: i16,
+    irq_pending: bool,
+    audio_halted: bool,
+    frequency_shift: u8,
+    pulse1: Pulse,
+    pulse2: Pulse,
+    sawtooth: Sawtooth,
+}
+
+impl Mapper024 {
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize, chr_ram_size: usize) -> Self {
+        Mapper024 {
+            prg_rom_size,
+            chr_rom_size,
+            chr_ram: vec![0; chr_ram_size],
+            prg_ram: [0; 0x2000],
+            prg_ram_enabled: false,
+            sram_dirty: false,
+            prg_16k_bank: 0,
+            prg_8k_bank: 0,
+            chr_banks: [0; 8],
+            mirroring: MirroringType::Vertical,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_enabled_after_ack: false,
+            irq_cycle_mode: false,
+            irq_prescaler: PRESCALER_PERIOD,
+            irq_pending: false,
+            audio_halted: false,
+            frequency_shift: 0,
+            pulse1: Pulse::new(),
+            pulse2: Pulse::new(),
+            sawtooth: Sawtooth::new(),
+        }
+    }
+
+    fn prg_address(&self, bank: usize, bank_size: usize, address: u16) -> usize {
+        let banks = (self.prg_rom_size / bank_size).max(1);
+        (bank % banks) * bank_size + (address as usize & (bank_size - 1))
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_counter == 0xFF {
+            self.irq_counter = self.irq_latch;
+            self.irq_pending = true;
+        } else {
+            self.irq_counter += 1;
+        }
+    }
+}
+
+impl Mapper for Mapper024 {
+    fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                (None, Some(self.prg_ram[(address & 0x1FFF) as usize]))
+            }
+            0x8000..=0xBFFF => (
+                Some(self.prg_address(self.prg_16k_bank, 0x4000, address)),
+                None,
+            ),
+            0xC000..=0xDFFF => (
+                Some(self.prg_address(self.prg_8k_bank, 0x2000, address)),
+                None,
+            ),
+            // The last 8 KiB bank is fixed at $E000.
+            0xE000..=0xFFFF => {
+                let last_bank = (self.prg_rom_size / 0x2000).saturating_sub(1);
+                (Some(self.prg_address(last_bank, 0x2000, address)), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address & 0xF003 {
+            0x6000..=0x7FFF if self.prg_ram_enabled => {
+                self.prg_ram[(address & 0x1FFF) as usize] = data;
+                self.sram_dirty = true;
+            }
+            0x8000..=0x8003 => self.prg_16k_bank = (data & 0x0F) as usize,
+            0xB003 => {
+                self.mirroring = match (data & 0x0C) >> 2 {
+                    0 => MirroringType::Vertical,
+                    1 => MirroringType::Horizontal,
+                    // Only the first one-screen layout can be shown.
+                    _ => MirroringType::OneScreen,
+                };
+                self.prg_ram_enabled = data & 0x80 == 0x80;
+            }
+            0xC000..=0xC003 => self.prg_8k_bank = (data & 0x1F) as usize,
+            0xD000..=0xD003 => self.chr_banks[(address & 0x03) as usize] = data as usize,
+            0xE000..=0xE003 => self.chr_banks[4 + (address & 0x03) as usize] = data as usize,
+            0xF000 => self.irq_latch = data,
+            0xF001 => {
+                self.irq_enabled_after_ack = data & 0x01 == 0x01;
+                self.irq_enabled = data & 0x02 == 0x02;
+                self.irq_cycle_mode = data & 0x04 == 0x04;
+                self.irq_pending = false;
+
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_latch;
+                    self.irq_prescaler = PRESCALER_PERIOD;
+                }
+            }
+            0xF002 => {
+                self.irq_pending = false;
+                self.irq_enabled = self.irq_enabled_after_ack;
+            }
+            _ => (),
+        }
+
+        None
+    }
+
+    fn ppu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x0000..=0x1FFF if !self.chr_ram.is_empty() => (
+                None,
+                Some(self.chr_ram[address as usize % self.chr_ram.len()]),
+            ),
+            0x0000..=0x1FFF => {
+                let banks = (self.chr_rom_size / 0x400).max(1);
+                let bank = self.chr_banks[(address / 0x400) as usize] % banks;
+                (Some(bank * 0x400 + (address & 0x03FF) as usize), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if let 0x0000..=0x1FFF = address {
+            if !self.chr_ram.is_empty() {
+                let index = address as usize % self.chr_ram.len();
+                self.chr_ram[index] = data;
+            }
+        }
+
+        None
+    }
+
+    fn mirroring_type(&self) -> Option<MirroringType> {
+        Some(self.mirroring)
+    }
+
+    fn sram_dirty(&self) -> bool {
+        self.sram_dirty
+    }
+
+    fn clear_sram_dirty(&mut self) {
+        self.sram_dirty = false;
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn clear_irq(&mut self) {
+        self.irq_pending = false;
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.irq_enabled {
+            if self.irq_cycle_mode {
+                self.clock_irq_counter();
+            } else {
+                self.irq_prescaler -= 3;
+                if self.irq_prescaler <= 0 {
+                    self.irq_prescaler += PRESCALER_PERIOD;
+                    self.clock_irq_counter();
+                }
+            }
+        }
+
+        if !self.audio_halted {
+            self.pulse1.clock(self.frequency_shift);
+            self.pulse2.clock(self.frequency_shift);
+            self.sawtooth.clock(self.frequency_shift);
+        }
+    }
+
+    fn audio_cpu_write(&mut self, address: u16, data: u8) {
+        match address & 0xF003 {
+            0x9000..=0x9002 => self.pulse1.write(address & 0x03, data),
+            0x9003 => {
+                self.audio_halted = data & 0x01 == 0x01;
+                // The periods can be divided by 16 or 256 for testing.
+                self.frequency_shift = if data & 0x04 == 0x04 {
+                    8
+                } else if data & 0x02 == 0x02 {
+                    4
+                } else {
+                    0
+                };
+            }
+            0xA000..=0xA002 => self.pulse2.write(address & 0x03, data),
+            0xB000..=0xB002 => self.sawtooth.write(address & 0x03, data),
+            _ => (),
+        }
+    }
+
+    fn audio_sample(&mut self) -> f32 {
+        let output = self.pulse1.output() + self.pulse2.output() + self.sawtooth.output();
+        output as f32 / MAX_OUTPUT
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mapper024, Pulse, Sawtooth};
+    use crate::cartridge::MirroringType;
+    use crate::mappers::Mapper;
+
+    #[test]
+    fn sawtooth_accumulator() {
+        let mut sawtooth = Sawtooth::new();
+        sawtooth.write(0, 0x0A);
+        sawtooth.write(1, 0x00);
+        sawtooth.write(2, 0x80);
+
+        // With a period of 0 the divider clocks the accumulator every cycle.
+        let mut accumulator = vec![];
+        for _ in 0..16 {
+            sawtooth.clock(0);
+            accumulator.push(sawtooth.accumulator);
+        }
+
+        assert_eq!(
+            accumulator,
+            vec![0, 10, 10, 20, 20, 30, 30, 40, 40, 50, 50, 60, 60, 0, 0, 10]
+        );
+        // 60 is 0b111100, the top 5 bits of which are 7.
+        assert_eq!(accumulator.iter().map(|value| value >> 3).max(), Some(7));
+
+        sawtooth.write(2, 0x00);
+        assert_eq!(sawtooth.output(), 0, "disabling clears the accumulator");
+    }
+
+    #[test]
+    fn pulse_duty() {
+        let mut pulse = Pulse::new();
+        // Duty 3 (4/16), volume 9, period 1.
+        pulse.write(0, 0x39);
+        pulse.write(1, 0x01);
+        pulse.write(2, 0x80);
+
+        let mut output = vec![];
+        for _ in 0..32 {
+            pulse.clock(0);
+            output.push(pulse.output());
+        }
+
+        // Each step lasts 2 cycles, and 4 of the 16 steps are high.
+        let high = output.iter().filter(|&&value| value == 9).count();
+        assert_eq!(high, 8);
+        assert!(output.iter().all(|&value| value == 0 || value == 9));
+        assert_eq!(&output[..2], &[0, 0], "step 14 is low");
+        assert_eq!(&output[22..30], &[9; 8], "steps 3 to 0 are high");
+
+        // The mode bit ignores the duty cycle.
+        pulse.write(0, 0x89);
+        assert_eq!(pulse.output(), 9);
+        pulse.write(2, 0x00);
+        assert_eq!(pulse.output(), 0, "disabled");
+    }
+
+    #[test]
+    fn mixes_channels() {
+        let mut mapper = Mapper024::new(0x40000, 0x20000, 0);
+        assert_eq!(mapper.audio_sample(), 0.0);
+
+        mapper.audio_cpu_write(0x9000, 0x8F);
+        mapper.audio_cpu_write(0x9002, 0x80);
+        mapper.audio_cpu_write(0xA000, 0x8F);
+        mapper.audio_cpu_write(0xA002, 0x80);
+        assert_eq!(mapper.audio_sample(), 30.0 / 61.0);
+    }
+
+    #[test]
+    fn banking() {
+        let mut mapper = Mapper024::new(0x40000, 0x20000, 0);
+
+        mapper.cpu_write(0x8000, 0x02);
+        mapper.cpu_write(0xC000, 0x05);
+        assert_eq!(mapper.cpu_read(0x8123), (Some(0x08123), None));
+        assert_eq!(mapper.cpu_read(0xC123), (Some(0x0A123), None));
+        assert_eq!(mapper.cpu_read(0xFFFC), (Some(0x3FFFC), None), "fixed");
+
+        mapper.cpu_write(0xE002, 0x7F);
+        assert_eq!(mapper.ppu_read(0x1801), (Some(0x1FC01), None));
+
+        mapper.cpu_write(0xB003, 0x84);
+        assert_eq!(mapper.mirroring_type(), Some(MirroringType::Horizontal));
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_read(0x6000), (None, Some(0x42)), "PRG RAM");
+    }
+
+    #[test]
+    fn irq_counter() {
+        let mut mapper = Mapper024::new(0x40000, 0x20000, 0);
+
+        // Cycle mode, counting up from $FD.
+        mapper.cpu_write(0xF000, 0xFD);
+        mapper.cpu_write(0xF001, 0x06);
+
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        assert!(!mapper.irq_pending());
+        mapper.cpu_clock();
+        assert!(mapper.irq_pending(), "overflowed from $FF");
+        assert_eq!(mapper.irq_counter, 0xFD, "reloaded from the latch");
+
+        // Acknowledging without the enable-after-ack bit stops the counter.
+        mapper.cpu_write(0xF002, 0x00);
+        assert!(!mapper.irq_pending());
+        for _ in 0..10 {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.irq_pending());
+
+        // Scanline mode clocks the counter every 113 2/3 CPU cycles.
+        mapper.cpu_write(0xF000, 0xFF);
+        mapper.cpu_write(0xF001, 0x02);
+        for _ in 0..113 {
+            mapper.cpu_clock();
+        }
+        assert!(!mapper.irq_pending());
+        mapper.cpu_clock();
+        assert!(mapper.irq_pending());
+    }
+}
@@ -0,0 +1,98 @@
+use super::mapper_024::Mapper024;
+use super::Mapper;
+use crate::cartridge::MirroringType;
+
+/// Konami's VRC6b, used by Madara and Esper Dream 2. It's the VRC6a
+/// (mapper 24) with address lines A0 and A1 swapped.
+#[derive(Clone)]
+pub struct Mapper026 {
+    vrc6: Mapper024,
+}
+
+impl Mapper026 {
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize, chr_ram_size: usize) -> Self {
+        Mapper026 {
+            vrc6: Mapper024::new(prg_rom_size, chr_rom_size, chr_ram_size),
+        }
+    }
+
+    fn swap_lines(address: u16) -> u16 {
+        address & !0x0003 | (address & 0x0001) << 1 | (address & 0x0002) >> 1
+    }
+}
+
+impl Mapper for Mapper026 {
+    fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        self.vrc6.cpu_read(address)
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0x8000..=0xFFFF => self.vrc6.cpu_write(Self::swap_lines(address), data),
+            _ => self.vrc6.cpu_write(address, data),
+        }
+    }
+
+    fn ppu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        self.vrc6.ppu_read(address)
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        self.vrc6.ppu_write(address, data)
+    }
+
+    fn mirroring_type(&self) -> Option<MirroringType> {
+        self.vrc6.mirroring_type()
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.vrc6.irq_pending()
+    }
+
+    fn clear_irq(&mut self) {
+        self.vrc6.clear_irq();
+    }
+
+    fn sram_dirty(&self) -> bool {
+        self.vrc6.sram_dirty()
+    }
+
+    fn clear_sram_dirty(&mut self) {
+        self.vrc6.clear_sram_dirty();
+    }
+
+    fn cpu_clock(&mut self) {
+        self.vrc6.cpu_clock();
+    }
+
+    fn audio_cpu_write(&mut self, address: u16, data: u8) {
+        self.vrc6.audio_cpu_write(Self::swap_lines(address), data);
+    }
+
+    fn audio_sample(&mut self) -> f32 {
+        self.vrc6.audio_sample()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper026;
+    use crate::cartridge::MirroringType;
+    use crate::mappers::Mapper;
+
+    #[test]
+    fn swapped_address_lines() {
+        let mut mapper = Mapper026::new(0x40000, 0x20000, 0);
+
+        // $B003 on the VRC6a is $B003 here too, but $D001 is $D002.
+        mapper.cpu_write(0xB003, 0x04);
+        assert_eq!(mapper.mirroring_type(), Some(MirroringType::Horizontal));
+        mapper.cpu_write(0xD001, 0x03);
+        assert_eq!(mapper.ppu_read(0x0800), (Some(0x0C00), None));
+
+        // $9002 is the pulse's enable register on the VRC6a.
+        mapper.audio_cpu_write(0x9000, 0x8F);
+        mapper.audio_cpu_write(0x9001, 0x80);
+        assert_eq!(mapper.audio_sample(), 15.0 / 61.0);
+    }
+}
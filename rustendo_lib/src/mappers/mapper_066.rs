@@ -0,0 +1,111 @@
+use super::Mapper;
+use crate::cartridge::MirroringType;
+
+/// GxROM: one register anywhere in $8000-$FFFF selects a 32 KiB PRG bank
+/// with bits 4-5 and an 8 KiB CHR bank with bits 0-1.
+#[derive(Clone)]
+pub struct Mapper066 {
+    prg_rom_size: usize,
+    chr_rom_size: usize,
+    chr_ram: Vec<u8>,
+    prg_bank: usize,
+    chr_bank: usize,
+    bus_conflicts: bool,
+}
+
+impl Mapper066 {
+    pub fn new(prg_rom_size: usize, chr_rom_size: usize, chr_ram_size: usize) -> Self {
+        Mapper066 {
+            prg_rom_size,
+            chr_rom_size,
+            chr_ram: vec![0; chr_ram_size],
+            prg_bank: 0,
+            chr_bank: 0,
+            bus_conflicts: false,
+        }
+    }
+
+    /// Sets whether the board has bus conflicts, which most GxROM boards do.
+    pub fn with_bus_conflicts(mut self, bus_conflicts: bool) -> Self {
+        self.bus_conflicts = bus_conflicts;
+        self
+    }
+}
+
+impl Mapper for Mapper066 {
+    fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x8000..=0xFFFF => {
+                let banks = (self.prg_rom_size / 0x8000).max(1);
+                let bank = self.prg_bank % banks;
+                (Some(bank * 0x8000 + (address & 0x7FFF) as usize), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if let 0x8000..=0xFFFF = address {
+            self.prg_bank = ((data >> 4) & 0x03) as usize;
+            self.chr_bank = (data & 0x03) as usize;
+        }
+
+        None
+    }
+
+    fn ppu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x0000..=0x1FFF if !self.chr_ram.is_empty() => (
+                None,
+                Some(self.chr_ram[address as usize % self.chr_ram.len()]),
+            ),
+            0x0000..=0x1FFF => {
+                let banks = (self.chr_rom_size / 0x2000).max(1);
+                let bank = self.chr_bank % banks;
+                (Some(bank * 0x2000 + address as usize), None)
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        if let 0x0000..=0x1FFF = address {
+            if !self.chr_ram.is_empty() {
+                let index = address as usize % self.chr_ram.len();
+                self.chr_ram[index] = data;
+            }
+        }
+
+        None
+    }
+
+    fn mirroring_type(&self) -> Option<MirroringType> {
+        None
+    }
+
+    fn has_bus_conflicts(&self) -> bool {
+        self.bus_conflicts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mapper066;
+    use crate::mappers::Mapper;
+
+    #[test]
+    fn banking() {
+        let mut mapper = Mapper066::new(0x20000, 0x8000, 0);
+        assert_eq!(mapper.cpu_read(0x8000), (Some(0x0000), None));
+        assert_eq!(mapper.ppu_read(0x0000), (Some(0x0000), None));
+
+        mapper.cpu_write(0x8000, 0x21);
+        assert_eq!(mapper.cpu_read(0x8123), (Some(0x10123), None));
+        assert_eq!(mapper.cpu_read(0xFFFC), (Some(0x17FFC), None));
+        assert_eq!(mapper.ppu_read(0x1801), (Some(0x3801), None));
+
+        mapper.cpu_write(0xFFFF, 0x32);
+        assert_eq!(mapper.cpu_read(0x8000), (Some(0x18000), None));
+        assert_eq!(mapper.ppu_read(0x0000), (Some(0x4000), None));
+    }
+}
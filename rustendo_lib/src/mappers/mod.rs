@@ -1,12 +1,94 @@
-use super::cartridge::MirroringType;
+use super::cartridge::{MirroringType, NametableSource};
 
-pub trait Mapper {
+pub trait Mapper: MapperClone {
     fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>);
     fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize>;
     fn ppu_read(&self, address: u16) -> (Option<usize>, Option<u8>);
     fn ppu_write(&mut self, address: u16, data: u8) -> Option<usize>;
     fn mirroring_type(&self) -> Option<MirroringType>;
+
+    /// Whether writes to PRG ROM are ANDed with the ROM byte at that
+    /// address, as on discrete logic boards that don't disable the ROM
+    /// during writes.
+    fn has_bus_conflicts(&self) -> bool {
+        false
+    }
+
+    /// Whether the mapper is holding the CPU's IRQ line low.
+    fn irq_pending(&self) -> bool {
+        false
+    }
+
+    /// Releases the IRQ line, for mappers whose IRQ is acknowledged by
+    /// something other than a register write.
+    fn clear_irq(&mut self) {}
+
+    /// Where a nametable access goes, for mappers that map each 1K page
+    /// separately. `None` leaves it to `mirroring_type`.
+    fn map_nametable(&self, _address: u16) -> Option<NametableSource> {
+        None
+    }
+
+    /// Reads from a `FillMode` or `Cart` nametable page.
+    fn nametable_read(&self, _source: NametableSource, _address: u16) -> u8 {
+        0
+    }
+
+    /// Writes to a `FillMode` or `Cart` nametable page.
+    fn nametable_write(&mut self, _source: NametableSource, _address: u16, _data: u8) {}
+
+    /// Whether $6000-$7FFF has been written since `clear_sram_dirty`, so
+    /// battery-backed RAM only needs saving when it has changed.
+    fn sram_dirty(&self) -> bool {
+        false
+    }
+
+    fn clear_sram_dirty(&mut self) {}
+
+    /// Called when PPU address line A12 goes from low to high, which
+    /// scanline counters like the MMC3's use as a clock.
+    fn on_a12_rising(&mut self) {}
+
+    /// Called after each rendering fetch from the pattern tables, for
+    /// mappers like the MMC2 that switch banks on particular tiles.
+    fn ppu_fetch_notify(&mut self, _address: u16) {}
+
+    /// Called once per CPU cycle, for mappers that count cycles, like the
+    /// VRC6's IRQ counter and expansion audio.
+    fn cpu_clock(&mut self) {}
+
+    /// Receives every CPU write to the cartridge, for mappers with
+    /// expansion audio registers.
+    fn audio_cpu_write(&mut self, _address: u16, _data: u8) {}
+
+    /// The mapper's expansion audio output for the current CPU cycle, to be
+    /// mixed with the APU's channels.
+    fn audio_sample(&mut self) -> f32 {
+        0.0
+    }
+}
+
+/// Lets a boxed mapper be cloned along with the rest of the machine. It's
+/// implemented for every mapper that derives `Clone`.
+pub trait MapperClone {
+    fn clone_box(&self) -> Box<dyn Mapper>;
+}
+
+impl<T: Mapper + Clone + 'static> MapperClone for T {
+    fn clone_box(&self) -> Box<dyn Mapper> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn Mapper> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 pub mod mapper_000;
 pub mod mapper_001;
+pub mod mapper_009;
+pub mod mapper_024;
+pub mod mapper_026;
+pub mod mapper_066;
@@ -1,10 +1,19 @@
 use crate::cartridge::Cartridge;
 use crate::cpu_bus::Bus;
+use crate::disassembler;
+use std::collections::HashSet;
 use std::fmt::{Display, Error, Formatter};
 use std::ops::Deref;
 
 const NEGATIVE_ONE: u8 = !1 + 1;
 
+// Each interrupt vector is the address of the low byte of a little-endian
+// pointer; the high byte follows at the next address.
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+#[derive(Clone)]
 struct DataBus {
     data: u8,
 }
@@ -23,6 +32,7 @@ impl DataBus {
     }
 }
 
+#[derive(Clone)]
 struct AddressBus {
     address_high: u8,
     address_low: u8,
@@ -46,6 +56,7 @@ impl AddressBus {
     }
 }
 
+#[derive(Clone)]
 struct ProgramCounter {
     pch: u8,
     pcl: u8,
@@ -92,6 +103,7 @@ impl Display for ProgramCounter {
     }
 }
 
+#[derive(Clone)]
 struct StatusRegister {
     carry: bool,
     zero: bool,
@@ -160,6 +172,7 @@ impl Display for StatusRegister {
     }
 }
 
+#[derive(Clone)]
 struct InstructionRegister {
     data: u8,
 }
@@ -189,7 +202,7 @@ impl InstructionRegister {
                 0x5 => Instruction::BVC(AddressingMode::Relative, 2, 2),
                 0x6 => Instruction::RTS(AddressingMode::Implied, 1, 6),
                 0x7 => Instruction::BVS(AddressingMode::Relative, 2, 2),
-                0x8 => Instruction::KIL,
+                0x8 => Instruction::NOP(AddressingMode::Immediate, 2, 2),
                 0x9 => Instruction::BCC(AddressingMode::Relative, 2, 2),
                 0xA => Instruction::LDY(AddressingMode::Immediate, 2, 2),
                 0xB => Instruction::BCS(AddressingMode::Relative, 2, 2),
@@ -219,8 +232,9 @@ impl InstructionRegister {
                 _ => unreachable!(),
             },
             0x2 => match high_nibble {
+                0x8 | 0xC | 0xE => Instruction::NOP(AddressingMode::Immediate, 2, 2),
                 0xA => Instruction::LDX(AddressingMode::Immediate, 2, 2),
-                0x0..=0x9 => Instruction::KIL,
+                0x0..=0x7 | 0x9 | 0xB | 0xD | 0xF => Instruction::KIL,
                 _ => unreachable!(),
             },
             0x3 | 0x7 | 0xB | 0xF => Instruction::KIL,
@@ -232,7 +246,10 @@ impl InstructionRegister {
                 0xB => Instruction::LDY(AddressingMode::ZeroPageX, 2, 4),
                 0xC => Instruction::CPY(AddressingMode::ZeroPage, 2, 3),
                 0xE => Instruction::CPX(AddressingMode::ZeroPage, 2, 3),
-                0x0 | 0x1 | 0x3..=0x7 | 0xD | 0xF => Instruction::KIL,
+                0x0 | 0x4 | 0x6 => Instruction::NOP(AddressingMode::ZeroPage, 2, 3),
+                0x1 | 0x3 | 0x5 | 0x7 | 0xD | 0xF => {
+                    Instruction::NOP(AddressingMode::ZeroPageX, 2, 4)
+                }
                 _ => unreachable!(),
             },
             0x5 => match high_nibble {
@@ -301,7 +318,7 @@ impl InstructionRegister {
                 0x5 => Instruction::EOR(AddressingMode::AbsoluteY, 3, 4),
                 0x6 => Instruction::ADC(AddressingMode::Immediate, 2, 2),
                 0x7 => Instruction::ADC(AddressingMode::AbsoluteY, 3, 4),
-                0x8 => Instruction::KIL,
+                0x8 => Instruction::NOP(AddressingMode::Immediate, 2, 2),
                 0x9 => Instruction::STA(AddressingMode::AbsoluteY, 3, 5),
                 0xA => Instruction::LDA(AddressingMode::Immediate, 2, 2),
                 0xB => Instruction::LDA(AddressingMode::AbsoluteY, 3, 4),
@@ -322,7 +339,9 @@ impl InstructionRegister {
                 0xB => Instruction::TSX(AddressingMode::Implied, 1, 2),
                 0xC => Instruction::DEX(AddressingMode::Implied, 1, 2),
                 0xE => Instruction::NOP(AddressingMode::Implied, 1, 2),
-                0x1 | 0x3 | 0x5 | 0x7 | 0xD | 0xF => Instruction::KIL,
+                0x1 | 0x3 | 0x5 | 0x7 | 0xD | 0xF => {
+                    Instruction::NOP(AddressingMode::Implied, 1, 2)
+                }
                 _ => unreachable!(),
             },
             0xC => match high_nibble {
@@ -334,7 +353,12 @@ impl InstructionRegister {
                 0xB => Instruction::LDY(AddressingMode::AbsoluteX, 3, 4),
                 0xC => Instruction::CPY(AddressingMode::Absolute, 3, 4),
                 0xE => Instruction::CPX(AddressingMode::Absolute, 3, 4),
-                0x0 | 0x1 | 0x3 | 0x5 | 0x7 | 0x9 | 0xD | 0xF => Instruction::KIL,
+                0x0 => Instruction::NOP(AddressingMode::Absolute, 3, 4),
+                // One more cycle when the index crosses a page.
+                0x1 | 0x3 | 0x5 | 0x7 | 0xD | 0xF => {
+                    Instruction::NOP(AddressingMode::AbsoluteX, 3, 4)
+                }
+                0x9 => Instruction::KIL,
                 _ => unreachable!(),
             },
             0xD => match high_nibble {
@@ -536,14 +560,155 @@ pub enum Instruction {
     KIL,
 }
 
+impl Instruction {
+    /// Decodes a single opcode byte.
+    pub fn decode(opcode: u8) -> Self {
+        let mut instruction_register = InstructionRegister::new();
+        instruction_register.write(opcode);
+        instruction_register.decode_instruction()
+    }
+
+    /// Returns the addressing mode and instruction length in bytes,
+    /// or `None` for an illegal opcode.
+    pub fn operands(&self) -> Option<(AddressingMode, u32)> {
+        self.parts().map(|(mode, bytes, _)| (mode, bytes))
+    }
+
+    /// Returns the addressing mode, instruction length in bytes and clock
+    /// cycles, or `None` for an illegal opcode.
+    pub fn parts(&self) -> Option<(AddressingMode, u32, u32)> {
+        match self {
+            Instruction::ADC(mode, bytes, cycles)
+            | Instruction::AND(mode, bytes, cycles)
+            | Instruction::ASL(mode, bytes, cycles)
+            | Instruction::BCC(mode, bytes, cycles)
+            | Instruction::BCS(mode, bytes, cycles)
+            | Instruction::BEQ(mode, bytes, cycles)
+            | Instruction::BIT(mode, bytes, cycles)
+            | Instruction::BMI(mode, bytes, cycles)
+            | Instruction::BNE(mode, bytes, cycles)
+            | Instruction::BPL(mode, bytes, cycles)
+            | Instruction::BRK(mode, bytes, cycles)
+            | Instruction::BVC(mode, bytes, cycles)
+            | Instruction::BVS(mode, bytes, cycles)
+            | Instruction::CLC(mode, bytes, cycles)
+            | Instruction::CLD(mode, bytes, cycles)
+            | Instruction::CLI(mode, bytes, cycles)
+            | Instruction::CLV(mode, bytes, cycles)
+            | Instruction::CMP(mode, bytes, cycles)
+            | Instruction::CPX(mode, bytes, cycles)
+            | Instruction::CPY(mode, bytes, cycles)
+            | Instruction::DEC(mode, bytes, cycles)
+            | Instruction::DEX(mode, bytes, cycles)
+            | Instruction::DEY(mode, bytes, cycles)
+            | Instruction::EOR(mode, bytes, cycles)
+            | Instruction::INC(mode, bytes, cycles)
+            | Instruction::INX(mode, bytes, cycles)
+            | Instruction::INY(mode, bytes, cycles)
+            | Instruction::JMP(mode, bytes, cycles)
+            | Instruction::JSR(mode, bytes, cycles)
+            | Instruction::LDA(mode, bytes, cycles)
+            | Instruction::LDX(mode, bytes, cycles)
+            | Instruction::LDY(mode, bytes, cycles)
+            | Instruction::LSR(mode, bytes, cycles)
+            | Instruction::NOP(mode, bytes, cycles)
+            | Instruction::ORA(mode, bytes, cycles)
+            | Instruction::PHA(mode, bytes, cycles)
+            | Instruction::PHP(mode, bytes, cycles)
+            | Instruction::PLA(mode, bytes, cycles)
+            | Instruction::PLP(mode, bytes, cycles)
+            | Instruction::ROL(mode, bytes, cycles)
+            | Instruction::ROR(mode, bytes, cycles)
+            | Instruction::RTI(mode, bytes, cycles)
+            | Instruction::RTS(mode, bytes, cycles)
+            | Instruction::SBC(mode, bytes, cycles)
+            | Instruction::SEC(mode, bytes, cycles)
+            | Instruction::SED(mode, bytes, cycles)
+            | Instruction::SEI(mode, bytes, cycles)
+            | Instruction::STA(mode, bytes, cycles)
+            | Instruction::STX(mode, bytes, cycles)
+            | Instruction::STY(mode, bytes, cycles)
+            | Instruction::TAX(mode, bytes, cycles)
+            | Instruction::TAY(mode, bytes, cycles)
+            | Instruction::TSX(mode, bytes, cycles)
+            | Instruction::TXA(mode, bytes, cycles)
+            | Instruction::TXS(mode, bytes, cycles)
+            | Instruction::TYA(mode, bytes, cycles) => Some((*mode, *bytes, *cycles)),
+            Instruction::KIL => None,
+        }
+    }
+
+    /// The instruction's three letter name, e.g. `LDA`.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::ADC(..) => "ADC",
+            Instruction::AND(..) => "AND",
+            Instruction::ASL(..) => "ASL",
+            Instruction::BCC(..) => "BCC",
+            Instruction::BCS(..) => "BCS",
+            Instruction::BEQ(..) => "BEQ",
+            Instruction::BIT(..) => "BIT",
+            Instruction::BMI(..) => "BMI",
+            Instruction::BNE(..) => "BNE",
+            Instruction::BPL(..) => "BPL",
+            Instruction::BRK(..) => "BRK",
+            Instruction::BVC(..) => "BVC",
+            Instruction::BVS(..) => "BVS",
+            Instruction::CLC(..) => "CLC",
+            Instruction::CLD(..) => "CLD",
+            Instruction::CLI(..) => "CLI",
+            Instruction::CLV(..) => "CLV",
+            Instruction::CMP(..) => "CMP",
+            Instruction::CPX(..) => "CPX",
+            Instruction::CPY(..) => "CPY",
+            Instruction::DEC(..) => "DEC",
+            Instruction::DEX(..) => "DEX",
+            Instruction::DEY(..) => "DEY",
+            Instruction::EOR(..) => "EOR",
+            Instruction::INC(..) => "INC",
+            Instruction::INX(..) => "INX",
+            Instruction::INY(..) => "INY",
+            Instruction::JMP(..) => "JMP",
+            Instruction::JSR(..) => "JSR",
+            Instruction::LDA(..) => "LDA",
+            Instruction::LDX(..) => "LDX",
+            Instruction::LDY(..) => "LDY",
+            Instruction::LSR(..) => "LSR",
+            Instruction::NOP(..) => "NOP",
+            Instruction::ORA(..) => "ORA",
+            Instruction::PHA(..) => "PHA",
+            Instruction::PHP(..) => "PHP",
+            Instruction::PLA(..) => "PLA",
+            Instruction::PLP(..) => "PLP",
+            Instruction::ROL(..) => "ROL",
+            Instruction::ROR(..) => "ROR",
+            Instruction::RTI(..) => "RTI",
+            Instruction::RTS(..) => "RTS",
+            Instruction::SBC(..) => "SBC",
+            Instruction::SEC(..) => "SEC",
+            Instruction::SED(..) => "SED",
+            Instruction::SEI(..) => "SEI",
+            Instruction::STA(..) => "STA",
+            Instruction::STX(..) => "STX",
+            Instruction::STY(..) => "STY",
+            Instruction::TAX(..) => "TAX",
+            Instruction::TAY(..) => "TAY",
+            Instruction::TSX(..) => "TSX",
+            Instruction::TXA(..) => "TXA",
+            Instruction::TXS(..) => "TXS",
+            Instruction::TYA(..) => "TYA",
+            Instruction::KIL => "KIL",
+        }
+    }
+}
+
 impl Display for Instruction {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        let mut debug = format!("{:?}", self);
-        debug.replace_range(3.., "");
-        write!(f, "{}", debug)
+        write!(f, "{}", self.mnemonic())
     }
 }
 
+#[derive(Clone)]
 struct Accumulator {
     data: u8,
 }
@@ -570,6 +735,7 @@ impl Deref for Accumulator {
     }
 }
 
+#[derive(Clone)]
 pub struct Mos6502 {
     /// Accumulator
     a: Accumulator,
@@ -591,12 +757,26 @@ pub struct Mos6502 {
     address_bus: AddressBus,
     /// Number of cycles remaining in current instruction
     cycles: u32,
+    /// Cycles run since power on, including reset sequences and cycles
+    /// stalled for DMA
+    cycle_count: u64,
     not_irq: bool,
+    /// The I flag as the IRQ poll at the end of the last instruction saw it.
+    /// CLI, SEI and PLP change I after the poll, so they are a step behind.
+    polled_irq_disable: bool,
     not_nmi: bool,
+    /// Set during a BRK or IRQ sequence, which an NMI can take over until it
+    /// fetches its vector.
+    hijackable_interrupt: bool,
     #[allow(dead_code)]
     not_set_overflow: bool,
     not_reset: bool,
     bus: Bus,
+    breakpoints: HashSet<u16>,
+    /// Set when the CPU has stopped at a breakpoint, until it's taken
+    at_breakpoint: bool,
+    /// The breakpoint last stopped at, which the next fetch runs past
+    stopped_at: Option<u16>,
 }
 
 enum IndexRegister {
@@ -605,7 +785,10 @@ enum IndexRegister {
 }
 
 impl Mos6502 {
-    /// Initializes a new `Mos6502` processor emulator.
+    /// Initializes a new `Mos6502` processor emulator in its power-on state.
+    ///
+    /// A, X and Y start at zero and S at 0x00, so the power-up reset
+    /// leaves the stack pointer at 0xFD.
     pub fn new() -> Self {
         Mos6502 {
             a: Accumulator::new(),
@@ -613,16 +796,22 @@ impl Mos6502 {
             x: 0,
             y: 0,
             pc: ProgramCounter::new(),
-            s: 0xFF,
+            s: 0x00,
             p: StatusRegister::new(),
             data_bus: DataBus::new(),
             address_bus: AddressBus::new(),
             cycles: 0,
+            cycle_count: 0,
             not_irq: true,
+            polled_irq_disable: false,
             not_nmi: true,
+            hijackable_interrupt: false,
             not_reset: true,
             not_set_overflow: true,
             bus: Bus::new(),
+            breakpoints: HashSet::new(),
+            at_breakpoint: false,
+            stopped_at: None,
         }
     }
 
@@ -630,6 +819,27 @@ impl Mos6502 {
         self.bus.load_cartridge(cartridge)
     }
 
+    pub fn unload_cartridge(&mut self) -> Option<Cartridge> {
+        self.bus.unload_cartridge()
+    }
+
+    /// The number of cycles run since power on. The power-up reset takes 7,
+    /// so the first instruction after it starts on cycle 7.
+    pub fn total_cycles(&self) -> u64 {
+        self.cycle_count
+    }
+
+    /// Whether the last instruction has finished, so the next clock starts
+    /// a new one.
+    pub fn instruction_complete(&self) -> bool {
+        self.cycles == 0
+    }
+
+    /// Counts a cycle in which the CPU is halted, e.g. during OAM DMA.
+    pub fn stall(&mut self) {
+        self.cycle_count += 1;
+    }
+
     pub fn get_bus(&self) -> &Bus {
         &self.bus
     }
@@ -642,6 +852,11 @@ impl Mos6502 {
         self.bus.cpu_read(address)
     }
 
+    /// Reads a byte without side effects, for inspecting memory.
+    pub fn cpu_peek(&self, address: u16) -> u8 {
+        self.bus.cpu_peek(address)
+    }
+
     pub fn cpu_write(&mut self, address: u16, data: u8) {
         self.bus.cpu_write(address, data)
     }
@@ -659,6 +874,42 @@ impl Mos6502 {
         self.not_irq = false;
     }
 
+    /// Sets the level of the IRQ line. IRQ is level triggered, so it keeps
+    /// being taken for as long as it is asserted and not masked.
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.not_irq = !asserted;
+    }
+
+    #[allow(dead_code)]
+    pub fn set_stack_pointer(&mut self, s: u8) {
+        self.s = s;
+    }
+
+    pub fn get_program_counter(&self) -> u16 {
+        self.pc.read()
+    }
+
+    /// Starts the subroutine at `address` with A and X set, as if a JSR
+    /// had been made to it from just before `return_address`, so that its
+    /// RTS lands there. This is how a music player calls into an NSF tune.
+    pub fn call_subroutine(&mut self, address: u16, a: u8, x: u8, return_address: u16) {
+        let pushed = return_address.wrapping_sub(1);
+
+        self.write_address(0x01, self.s);
+        self.data_bus.write((pushed >> 8) as u8);
+        self.write();
+        self.s = self.s.wrapping_sub(1);
+
+        self.write_address(0x01, self.s);
+        self.data_bus.write(pushed as u8);
+        self.write();
+        self.s = self.s.wrapping_sub(1);
+
+        self.a.write(a);
+        self.x = x;
+        self.pc.write(address);
+    }
+
     pub fn ppu_clock(&mut self, nmi_enable: &mut bool) -> bool {
         self.bus.ppu_clock(nmi_enable)
     }
@@ -679,6 +930,17 @@ impl Mos6502 {
             .cpu_write(self.address_bus.address(), self.data_bus.read());
     }
 
+    /// Writes back the result on the data bus the way read-modify-write
+    /// instructions do, which write the unmodified `operand` first while
+    /// they work out the result. Mapper registers see both writes.
+    fn write_modified(&mut self, operand: u8) {
+        let result = self.data_bus.read();
+        self.data_bus.write(operand);
+        self.write();
+        self.data_bus.write(result);
+        self.write();
+    }
+
     /// Runs the processor for a single clock cycle.
     ///
     /// Really, it does everything in one go on the
@@ -687,25 +949,49 @@ impl Mos6502 {
     ///
     /// Returns true if the instruction is complete.
     pub fn clock(&mut self) -> bool {
+        self.cycle_count += 1;
+
+        // An NMI during the first four cycles of a BRK or IRQ sends it to the
+        // NMI vector instead. A BRK still leaves the B flag set on the stack.
+        if self.hijackable_interrupt && self.cycles >= 4 && !self.not_nmi {
+            self.load_vector(NMI_VECTOR);
+            self.not_nmi = true;
+            self.hijackable_interrupt = false;
+        }
+
         if self.cycles == 0 {
+            self.hijackable_interrupt = false;
+
             if !self.not_nmi {
-                self.interrupt(7, 0, 0xFFFB, false, false);
+                self.interrupt(7, 0, NMI_VECTOR, false, false);
                 // Assume NMI should end after reset is complete
                 self.not_nmi = true;
             } else if !self.not_reset {
-                self.interrupt(6, 0, 0xFFFD, true, false);
-                // Set stack pointer to 0xFD to mimic reset
-                self.s = 0xFD;
+                // The three pushes are suppressed, but S is still decremented.
+                self.interrupt(7, 0, RESET_VECTOR, true, false);
                 // Assume that reset should end after reset is complete
                 self.not_reset = true;
-            } else if !self.not_irq && !self.p.irq_disable {
-                self.interrupt(7, 0, 0xFFFF, false, false);
+            } else if !self.not_irq && !self.polled_irq_disable {
+                self.interrupt(7, 0, IRQ_VECTOR, false, false);
                 // Assume that IRQ should end after interrupt is complete
                 self.not_irq = true;
             } else {
                 // No interrupt, execute instruction like normal.
-                self.read_instruction();
+                let irq_disable = self.p.irq_disable;
+                if !self.read_instruction() {
+                    // Stopped at a breakpoint, so this clock does nothing
+                    // and the next one fetches the instruction.
+                    self.cycle_count -= 1;
+                    return true;
+                }
                 self.execute_instruction();
+
+                self.polled_irq_disable = match self.instruction_register.decode_instruction() {
+                    Instruction::CLI(..) | Instruction::SEI(..) | Instruction::PLP(..) => {
+                        irq_disable
+                    }
+                    _ => self.p.irq_disable,
+                };
             }
         }
 
@@ -713,6 +999,86 @@ impl Mos6502 {
         self.cycles == 0
     }
 
+    /// Runs up to `max_instructions` instructions, returning one trace line per
+    /// instruction with the state before it executes, in the form
+    /// `C000  4C  A:00 X:00 Y:00 P:24 SP:FD CYC:7`.
+    #[cfg(test)]
+    pub fn run_with_trace(&mut self, max_instructions: usize) -> Vec<String> {
+        let mut trace = Vec::with_capacity(max_instructions);
+
+        for _ in 0..max_instructions {
+            trace.push(self.trace_line());
+            while !self.clock() {}
+        }
+
+        trace
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    /// Whether the CPU has stopped at a breakpoint since this was last
+    /// called.
+    pub fn take_breakpoint_hit(&mut self) -> bool {
+        std::mem::take(&mut self.at_breakpoint)
+    }
+
+    /// Runs until the next instruction to be fetched is at a breakpoint,
+    /// or until `max_cycles` cycles have elapsed.
+    ///
+    /// Returns true if a breakpoint was hit. The instruction at the
+    /// breakpoint has not run yet, so calling this again continues past it.
+    #[cfg(test)]
+    pub fn clock_until_break(&mut self, max_cycles: u64) -> bool {
+        for _ in 0..max_cycles {
+            self.clock();
+
+            if self.take_breakpoint_hit() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Disassembles `n` instructions starting at the program counter.
+    ///
+    /// 6502 code can't be reliably decoded backwards, so this only looks ahead.
+    pub fn disassemble_around_pc(&self, n: usize) -> Vec<(u16, String)> {
+        let pc = self.pc.read();
+        // No instruction is longer than three bytes.
+        let bytes: Vec<u8> = (0..n * 3)
+            .map(|offset| self.cpu_peek(pc.wrapping_add(offset as u16)))
+            .collect();
+
+        let mut lines = disassembler::disassemble(&bytes, pc);
+        lines.truncate(n);
+        lines
+    }
+
+    /// The state before the next instruction, as a line of `run_with_trace`.
+    pub fn trace_line(&self) -> String {
+        let pc = self.pc.read();
+
+        // The B flag only exists on the stack, so leave it out like nestest does.
+        format!(
+            "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            pc,
+            self.cpu_peek(pc),
+            self.a.read(),
+            self.x,
+            self.y,
+            self.p.get() & !0x10,
+            self.s,
+            self.cycle_count
+        )
+    }
+
     fn fetch_next_byte(&mut self) -> u8 {
         self.pc.increment();
         self.address_bus
@@ -904,6 +1270,9 @@ impl Mos6502 {
         self.pc.write(pc);
     }
 
+    /// Pushes the return address and status, then jumps through the
+    /// pointer at `interrupt_vector` (low byte) and the address after it
+    /// (high byte).
     fn interrupt(
         &mut self,
         cycles: u32,
@@ -923,23 +1292,22 @@ impl Mos6502 {
         // A reset suppresses writes to memory.
         if !suppress_writes {
             self.write_address(0x01, self.s);
-            self.s = self.s.wrapping_sub(1);
             self.data_bus.write(high);
             self.write();
         }
+        self.s = self.s.wrapping_sub(1);
 
         // A reset suppresses writes to memory.
         if !suppress_writes {
             self.write_address(0x01, self.s);
-            self.s = self.s.wrapping_sub(1);
             self.data_bus.write(low);
             self.write();
         }
+        self.s = self.s.wrapping_sub(1);
 
         // A reset suppresses writes to memory.
         if !suppress_writes {
             self.write_address(0x01, self.s);
-            self.s = self.s.wrapping_sub(1);
             let mut p = self.p.get();
 
             // The B flag should be set if this interrupt is from a BRK instruction,
@@ -953,23 +1321,30 @@ impl Mos6502 {
             self.data_bus.write(p);
             self.write();
         }
+        self.s = self.s.wrapping_sub(1);
 
+        self.load_vector(interrupt_vector);
+        self.p.irq_disable = true;
+        self.polled_irq_disable = true;
+        self.hijackable_interrupt = interrupt_vector == IRQ_VECTOR;
+    }
+
+    fn load_vector(&mut self, interrupt_vector: u16) {
+        // The vector is stored little-endian, low byte first.
         let vector_high = ((interrupt_vector & 0xFF00) >> 8) as u8;
         let vector_low = (interrupt_vector & 0xFF) as u8;
 
         self.write_address(vector_high, vector_low);
         self.read();
-        self.pc.write_high(self.data_bus.read());
+        self.pc.write_low(self.data_bus.read());
 
-        let interrupt_vector = interrupt_vector.wrapping_sub(1);
+        let interrupt_vector = interrupt_vector.wrapping_add(1);
         let vector_high = ((interrupt_vector & 0xFF00) >> 8) as u8;
         let vector_low = (interrupt_vector & 0xFF) as u8;
 
         self.write_address(vector_high, vector_low);
         self.read();
-        self.pc.write_low(self.data_bus.read());
-
-        self.p.irq_disable = true;
+        self.pc.write_high(self.data_bus.read());
     }
 
     pub fn add_with_carry(&mut self) {
@@ -1011,11 +1386,22 @@ impl Mos6502 {
         self.p.overflow = ((accumulator_data ^ sum) & (!bus_data ^ sum) & 0x80) == 0x80
     }
 
-    fn read_instruction(&mut self) {
+    /// Fetches the next opcode. Returns false without fetching the first
+    /// time the fetch is from a breakpoint.
+    fn read_instruction(&mut self) -> bool {
+        let pc = self.pc.read();
+        if self.breakpoints.contains(&pc) && self.stopped_at != Some(pc) {
+            self.stopped_at = Some(pc);
+            self.at_breakpoint = true;
+            return false;
+        }
+        self.stopped_at = None;
+
         self.address_bus
             .write(self.pc.read_high(), self.pc.read_low());
         self.read();
         self.instruction_register.write(self.data_bus.read());
+        true
     }
 
     fn execute_instruction(&mut self) {
@@ -1058,7 +1444,7 @@ impl Mos6502 {
                 if mode == AddressingMode::Accumulator {
                     self.a.write(self.data_bus.read());
                 } else {
-                    self.write();
+                    self.write_modified(operand);
                 }
             }
             Instruction::BCC(mode, _, cycles) => self.branch(!self.p.carry, mode, cycles),
@@ -1076,7 +1462,9 @@ impl Mos6502 {
             Instruction::BNE(mode, _, cycles) => self.branch(!self.p.zero, mode, cycles),
             Instruction::BPL(mode, _, cycles) => self.branch(!self.p.negative, mode, cycles),
             Instruction::BRK(_, bytes, cycles) => {
-                self.interrupt(cycles, bytes, 0xFFFF, false, true);
+                self.interrupt(cycles, bytes, IRQ_VECTOR, false, true);
+                // Subtract one to counteract the standard PC increment.
+                self.pc.write(self.pc.read().wrapping_sub(1));
             }
             Instruction::BVC(mode, _, cycles) => self.branch(!self.p.overflow, mode, cycles),
             Instruction::BVS(mode, _, cycles) => self.branch(self.p.overflow, mode, cycles),
@@ -1108,7 +1496,7 @@ impl Mos6502 {
                 let result = self.increment(memory, NEGATIVE_ONE, cycles);
 
                 self.data_bus.write(result);
-                self.write();
+                self.write_modified(memory);
             }
             Instruction::DEX(_, _, cycles) => {
                 self.x = self.increment(self.x, NEGATIVE_ONE, cycles);
@@ -1134,7 +1522,7 @@ impl Mos6502 {
 
                 let result = self.increment(operand, 1, cycles);
                 self.data_bus.write(result);
-                self.write();
+                self.write_modified(operand);
             }
             Instruction::INX(_, _, cycles) => {
                 self.x = self.increment(self.x, 1, cycles);
@@ -1212,11 +1600,17 @@ impl Mos6502 {
                 if mode == AddressingMode::Accumulator {
                     self.a.write(self.data_bus.read());
                 } else {
-                    self.write();
+                    self.write_modified(operand);
                 }
             }
-            Instruction::NOP(_, _, cycles) => {
+            Instruction::NOP(mode, _, cycles) => {
                 self.cycles = cycles;
+                self.do_addressing_mode(mode);
+
+                // The unofficial NOPs with an operand read it and discard it.
+                if mode != AddressingMode::Implied {
+                    self.read();
+                }
             }
             Instruction::ORA(mode, _, cycles) => {
                 self.cycles = cycles;
@@ -1288,7 +1682,7 @@ impl Mos6502 {
                 if mode == AddressingMode::Accumulator {
                     self.a.write(self.data_bus.read());
                 } else {
-                    self.write();
+                    self.write_modified(operand);
                 }
             }
             Instruction::ROR(mode, _, cycles) => {
@@ -1312,7 +1706,7 @@ impl Mos6502 {
                 if mode == AddressingMode::Accumulator {
                     self.a.write(self.data_bus.read());
                 } else {
-                    self.write();
+                    self.write_modified(operand);
                 }
             }
             Instruction::RTI(_, _, cycles) => {
@@ -1440,9 +1834,59 @@ impl Mos6502 {
 
 #[cfg(test)]
 mod tests {
-    use super::Mos6502;
+    use super::{Instruction, InstructionRegister, Mos6502};
+    #[cfg(feature = "assembler")]
     use crate::assembler::{self, AssemblerError};
+    use crate::cartridge::Cartridge;
+    use std::fs;
+    use std::path::Path;
+
+    /// Reduces a nestest.log line to the fields in a `run_with_trace` line.
+    fn normalize_nestest_line(line: &str) -> String {
+        let registers_start = line.find("A:").expect("registers in nestest line");
+        let registers_end = line.find(" PPU").expect("PPU position in nestest line");
+        let cycles_start = line.find("CYC:").expect("cycles in nestest line");
+        format!(
+            "{}  {}  {} {}",
+            &line[0..4],
+            &line[6..8],
+            &line[registers_start..registers_end],
+            &line[cycles_start..]
+        )
+    }
+
+    /// Compares a trace against the expected nestest.log lines, reporting the
+    /// first divergence along with the lines leading up to it.
+    fn assert_trace_matches(trace: &[String], expected: &str) {
+        let expected: Vec<String> = expected
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(normalize_nestest_line)
+            .collect();
+
+        for (line_number, (actual, expected)) in trace.iter().zip(expected.iter()).enumerate() {
+            if actual != expected {
+                let context_start = line_number.saturating_sub(5);
+                panic!(
+                    "trace diverges at line {}:\n{}\nexpected: {}\nactual:   {}",
+                    line_number + 1,
+                    trace[context_start..line_number].join("\n"),
+                    expected,
+                    actual
+                );
+            }
+        }
+
+        assert!(
+            trace.len() >= expected.len(),
+            "trace ended after {} of {} lines",
+            trace.len(),
+            expected.len()
+        );
+    }
 
+    #[cfg(feature = "assembler")]
     fn run_program(program: &str) -> Mos6502 {
         match assembler::run_program(program) {
             Ok(cpu) => cpu,
@@ -1460,11 +1904,15 @@ mod tests {
                     AssemblerError::InvalidValue(line) => {
                         panic!("Invalid immediate value at line {}", line)
                     }
+                    AssemblerError::ProgramTooLarge(size) => {
+                        panic!("Program too large ({} bytes)", size)
+                    }
                 };
             }
         }
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn adc() {
         let mut cpu = run_program(
@@ -1490,6 +1938,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF) & 0x01, 0x01, "carry bit set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn and() {
         let mut cpu = run_program(
@@ -1520,6 +1969,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF) & 0x02, 0x00, "zero bit not set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn asl() {
         let mut cpu = run_program(
@@ -1537,6 +1987,7 @@ mod tests {
         assert!(status & 0x01 == 0x01, "carry bit set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bcc() {
         let mut cpu = run_program(
@@ -1564,6 +2015,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "branch taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bcs() {
         let mut cpu = run_program(
@@ -1591,6 +2043,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFA, "branch not taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn beq() {
         let mut cpu = run_program(
@@ -1619,6 +2072,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "branch not taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bit() {
         let mut cpu = run_program(
@@ -1637,6 +2091,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x02, "zero flag set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bmi() {
         let mut cpu = run_program(
@@ -1666,6 +2121,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0x02, "branch not taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bne() {
         let mut cpu = run_program(
@@ -1695,6 +2151,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0x01, "branch taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bpl() {
         let mut cpu = run_program(
@@ -1724,6 +2181,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0x03, "branch not taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn brk() {
         let mut cpu = run_program(
@@ -1757,6 +2215,7 @@ mod tests {
         );
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bvc() {
         let mut cpu = run_program(
@@ -1784,6 +2243,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0x06, "branch taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn bvs() {
         let mut cpu = run_program(
@@ -1811,6 +2271,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "branch taken");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn cmp() {
         let mut cpu = run_program(
@@ -1850,6 +2311,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x00, "zero flag not set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn cpx() {
         let mut cpu = run_program(
@@ -1889,6 +2351,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x00, "zero flag not set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn cpy() {
         let mut cpu = run_program(
@@ -1928,6 +2391,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x00, "zero flag not set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn dec() {
         let mut cpu = run_program(
@@ -1963,6 +2427,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn dex() {
         let mut cpu = run_program(
@@ -2000,6 +2465,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn dey() {
         let mut cpu = run_program(
@@ -2037,6 +2503,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn eor() {
         let mut cpu = run_program(
@@ -2064,6 +2531,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF) & 0x02, 0x02, "zero bit set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn inc() {
         let mut cpu = run_program(
@@ -2099,6 +2567,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn inx() {
         let mut cpu = run_program(
@@ -2136,6 +2605,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn iny() {
         let mut cpu = run_program(
@@ -2173,6 +2643,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn jmp() {
         let mut cpu = run_program(
@@ -2189,6 +2660,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0x00, "load and store jumped over");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn jsr() {
         let mut cpu = run_program(
@@ -2207,6 +2679,56 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FE), 0x02, "low byte = 0x02");
     }
 
+    #[test]
+    fn rti() {
+        let mut cpu = Mos6502::new();
+        cpu.cpu_write(0x0000, 0x40); // RTI
+
+        // P, then PCL, then PCH, as an interrupt leaves them.
+        cpu.cpu_write(0x01FD, 0xC3);
+        cpu.cpu_write(0x01FE, 0x34);
+        cpu.cpu_write(0x01FF, 0x12);
+        cpu.set_stack_pointer(0xFC);
+
+        while !cpu.clock() {}
+
+        assert_eq!(cpu.pc.read(), 0x1234, "PC is the pushed address");
+        assert_eq!(cpu.p.get(), 0xE3, "P is the pushed status");
+        assert_eq!(cpu.s, 0xFF, "three bytes pulled");
+    }
+
+    #[test]
+    fn rts() {
+        let mut cpu = Mos6502::new();
+        cpu.cpu_write(0x0000, 0x60); // RTS
+
+        // JSR pushes the address of its last byte.
+        cpu.cpu_write(0x01FE, 0x33);
+        cpu.cpu_write(0x01FF, 0x12);
+        cpu.set_stack_pointer(0xFD);
+
+        while !cpu.clock() {}
+
+        assert_eq!(cpu.pc.read(), 0x1234, "PC is one past the pushed address");
+        assert_eq!(cpu.s, 0xFF, "two bytes pulled");
+
+        // JSR $0010 then RTS lands on the instruction after the JSR.
+        let mut cpu = Mos6502::new();
+        cpu.set_stack_pointer(0xFF);
+        for (offset, byte) in [0x20, 0x10, 0x00].iter().enumerate() {
+            cpu.cpu_write(offset as u16, *byte);
+        }
+        cpu.cpu_write(0x0010, 0x60);
+
+        for _ in 0..2 {
+            while !cpu.clock() {}
+        }
+
+        assert_eq!(cpu.pc.read(), 0x0003, "returned after the JSR");
+        assert_eq!(cpu.s, 0xFF, "stack balanced");
+    }
+
+    #[cfg(feature = "assembler")]
     #[test]
     fn lsr() {
         let mut cpu = run_program(
@@ -2240,6 +2762,7 @@ mod tests {
         assert_eq!(status & 0x80, 0x00, "negative bit unset");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn ora() {
         let mut cpu = run_program(
@@ -2274,6 +2797,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x02, "result zero");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn pha() {
         let mut cpu = run_program(
@@ -2286,6 +2810,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF), 0xFF, "accumulator pushed on stack");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn pla() {
         let mut cpu = run_program(
@@ -2301,6 +2826,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF), 0xFF, "accumulator pulled from stack");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn rol() {
         let mut cpu = run_program(
@@ -2329,6 +2855,45 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn rotate_16_bit() {
+        // Rotate $8103 right through carry twice, high byte first, then
+        // back left again, low byte first.
+        let mut cpu = run_program(
+            "
+            LDA #$03
+            STA $F0
+            LDA #$81
+            STA $F1
+            CLC
+            ROR $F1
+            ROR $F0
+            ROR $F1
+            ROR $F0
+            PHP
+            LDA $F0
+            STA $F2
+            LDA $F1
+            STA $F3
+            ROL $F0
+            ROL $F1
+            ROL $F0
+            ROL $F1
+            PHP
+        ",
+        );
+
+        assert_eq!(cpu.cpu_read(0xF3), 0xA0, "high byte rotated right");
+        assert_eq!(cpu.cpu_read(0xF2), 0x40, "low byte rotated right");
+        assert_eq!(cpu.cpu_read(0x01FF) & 0x01, 0x01, "bit 1 left in carry");
+
+        assert_eq!(cpu.cpu_read(0xF1), 0x81, "high byte rotated back");
+        assert_eq!(cpu.cpu_read(0xF0), 0x03, "low byte rotated back");
+        assert_eq!(cpu.cpu_read(0x01FE) & 0x01, 0x00, "carry clear again");
+    }
+
+    #[cfg(feature = "assembler")]
     #[test]
     fn ror() {
         let mut cpu = run_program(
@@ -2357,6 +2922,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0xFF), 0xFF, "correct result");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn tax() {
         let mut cpu = run_program(
@@ -2372,6 +2938,7 @@ mod tests {
         assert_eq!(cpu.cpu_read(0x01FF) & 0x80, 0x80, "negative bit set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn sbc() {
         let mut cpu = run_program(
@@ -2405,6 +2972,7 @@ mod tests {
         assert_eq!(status & 0x02, 0x00, "zero bit not set");
     }
 
+    #[cfg(feature = "assembler")]
     #[test]
     fn irq() {
         let mut cpu = Mos6502::new();
@@ -2433,9 +3001,9 @@ mod tests {
             location += 1;
         }
 
-        // Set interrupt vector to start at RTI
+        // Set interrupt vector to start at STX
         cpu.get_bus_mut().cpu_write(0xFFFF, 0x00); // Address high
-        cpu.get_bus_mut().cpu_write(0xFFFE, 0x06); // Address low
+        cpu.get_bus_mut().cpu_write(0xFFFE, 0x07); // Address low
 
         // Do loop for a while
         for _ in 0..20 {
@@ -2452,4 +3020,413 @@ mod tests {
 
         assert_ne!(cpu.cpu_read(0x00FF), 0, "data stored in 0xFF");
     }
+
+    /// Runs `program` from $0000 with an IRQ handler at $0200 that stores X
+    /// in $FF. The IRQ line is asserted on the first cycle of instruction
+    /// number `irq_at`, and the value in $FF is returned after `steps`
+    /// instructions or interrupts.
+    #[cfg(feature = "assembler")]
+    fn run_with_irq(program: &str, irq_at: usize, steps: usize) -> u8 {
+        let mut cpu = Mos6502::new();
+
+        let program = assembler::assemble_program(program).expect("Encountered assembler error");
+        for (location, byte) in program.iter().flatten().enumerate() {
+            cpu.cpu_write(location as u16, *byte);
+        }
+
+        // STX $FF, then loop forever with JMP $0202.
+        for (offset, byte) in [0x86, 0xFF, 0x4C, 0x02, 0x02].iter().enumerate() {
+            cpu.cpu_write(0x0200 + offset as u16, *byte);
+        }
+        cpu.cpu_write(0xFFFE, 0x00);
+        cpu.cpu_write(0xFFFF, 0x02);
+        cpu.cpu_write(0x00FF, 0xAA);
+
+        for step in 0..steps {
+            if step == irq_at {
+                cpu.clock();
+                cpu.set_irq(true);
+            }
+            while !cpu.clock() {}
+        }
+
+        cpu.cpu_read(0x00FF)
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn cli_sei_delay() {
+        // I is cleared by CLI, but the IRQ waits for one more instruction.
+        let program = "
+            SEI
+            CLI
+            INX
+            INX
+            INX
+        ";
+        assert_eq!(run_with_irq(program, 1, 5), 0x01, "IRQ taken after the INX");
+
+        // An IRQ arriving during SEI is still taken, right after the SEI.
+        let program = "
+            CLI
+            SEI
+            INX
+            INX
+        ";
+        assert_eq!(run_with_irq(program, 1, 4), 0x00, "IRQ taken after the SEI");
+    }
+
+    #[test]
+    fn brk_nmi_hijack() {
+        let run_brk = |nmi_at: u32| {
+            let mut cpu = Mos6502::new();
+            cpu.cpu_write(0x0000, 0x00); // BRK
+            cpu.cpu_write(0xFFFA, 0x00); // NMI vector, $0300
+            cpu.cpu_write(0xFFFB, 0x03);
+            cpu.cpu_write(0xFFFE, 0x00); // IRQ/BRK vector, $0200
+            cpu.cpu_write(0xFFFF, 0x02);
+
+            for cycle in 1..=7 {
+                if cycle == nmi_at {
+                    cpu.nmi();
+                }
+                cpu.clock();
+            }
+
+            (cpu.pc.read(), cpu.cpu_read(0x01FE) & 0x10, cpu.not_nmi)
+        };
+
+        for nmi_at in 2..=4 {
+            assert_eq!(
+                run_brk(nmi_at),
+                (0x0300, 0x10, true),
+                "NMI on cycle {} takes the NMI vector with B set",
+                nmi_at
+            );
+        }
+
+        for nmi_at in 5..=7 {
+            assert_eq!(
+                run_brk(nmi_at),
+                (0x0200, 0x10, false),
+                "NMI on cycle {} waits until after the BRK",
+                nmi_at
+            );
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn cycle_count() {
+        let mut cpu = Mos6502::new();
+        let program = assembler::assemble_program(
+            "
+            LDA #$01
+            STA $10
+            INC $10
+            LDX $10
+            NOP
+        ",
+        )
+        .expect("Encountered assembler error");
+
+        for (location, byte) in program.iter().flatten().enumerate() {
+            cpu.cpu_write(0x8000 + location as u16, *byte);
+        }
+        cpu.cpu_write(0xFFFC, 0x00);
+        cpu.cpu_write(0xFFFD, 0x80);
+
+        cpu.reset();
+        while !cpu.clock() {}
+        assert_eq!(cpu.total_cycles(), 7, "power-up reset");
+
+        for _ in 0..5 {
+            while !cpu.clock() {}
+        }
+        assert_eq!(cpu.total_cycles(), 7 + 2 + 3 + 5 + 3 + 2);
+    }
+
+    #[test]
+    fn read_modify_write() {
+        let mut cpu = Mos6502::new();
+        // INC $10, ASL $0300, ROR A, DEC $0300,X
+        let program = [0xE6, 0x10, 0x0E, 0x00, 0x03, 0x6A, 0xDE, 0x00, 0x03];
+        for (offset, byte) in program.iter().enumerate() {
+            cpu.cpu_write(0x8000 + offset as u16, *byte);
+        }
+        cpu.cpu_write(0xFFFC, 0x00);
+        cpu.cpu_write(0xFFFD, 0x80);
+        cpu.cpu_write(0x0010, 0x05);
+        cpu.cpu_write(0x0300, 0x41);
+
+        cpu.reset();
+        while !cpu.clock() {}
+        cpu.get_bus_mut().take_writes();
+
+        let mut next_writes = || {
+            while !cpu.clock() {}
+            cpu.get_bus_mut().take_writes()
+        };
+        assert_eq!(next_writes(), vec![(0x0010, 0x05), (0x0010, 0x06)], "INC");
+        assert_eq!(next_writes(), vec![(0x0300, 0x41), (0x0300, 0x82)], "ASL");
+        assert_eq!(next_writes(), vec![], "ROR A");
+        assert_eq!(next_writes(), vec![(0x0300, 0x82), (0x0300, 0x81)], "DEC");
+    }
+
+    #[test]
+    fn unofficial_nops() {
+        let nops = [
+            (&[0x80, 0x82, 0x89, 0xC2, 0xE2][..], 2, 2),
+            (&[0x04, 0x44, 0x64][..], 2, 3),
+            (&[0x14, 0x34, 0x54, 0x74, 0xD4, 0xF4][..], 2, 4),
+            (&[0x0C][..], 3, 4),
+            (&[0x1C, 0x3C, 0x5C, 0x7C, 0xDC, 0xFC][..], 3, 4),
+            (&[0x1A, 0x3A, 0x5A, 0x7A, 0xDA, 0xFA][..], 1, 2),
+        ];
+
+        for (opcodes, bytes, cycles) in nops.iter() {
+            for opcode in opcodes.iter() {
+                let mut instruction_register = InstructionRegister::new();
+                instruction_register.write(*opcode);
+
+                match instruction_register.decode_instruction() {
+                    Instruction::NOP(_, nop_bytes, nop_cycles) => assert_eq!(
+                        (nop_bytes, nop_cycles),
+                        (*bytes, *cycles),
+                        "${:02X} size and timing",
+                        opcode
+                    ),
+                    instruction => panic!("${:02X} decoded as {:?}", opcode, instruction),
+                }
+            }
+        }
+
+        let run_nop_absolute_x = |x: u8| {
+            let mut cpu = Mos6502::new();
+            // NOP $02F0,X
+            cpu.cpu_write(0x0000, 0x1C);
+            cpu.cpu_write(0x0001, 0xF0);
+            cpu.cpu_write(0x0002, 0x02);
+            cpu.x = x;
+
+            let mut cycles = 1;
+            while !cpu.clock() {
+                cycles += 1;
+            }
+            (cpu.pc.read(), cycles)
+        };
+
+        assert_eq!(run_nop_absolute_x(0x0F), (0x0003, 4), "same page");
+        assert_eq!(run_nop_absolute_x(0x10), (0x0003, 5), "page crossed");
+    }
+
+    #[test]
+    fn interrupt_vectors() {
+        let new_cpu = || {
+            let mut cpu = Mos6502::new();
+            // Each vector points somewhere different.
+            for (offset, vector) in (0xFFFA..=0xFFFF).enumerate() {
+                cpu.cpu_write(vector, 0x10 + offset as u8);
+            }
+            cpu
+        };
+
+        let mut cpu = new_cpu();
+        cpu.nmi();
+        cpu.clock();
+        assert_eq!(cpu.pc.read(), 0x1110, "NMI through $FFFA/$FFFB");
+
+        let mut cpu = new_cpu();
+        cpu.reset();
+        cpu.clock();
+        assert_eq!(cpu.pc.read(), 0x1312, "reset through $FFFC/$FFFD");
+
+        let mut cpu = new_cpu();
+        cpu.p.irq_disable = false;
+        cpu.polled_irq_disable = false;
+        cpu.irq();
+        cpu.clock();
+        assert_eq!(cpu.pc.read(), 0x1514, "IRQ through $FFFE/$FFFF");
+
+        // $0000 holds a BRK.
+        let mut cpu = new_cpu();
+        cpu.clock();
+        assert_eq!(cpu.pc.read(), 0x1514, "BRK through $FFFE/$FFFF");
+    }
+
+    #[test]
+    fn jmp_indirect_page_wrap() {
+        let mut cpu = Mos6502::new();
+        // JMP ($30FF)
+        cpu.cpu_write(0x0000, 0x6C);
+        cpu.cpu_write(0x0001, 0xFF);
+        cpu.cpu_write(0x0002, 0x30);
+
+        cpu.cpu_write(0x30FF, 0x80); // Address low
+        cpu.cpu_write(0x3000, 0x12); // Address high, wrapped within the page
+        cpu.cpu_write(0x3100, 0x56); // Address high, if the page were crossed
+
+        while !cpu.clock() {}
+
+        assert_eq!(cpu.pc.read(), 0x1280, "high byte read from $3000");
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut cpu = Mos6502::new();
+        // LDX #$05; DEX; BNE -3; LDY #$07
+        for (offset, byte) in [0xA2, 0x05, 0xCA, 0xD0, 0xFD, 0xA0, 0x07]
+            .iter()
+            .enumerate()
+        {
+            cpu.cpu_write(offset as u16, *byte);
+        }
+
+        cpu.add_breakpoint(0x0003);
+        assert!(cpu.clock_until_break(1000), "stops at BNE");
+        assert_eq!(cpu.pc.read(), 0x0003);
+        assert_eq!(cpu.x, 0x04, "DEX ran once");
+
+        assert!(cpu.clock_until_break(1000), "stops again on the next loop");
+        assert_eq!(cpu.pc.read(), 0x0003);
+        assert_eq!(cpu.x, 0x03);
+
+        cpu.remove_breakpoint(0x0003);
+        cpu.add_breakpoint(0x0005);
+        assert!(cpu.clock_until_break(1000), "stops after the loop");
+        assert_eq!(cpu.pc.read(), 0x0005);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.y, 0x00, "LDY not run yet");
+
+        cpu.remove_breakpoint(0x0005);
+        assert!(
+            !cpu.clock_until_break(2),
+            "budget elapses without a breakpoint"
+        );
+        assert_eq!(cpu.y, 0x07);
+    }
+
+    #[test]
+    fn disassemble_around_pc() {
+        let mut cpu = Mos6502::new();
+        for (offset, byte) in [0xA2, 0x05, 0xCA, 0xD0, 0xFD].iter().enumerate() {
+            cpu.cpu_write(0x0600 + offset as u16, *byte);
+        }
+        cpu.pc.write(0x0600);
+
+        let lines: Vec<String> = cpu
+            .disassemble_around_pc(3)
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect();
+        assert_eq!(
+            lines,
+            vec!["$0600: LDX #$05", "$0602: DEX", "$0603: BNE $0602"]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut cpu = Mos6502::new();
+
+        // Reset vector points to 0x8123
+        cpu.cpu_write(0xFFFC, 0x23); // Address low
+        cpu.cpu_write(0xFFFD, 0x81); // Address high
+
+        cpu.reset();
+        while !cpu.clock() {}
+
+        assert_eq!(cpu.pc.read(), 0x8123, "PC loaded from reset vector");
+        assert_eq!(cpu.s, 0xFD, "power-up reset leaves S at 0xFD");
+        assert!(cpu.p.irq_disable, "reset sets the interrupt disable flag");
+        assert_eq!(cpu.a.read(), 0x00);
+        assert_eq!(cpu.x, 0x00);
+        assert_eq!(cpu.y, 0x00);
+        assert_eq!(
+            cpu.cpu_read(0x01FD),
+            0x00,
+            "reset does not push to the stack"
+        );
+
+        // A second reset decrements S by another three.
+        cpu.reset();
+        while !cpu.clock() {}
+
+        assert_eq!(cpu.pc.read(), 0x8123);
+        assert_eq!(cpu.s, 0xFA, "S decremented by three on every reset");
+    }
+
+    // Loads nestest into flat RAM, ready to run in automation mode.
+    fn nestest_cpu() -> Mos6502 {
+        let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let nes_test = current_dir.parent().unwrap().join("nestest.nes");
+        let cartridge = Cartridge::new(fs::read(nes_test).unwrap()).unwrap();
+
+        // Mirror the 16 KiB PRG ROM into both halves of the cartridge space.
+        let mut cpu = Mos6502::new();
+        for (offset, byte) in cartridge.prg_rom().iter().enumerate() {
+            cpu.cpu_write(0x8000 + offset as u16, *byte);
+            cpu.cpu_write(0xC000 + offset as u16, *byte);
+        }
+
+        // Automation mode starts at $C000 with the CPU in its reset state,
+        // after the 7 cycles the reset takes.
+        cpu.pc.write(0xC000);
+        cpu.s = 0xFD;
+        cpu.p.set(0x24);
+        cpu.cycle_count = 7;
+        cpu
+    }
+
+    #[test]
+    fn nestest() {
+        let mut cpu = nestest_cpu();
+
+        let trace = cpu.run_with_trace(12);
+        assert_trace_matches(
+            &trace,
+            "
+            C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+            C5F5  A2 00     LDX #$00                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 30 CYC:10
+            C5F7  86 00     STX $00 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 36 CYC:12
+            C5F9  86 10     STX $10 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 45 CYC:15
+            C5FB  86 11     STX $11 = 00                    A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 54 CYC:18
+            C5FD  20 2D C7  JSR $C72D                       A:00 X:00 Y:00 P:26 SP:FD PPU:  0, 63 CYC:21
+            C72D  EA        NOP                             A:00 X:00 Y:00 P:26 SP:FB PPU:  0, 81 CYC:27
+            C72E  38        SEC                             A:00 X:00 Y:00 P:26 SP:FB PPU:  0, 87 CYC:29
+            C72F  B0 04     BCS $C735                       A:00 X:00 Y:00 P:27 SP:FB PPU:  0, 93 CYC:31
+            C735  EA        NOP                             A:00 X:00 Y:00 P:27 SP:FB PPU:  0,102 CYC:34
+            C736  18        CLC                             A:00 X:00 Y:00 P:27 SP:FB PPU:  0,108 CYC:36
+            C737  B0 03     BCS $C73C                       A:00 X:00 Y:00 P:26 SP:FB PPU:  0,114 CYC:38
+        ",
+        );
+
+        // The unofficial opcode tests start at $C6BD. By then, nestest has
+        // stored the result of the official opcode tests in $02 (0 is a pass).
+        for _ in 0..10000 {
+            if cpu.pc.read() == 0xC6BD {
+                break;
+            }
+            while !cpu.clock() {}
+        }
+        assert_eq!(cpu.pc.read(), 0xC6BD, "reached the unofficial opcode tests");
+        assert_eq!(cpu.cpu_read(0x0002), 0x00, "official opcode tests passed");
+    }
+
+    // Compares every line of the official opcode tests, up to $C6BD, with
+    // the log Nintendulator wrote for the same run. The unofficial opcodes
+    // after that aren't implemented.
+    #[test]
+    #[ignore = "needs nestest.log from https://www.qmtpro.com/~nes/misc/, which isn't bundled yet"]
+    fn nestest_log() {
+        let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let log = fs::read_to_string(current_dir.parent().unwrap().join("nestest.log")).unwrap();
+        let expected: Vec<&str> = log
+            .lines()
+            .take_while(|line| !line.starts_with("C6BD"))
+            .collect();
+
+        let trace = nestest_cpu().run_with_trace(expected.len());
+        assert_trace_matches(&trace, &expected.join("\n"));
+    }
 }
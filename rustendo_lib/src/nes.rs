@@ -1,13 +1,154 @@
-use crate::cartridge::Cartridge;
-use crate::controller::Controller;
+use crate::cartridge::{Cartridge, CartridgeInfo};
+use crate::cheats::{Cheat, CheatError};
+use crate::controller::{Controller, Port2};
+use crate::cpu_bus::BusMemory;
+use crate::cpu_ram::Ram;
 use crate::mos6502::Mos6502;
+use crate::palette::PaletteError;
+use crate::png;
+pub use crate::ricoh2c02::PpuWrite;
+use crate::zapper::Zapper;
+use std::borrow::Cow;
+use std::collections::VecDeque;
+
+// Rewind keeps a snapshot per frame, so this many per second of history.
+const FRAMES_PER_SECOND: usize = 60;
+// The number of instructions the trace keeps, about a frame's worth.
+const TRACE_CAPACITY: usize = 0x2000;
+
+#[derive(Debug, PartialEq)]
+pub struct Timeout;
+
+/// What the CPU's 2K of RAM holds at power-on. Real consoles leave it in a
+/// console-specific, mostly repeating state, which some test ROMs rely on.
+pub enum RamInit {
+    Zeroed,
+    /// The bytes repeated across all of RAM.
+    Pattern(Vec<u8>),
+    AllOnes,
+}
+
+/// How many pixels to hide at each edge of the 256x240 screen, the way a
+/// TV's bezel hid the garbage many games leave there.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Overscan {
+    top: u32,
+    bottom: u32,
+    left: u32,
+    right: u32,
+}
+
+impl Overscan {
+    /// Leaves at least one row and column visible, however much is asked
+    /// to be cropped.
+    pub fn new(top: u32, bottom: u32, left: u32, right: u32) -> Self {
+        let top = top.min(0xEF);
+        let left = left.min(0xFF);
+
+        Overscan {
+            top,
+            bottom: bottom.min(0xEF - top),
+            left,
+            right: right.min(0xFF - left),
+        }
+    }
+
+    pub fn none() -> Self {
+        Overscan::new(0, 0, 0, 0)
+    }
+
+    /// The width and height of what's left of the screen.
+    pub fn visible_size(&self) -> (u32, u32) {
+        (
+            0x100 - self.left - self.right,
+            0xF0 - self.top - self.bottom,
+        )
+    }
+
+    /// Crops a 256x240 RGBA framebuffer, only copying it if there's
+    /// anything to crop.
+    pub fn crop<'a>(&self, rgba: &'a [u8]) -> Cow<'a, [u8]> {
+        if *self == Overscan::none() {
+            return Cow::Borrowed(rgba);
+        }
+
+        let (width, height) = self.visible_size();
+        let row_start = self.left as usize * 4;
+        let row_end = row_start + width as usize * 4;
+
+        Cow::Owned(
+            rgba.chunks(0x100 * 4)
+                .skip(self.top as usize)
+                .take(height as usize)
+                .flat_map(|row| &row[row_start..row_end])
+                .copied()
+                .collect(),
+        )
+    }
+}
+
+/// Where the PPU was when the CPU started an instruction.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    pub ppu_scanline: u32,
+    pub ppu_cycle: u32,
+    pub cpu_cycle: u64,
+}
+
+/// Controller input recorded a frame at a time, which plays back the same
+/// from the same starting point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub start: MovieStart,
+    /// Player 1's and player 2's buttons during each frame, as `Button` bits.
+    pub frames: Vec<(u8, u8)>,
+}
+
+/// Where a movie starts from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MovieStart {
+    /// Power-on, before the first frame has run.
+    PowerOn,
+    /// A reset.
+    Reset,
+}
 
 pub struct Nes {
     cpu: Mos6502,
     clocks: u32,
+    frames: u64,
+    dma_cycle: u16,
+    dma_data: Option<u8>,
+    dma_halted: bool,
+    rewind_snapshots: VecDeque<Snapshot>,
+    rewind_capacity: usize,
+    frame_callback: Option<FrameCallback>,
+    nmi_callback: Option<Box<dyn FnMut()>>,
+    overscan: Overscan,
+    trace: Option<VecDeque<TraceEntry>>,
+    recording: Option<Movie>,
+    // The movie being played back and the frame it's up to.
+    playback: Option<(Movie, usize)>,
+}
+
+/// Called with the RGBA framebuffer at the end of each frame.
+pub type FrameCallback = Box<dyn FnMut(&[u8])>;
+
+/// The whole machine as it was at the end of a frame. RAM, OAM and the
+/// framebuffer are kept apart from the rest of the machine in `memory`.
+struct Snapshot {
+    // Everything but memory, which is left empty.
+    cpu: Mos6502,
+    // For the newest snapshot, all of memory. For older ones, only what
+    // changed since, as a `diff` against the next snapshot's memory.
+    memory: Vec<u8>,
+    clocks: u32,
+    frames: u64,
     dma_cycle: u16,
-    dma_data: u8,
-    dma_dummy: bool,
+    dma_data: Option<u8>,
+    dma_halted: bool,
 }
 
 impl Nes {
@@ -15,18 +156,120 @@ impl Nes {
         Nes {
             cpu: Mos6502::new(),
             clocks: 0,
+            frames: 0,
             dma_cycle: 0,
-            dma_data: 0,
-            dma_dummy: true,
+            dma_data: None,
+            dma_halted: false,
+            rewind_snapshots: VecDeque::new(),
+            rewind_capacity: 0,
+            frame_callback: None,
+            nmi_callback: None,
+            overscan: Overscan::none(),
+            trace: None,
+            recording: None,
+            playback: None,
         }
     }
 
+    /// A machine whose output depends only on the cartridge and the inputs
+    /// it's given, so two of them produce byte-identical framebuffers. RAM
+    /// powers on zeroed and the PPU on an even frame; nothing in the core
+    /// reads the clock or random numbers, and the CPU's breakpoints are only
+    /// looked up, never iterated.
+    pub fn new_deterministic() -> Self {
+        Nes::new_with_ram_init(RamInit::Zeroed)
+    }
+
+    pub fn new_with_ram_init(init: RamInit) -> Self {
+        let ram = match init {
+            RamInit::Zeroed => Ram::new(),
+            RamInit::Pattern(pattern) => Ram::new_with_pattern(&pattern),
+            RamInit::AllOnes => Ram::new_with_pattern(&[0xFF]),
+        };
+
+        let mut nes = Nes::new();
+        nes.cpu.get_bus_mut().set_ram(ram);
+        nes
+    }
+
     pub fn load_cartridge(&mut self, cartridge: Cartridge) {
-        self.cpu.load_cartridge(cartridge)
+        self.cpu.load_cartridge(cartridge);
+        // Rewinding mustn't bring back the previous game.
+        self.rewind_snapshots.clear();
+    }
+
+    /// Ejects the cartridge, freeing its ROM. The PPU is reset so the last
+    /// frame isn't left on screen, and any movie being recorded or played
+    /// is dropped. Another cartridge can be loaded afterwards.
+    pub fn unload_cartridge(&mut self) {
+        self.cpu.unload_cartridge();
+        self.rewind_snapshots.clear();
+        self.recording = None;
+        if self.playback.take().is_some() {
+            self.controller(0).force_buttons(None);
+            self.controller(1).force_buttons(None);
+        }
+        self.reset_ppu();
+    }
+
+    pub fn has_cartridge(&self) -> bool {
+        self.cpu.get_bus().get_ppu().has_cartridge()
+    }
+
+    /// What the loaded cartridge's header says about it.
+    pub fn cartridge_info(&self) -> Option<CartridgeInfo> {
+        self.cpu
+            .get_bus()
+            .get_ppu()
+            .get_cartridge()
+            .map(Cartridge::info)
+    }
+
+    /// Whether the cartridge has battery-backed RAM that has been written
+    /// since `clear_sram_dirty`, so it only needs saving when it changed.
+    pub fn sram_dirty(&self) -> bool {
+        match self.cpu.get_bus().get_ppu().get_cartridge() {
+            Some(cartridge) => cartridge.has_battery() && cartridge.sram_dirty(),
+            None => false,
+        }
+    }
+
+    pub fn clear_sram_dirty(&mut self) {
+        if let Some(cartridge) = self.cpu.get_bus_mut().get_ppu_mut().get_cartridge_mut() {
+            cartridge.clear_sram_dirty();
+        }
+    }
+
+    /// Player `player`'s controller, counting from 0. The second is only
+    /// read while it's plugged into port 2, and the third and fourth only
+    /// through the Four Score.
+    pub fn controller(&mut self, player: usize) -> &mut Controller {
+        self.cpu.get_bus_mut().controller(player)
+    }
+
+    /// Plugs in the Four Score, for games with up to four players.
+    pub fn set_four_score(&mut self, enabled: bool) {
+        self.cpu.get_bus_mut().set_four_score(enabled);
+    }
+
+    /// Plugs a standard controller, the default, or the Zapper into the
+    /// second controller port.
+    pub fn set_port2_device(&mut self, device: Port2) {
+        self.cpu.get_bus_mut().set_port2_device(device);
+    }
+
+    /// The Zapper, which is only read while it's plugged into port 2.
+    pub fn zapper(&mut self) -> &mut Zapper {
+        self.cpu.get_bus_mut().zapper()
+    }
+
+    /// Points the Zapper at pixel (`x`, `y`) of the screen.
+    pub fn zapper_aim(&mut self, x: u8, y: u8) {
+        self.zapper().aim(x, y);
     }
 
-    pub fn controller(&mut self) -> &mut Controller {
-        self.cpu.get_bus_mut().controller()
+    pub fn zapper_trigger(&mut self, down: bool) {
+        self.zapper().set_trigger(down);
     }
 
     pub fn clock(&mut self) -> bool {
@@ -37,11 +280,23 @@ impl Nes {
 
         // CPU runs at 1/12 the master clock speed, 3x as slow as the PPU
         if self.clocks % 3 == 0 {
+            self.cpu.get_bus_mut().clock_cartridge();
+
             let dma_transfer = self.cpu.get_bus().get_dma_transfer();
 
             match dma_transfer {
-                Some(data) => self.dma_transfer(data),
-                None => {
+                // The CPU finishes the instruction that wrote $4014 first.
+                Some(page) if self.dma_halted || self.cpu.instruction_complete() => {
+                    self.cpu.stall();
+                    self.dma_transfer(page);
+                }
+                _ => {
+                    if self.trace.is_some() && self.cpu.instruction_complete() {
+                        self.record_trace();
+                    }
+
+                    let irq = self.cpu.get_bus().irq_pending();
+                    self.cpu.set_irq(irq);
                     self.cpu.clock();
                 }
             }
@@ -49,50 +304,1213 @@ impl Nes {
 
         if nmi_enable {
             self.cpu.nmi();
+
+            if let Some(callback) = &mut self.nmi_callback {
+                callback();
+            }
         }
 
         self.clocks = self.clocks.wrapping_add(1);
+
+        if frame_complete {
+            self.frames += 1;
+
+            if self.rewind_capacity > 0 {
+                self.push_snapshot();
+            }
+
+            self.next_movie_frame();
+
+            if let Some(callback) = &mut self.frame_callback {
+                callback(self.cpu.get_bus().get_ppu().get_framebuffer_rgba());
+            }
+        }
+
         frame_complete
     }
 
-    fn dma_transfer(&mut self, data: u8) {
-        let starting_addr = (data as u16) << 8;
-        let current_addr = starting_addr + self.dma_cycle;
+    /// Runs until the PPU finishes the current frame.
+    pub fn run_frame(&mut self) {
+        while !self.clock() {}
+    }
+
+    pub fn run_frames(&mut self, n: u32) {
+        for _ in 0..n {
+            self.run_frame();
+        }
+    }
+
+    /// Calls `callback` with the RGBA framebuffer at the end of every frame.
+    pub fn set_frame_callback(&mut self, callback: FrameCallback) {
+        self.frame_callback = Some(callback);
+    }
+
+    /// Calls `callback` whenever the PPU raises an NMI at the start of
+    /// vertical blank.
+    pub fn set_nmi_callback(&mut self, callback: Box<dyn FnMut()>) {
+        self.nmi_callback = Some(callback);
+    }
+
+    /// Keeps a snapshot of every frame for the last `max_seconds` seconds so
+    /// that `rewind_frame` can step back through them. Zero turns rewind
+    /// off. Apart from the newest, snapshots only keep the memory that
+    /// changed between frames, so a second of history takes around 600KB
+    /// (570KB measured with nestest), more for cartridges with extra RAM.
+    pub fn enable_rewind(&mut self, max_seconds: u32) {
+        self.rewind_capacity = max_seconds as usize * FRAMES_PER_SECOND;
+        self.rewind_snapshots.clear();
+
+        if self.rewind_capacity > 0 {
+            self.push_snapshot();
+        }
+    }
+
+    /// Goes back to the end of the previous frame. Returns false, leaving
+    /// the machine as it is, once the oldest snapshot has been reached.
+    ///
+    /// The controller, Zapper and host settings like the palette keep their
+    /// current state, so buttons held in the past don't stay pressed.
+    pub fn rewind_frame(&mut self) -> bool {
+        if self.rewind_snapshots.len() < 2 {
+            return false;
+        }
+
+        // The newest snapshot is the frame being left.
+        let mut memory = self.rewind_snapshots.pop_back().unwrap().memory;
+        let snapshot = self.rewind_snapshots.back_mut().unwrap();
+        patch(&mut memory, &snapshot.memory);
+
+        let mut cpu = snapshot.cpu.clone();
+        let bus = cpu.get_bus_mut();
+        bus.put_memory(BusMemory::load(&memory));
+        snapshot.memory = memory;
+
+        for player in 0..4 {
+            std::mem::swap(
+                bus.controller(player),
+                self.cpu.get_bus_mut().controller(player),
+            );
+        }
+        std::mem::swap(bus.zapper(), self.cpu.get_bus_mut().zapper());
+        bus.get_ppu_mut()
+            .take_host_settings(self.cpu.get_bus_mut().get_ppu_mut());
+
+        self.cpu = cpu;
+        self.clocks = snapshot.clocks;
+        self.frames = snapshot.frames;
+        self.dma_cycle = snapshot.dma_cycle;
+        self.dma_data = snapshot.dma_data;
+        self.dma_halted = snapshot.dma_halted;
+        true
+    }
+
+    fn push_snapshot(&mut self) {
+        if self.rewind_snapshots.len() == self.rewind_capacity {
+            self.rewind_snapshots.pop_front();
+        }
+
+        let bus_memory = self.cpu.get_bus_mut().take_memory();
+        let cpu = self.cpu.clone();
+        let mut memory = vec![];
+        bus_memory.save(&mut memory);
+        self.cpu.get_bus_mut().put_memory(bus_memory);
 
-        // Don't start the DMA transfer until we hit an odd clock cycle.
-        if self.dma_dummy {
-            if self.clocks % 2 == 1 {
-                self.dma_dummy = false;
+        if let Some(previous) = self.rewind_snapshots.back_mut() {
+            previous.memory = diff(&memory, &previous.memory);
+        }
+
+        self.rewind_snapshots.push_back(Snapshot {
+            cpu,
+            memory,
+            clocks: self.clocks,
+            frames: self.frames,
+            dma_cycle: self.dma_cycle,
+            dma_data: self.dma_data,
+            dma_halted: self.dma_halted,
+        });
+    }
+
+    /// Starts or stops recording where the PPU is at each CPU instruction,
+    /// for debugging raster timing. It's off by default, as it slows every
+    /// instruction down.
+    pub fn enable_trace(&mut self, enabled: bool) {
+        self.trace = if enabled {
+            Some(VecDeque::with_capacity(TRACE_CAPACITY))
+        } else {
+            None
+        };
+    }
+
+    /// The most recent instructions traced, oldest first.
+    pub fn recent_trace(&self) -> Vec<TraceEntry> {
+        match &self.trace {
+            Some(trace) => trace.iter().copied().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Starts or stops logging writes to the PPU's registers along with the
+    /// scanline and cycle they land on.
+    pub fn enable_ppu_write_log(&mut self, enabled: bool) {
+        self.cpu
+            .get_bus_mut()
+            .get_ppu_mut()
+            .enable_write_log(enabled);
+    }
+
+    /// The PPU register writes logged since the last call, oldest first.
+    pub fn take_ppu_write_log(&mut self) -> Vec<PpuWrite> {
+        self.cpu.get_bus_mut().get_ppu_mut().take_write_log()
+    }
+
+    fn record_trace(&mut self) {
+        let pc = self.cpu.get_program_counter();
+        let ppu = self.cpu.get_bus().get_ppu();
+        let entry = TraceEntry {
+            pc,
+            opcode: self.cpu.cpu_peek(pc),
+            ppu_scanline: ppu.get_scanline(),
+            ppu_cycle: ppu.get_cycle(),
+            cpu_cycle: self.cpu.total_cycles(),
+        };
+
+        if let Some(trace) = &mut self.trace {
+            if trace.len() == TRACE_CAPACITY {
+                trace.pop_front();
             }
+            trace.push_back(entry);
+        }
+    }
 
-            return;
+    /// Starts recording the first two controllers every frame. The movie
+    /// starts from power-on if no frames have run yet, otherwise the console
+    /// is reset so that it starts from there.
+    pub fn start_recording(&mut self) {
+        let start = if self.frames == 0 && self.clocks == 0 {
+            MovieStart::PowerOn
+        } else {
+            self.reset();
+            MovieStart::Reset
+        };
+
+        self.recording = Some(Movie {
+            start,
+            frames: vec![],
+        });
+    }
+
+    /// Stops recording and returns what was recorded, which is empty if
+    /// nothing was being recorded.
+    pub fn stop_recording(&mut self) -> Movie {
+        self.recording.take().unwrap_or(Movie {
+            start: MovieStart::PowerOn,
+            frames: vec![],
+        })
+    }
+
+    /// Plays back `movie` on the first two controllers, ignoring what's
+    /// pressed on them until it ends. A movie recorded from a reset resets
+    /// the console; one recorded from power-on should be played on a console
+    /// that has just been powered on with the same cartridge.
+    pub fn play_movie(&mut self, movie: Movie) {
+        if movie.start == MovieStart::Reset {
+            self.reset();
         }
 
-        if self.clocks % 2 == 0 {
-            self.dma_data = self.cpu.cpu_read(current_addr);
+        self.playback = Some((movie, 0));
+        self.apply_movie_frame();
+    }
+
+    // At the end of each frame, records the input it ran with or moves on
+    // to the next frame of the movie being played.
+    fn next_movie_frame(&mut self) {
+        if self.recording.is_some() {
+            let p1 = self.controller(0).buttons();
+            let p2 = self.controller(1).buttons();
+            if let Some(movie) = &mut self.recording {
+                movie.frames.push((p1, p2));
+            }
+        }
+
+        if let Some((_, frame)) = &mut self.playback {
+            *frame += 1;
+            self.apply_movie_frame();
+        }
+    }
+
+    fn apply_movie_frame(&mut self) {
+        let buttons = match &self.playback {
+            Some((movie, frame)) => movie.frames.get(*frame).copied(),
+            None => return,
+        };
+
+        let (p1, p2) = match buttons {
+            Some((p1, p2)) => (Some(p1), Some(p2)),
+            // The movie's over, so hand the controllers back.
+            None => {
+                self.playback = None;
+                (None, None)
+            }
+        };
+        self.controller(0).force_buttons(p1);
+        self.controller(1).force_buttons(p2);
+    }
+
+    /// The number of frames completed since power on.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// The number of CPU cycles since power on, as in the `CYC` column of
+    /// nestest's log.
+    pub fn total_cycles(&self) -> u64 {
+        self.cpu.total_cycles()
+    }
+
+    /// Runs the system until the byte at `address` satisfies `predicate`,
+    /// giving up after `max_frames` frames.
+    pub fn run_until(
+        &mut self,
+        address: u16,
+        predicate: impl Fn(u8) -> bool,
+        max_frames: u64,
+    ) -> Result<(), Timeout> {
+        let mut frames = 0;
+
+        while frames < max_frames {
+            if predicate(self.peek(address)) {
+                return Ok(());
+            }
+
+            if self.clock() {
+                frames += 1;
+            }
+        }
+
+        if predicate(self.peek(address)) {
+            Ok(())
         } else {
-            self.cpu
-                .get_bus_mut()
-                .get_ppu_mut()
-                .oam_dma(self.dma_cycle, self.dma_data);
-            self.dma_cycle = self.dma_cycle.wrapping_add(1);
+            Err(Timeout)
+        }
+    }
+
+    /// Stops `clock_until_break` before the instruction at `address` runs.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.cpu.add_breakpoint(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.cpu.remove_breakpoint(address);
+    }
+
+    /// Runs until the CPU is about to run the instruction at a breakpoint,
+    /// or until `max_cycles` CPU cycles have elapsed. Returns true if a
+    /// breakpoint was hit; calling this again continues past it.
+    pub fn clock_until_break(&mut self, max_cycles: u64) -> bool {
+        // Forget any breakpoint hit while running some other way.
+        self.cpu.take_breakpoint_hit();
+        let end = self.total_cycles() + max_cycles;
+
+        while self.total_cycles() < end {
+            self.clock();
+
+            if self.cpu.take_breakpoint_hit() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Runs `max_instructions` instructions, returning a line for each with
+    /// the CPU's state before it runs, in the form
+    /// `C000  4C  A:00 X:00 Y:00 P:24 SP:FD CYC:7`.
+    pub fn run_with_trace(&mut self, max_instructions: usize) -> Vec<String> {
+        let mut trace = Vec::with_capacity(max_instructions);
+
+        for _ in 0..max_instructions {
+            trace.push(self.cpu.trace_line());
+
+            // Run until the CPU is ready to fetch the next instruction.
+            let start = self.total_cycles();
+            while self.total_cycles() == start
+                || !self.cpu.instruction_complete()
+                || self.cpu.get_bus().get_dma_transfer().is_some()
+            {
+                self.clock();
+            }
+        }
+
+        trace
+    }
+
+    /// Disassembles the next `n` instructions, starting at the program
+    /// counter.
+    pub fn disassemble_around_pc(&self, n: usize) -> Vec<(u16, String)> {
+        self.cpu.disassemble_around_pc(n)
+    }
+
+    /// Reads memory without side effects, for debuggers.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.cpu.cpu_peek(address)
+    }
+
+    /// Writes RAM or cartridge RAM without side effects, for debuggers.
+    pub fn poke(&mut self, address: u16, data: u8) {
+        self.cpu.get_bus_mut().poke(address, data)
+    }
+
+    /// Turns on a Game Genie code, or a raw `AAAA:VV` code that pins a
+    /// byte of RAM.
+    pub fn add_cheat(&mut self, code: &str) -> Result<(), CheatError> {
+        let cheat = Cheat::parse(code)?;
+        self.cpu.get_bus_mut().add_cheat(cheat);
+        Ok(())
+    }
+
+    /// Turns off a code added with `add_cheat`, returning whether it was on.
+    pub fn remove_cheat(&mut self, code: &str) -> bool {
+        match Cheat::parse(code) {
+            Ok(cheat) => self.cpu.get_bus_mut().remove_cheat(cheat),
+            Err(_) => false,
+        }
+    }
+
+    /// Runs one CPU cycle of OAM DMA from `page`. The CPU is halted for a
+    /// cycle, then the DMA reads on even (get) cycles and writes to OAM on
+    /// odd (put) cycles, so a transfer takes 513 cycles, or 514 when it
+    /// has to wait a cycle to line its first read up with a get cycle.
+    fn dma_transfer(&mut self, page: u8) {
+        if !self.dma_halted {
+            self.dma_halted = true;
+            return;
+        }
+
+        let get_cycle = self.cpu.total_cycles() % 2 == 0;
+
+        match self.dma_data {
+            Some(data) => {
+                self.cpu
+                    .get_bus_mut()
+                    .get_ppu_mut()
+                    .oam_dma(self.dma_cycle, data);
+                self.dma_data = None;
+                self.dma_cycle += 1;
+            }
+            None if get_cycle => {
+                let address = (page as u16) << 8 | self.dma_cycle;
+                self.dma_data = Some(self.cpu.cpu_read(address));
+            }
+            // The alignment cycle.
+            None => (),
         }
 
         // End the DMA transfer after 256 bytes are copied.
         if self.dma_cycle == 0x100 {
             self.cpu.get_bus_mut().end_dma_transfer();
-            self.dma_dummy = true;
+            self.dma_halted = false;
             self.dma_cycle = 0;
         }
     }
 
-    pub fn get_screen(&self) -> &Vec<Vec<(u8, u8, u8)>> {
+    #[deprecated(note = "use get_framebuffer_rgba or get_pixel")]
+    #[allow(deprecated)]
+    pub fn get_screen(&self) -> Vec<Vec<(u8, u8, u8)>> {
         self.cpu.get_bus().get_ppu().get_screen()
     }
 
+    pub fn get_pixel(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        self.cpu.get_bus().get_ppu().get_pixel(x, y)
+    }
+
+    pub fn get_framebuffer_rgba(&self) -> &[u8] {
+        self.cpu.get_bus().get_ppu().get_framebuffer_rgba()
+    }
+
+    /// Hides `top`, `bottom`, `left` and `right` pixels at the edges of
+    /// the visible framebuffer. No crop is the default.
+    pub fn set_overscan(&mut self, top: u32, bottom: u32, left: u32, right: u32) {
+        self.overscan = Overscan::new(top, bottom, left, right);
+    }
+
+    pub fn overscan(&self) -> Overscan {
+        self.overscan
+    }
+
+    /// The RGBA framebuffer with the overscan cropped off, which is
+    /// `overscan().visible_size()` pixels.
+    pub fn get_visible_framebuffer_rgba(&self) -> Cow<'_, [u8]> {
+        self.overscan.crop(self.get_framebuffer_rgba())
+    }
+
+    /// CRC-32 of the RGBA framebuffer, for comparing screens in tests.
+    pub fn framebuffer_hash(&self) -> u32 {
+        crc32(self.get_framebuffer_rgba())
+    }
+
+    /// The current frame as a 256x240 RGBA PNG.
+    pub fn screenshot_png(&self) -> Vec<u8> {
+        png::encode_rgba(0x100, 0xF0, self.get_framebuffer_rgba())
+    }
+
+    /// All 64 sprites in OAM as (y, tile, attributes, x), for debugging.
+    pub fn oam_snapshot(&self) -> Vec<(u8, u8, u8, u8)> {
+        self.cpu.get_bus().get_ppu().get_oam_sprites()
+    }
+
+    /// The sprites to be drawn on the next scanline, as (y, tile, attributes, x).
+    pub fn secondary_oam_snapshot(&self) -> Vec<(u8, u8, u8, u8)> {
+        self.cpu.get_bus().get_ppu().get_secondary_oam_sprites()
+    }
+
+    /// A 128x128 image of pattern table `table` in palette `palette`, for
+    /// debugging.
+    pub fn render_pattern_table(&self, table: u8, palette: u8) -> Vec<(u8, u8, u8)> {
+        self.cpu
+            .get_bus()
+            .get_ppu()
+            .render_pattern_table(table, palette)
+    }
+
+    /// A 256x240 image of nametable `index`, for debugging.
+    pub fn render_nametable(&self, index: u8) -> Vec<(u8, u8, u8)> {
+        self.cpu.get_bus().get_ppu().render_nametable(index)
+    }
+
+    pub fn palette_colors(&self) -> &[(u8, u8, u8)] {
+        self.cpu.get_bus().get_ppu().palette_colors()
+    }
+
+    /// Replaces the 64 colors the PPU outputs, e.g. with ones from
+    /// `palette::parse_pal`.
+    pub fn set_palette(&mut self, palette: &[(u8, u8, u8)]) -> Result<(), PaletteError> {
+        self.cpu.get_bus_mut().get_ppu_mut().set_palette(palette)
+    }
+
     pub fn reset(&mut self) {
         self.cpu.reset();
     }
+
+    /// Skips drawing pixels for speed when running ROMs that don't need
+    /// the screen. Vertical blank and NMIs still happen.
+    pub fn set_rendering_enabled(&mut self, enabled: bool) {
+        self.cpu
+            .get_bus_mut()
+            .get_ppu_mut()
+            .set_output_enabled(enabled);
+    }
+
+    pub fn get_rendering_enabled(&self) -> bool {
+        self.cpu.get_bus().get_ppu().get_output_enabled()
+    }
+
+    /// Resets only the PPU, leaving the CPU and cartridge as they are.
+    pub fn reset_ppu(&mut self) {
+        self.cpu.get_bus_mut().get_ppu_mut().reset();
+    }
+}
+
+/// The changes that turn `from` into `to`, which must be the same length,
+/// as runs of (unchanged length, changed length, changed bytes), with the
+/// lengths as little-endian u32s.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut changes = vec![];
+    let mut position = 0;
+
+    while position < to.len() {
+        let start = position;
+        while position < to.len() && from[position] == to[position] {
+            position += 1;
+        }
+        let unchanged = position - start;
+
+        // Short unchanged stretches cost less to copy than to skip.
+        let start = position;
+        let mut end = position;
+        while position < to.len() && position - end < 8 {
+            position += 1;
+            if from[position - 1] != to[position - 1] {
+                end = position;
+            }
+        }
+        position = end;
+
+        changes.extend_from_slice(&(unchanged as u32).to_le_bytes());
+        changes.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        changes.extend_from_slice(&to[start..end]);
+    }
+
+    changes
+}
+
+/// Applies changes from `diff` to `data`.
+fn patch(data: &mut [u8], mut changes: &[u8]) {
+    let mut position = 0;
+
+    while !changes.is_empty() {
+        let unchanged = u32::from_le_bytes([changes[0], changes[1], changes[2], changes[3]]);
+        let changed = u32::from_le_bytes([changes[4], changes[5], changes[6], changes[7]]);
+        position += unchanged as usize;
+
+        let (bytes, rest) = changes[8..].split_at(changed as usize);
+        data[position..position + bytes.len()].copy_from_slice(bytes);
+        position += bytes.len();
+        changes = rest;
+    }
+}
+
+/// The CRC-32 used by zip and PNG (reflected polynomial 0xEDB88320).
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFFu32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "assembler")]
+    use super::Timeout;
+    use super::{crc32, MovieStart, Nes, RamInit, TraceEntry, TRACE_CAPACITY};
+    #[cfg(feature = "assembler")]
+    use crate::assembler;
+    use crate::cartridge::{Cartridge, CartridgeFormat, MirroringType};
+    use crate::controller::Button;
+    use crate::mappers::Mapper;
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::Path;
+    use std::rc::Rc;
+
+    /// A mapper that only has an IRQ line, controlled by the test.
+    #[derive(Clone)]
+    struct IrqMapper {
+        irq: Rc<Cell<bool>>,
+    }
+
+    impl Mapper for IrqMapper {
+        fn cpu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn cpu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn mirroring_type(&self) -> Option<MirroringType> {
+            None
+        }
+
+        fn irq_pending(&self) -> bool {
+            self.irq.get()
+        }
+    }
+
+    #[cfg(feature = "assembler")]
+    fn load_program(program: &str) -> Nes {
+        let program = assembler::assemble_program(program).expect("Encountered assembler error");
+        let mut nes = Nes::new();
+
+        for (location, byte) in program.iter().flatten().enumerate() {
+            nes.cpu.cpu_write(location as u16, *byte);
+        }
+
+        nes
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn run_until() {
+        let mut nes = load_program(
+            "
+            LDX #$00
+            INX
+            STX $10
+            JMP $0002 // Jump back to INX, keep incrementing
+        ",
+        );
+
+        assert_eq!(nes.run_until(0x0010, |value| value == 0x20, 2), Ok(()));
+        assert_eq!(nes.peek(0x0010), 0x20);
+
+        assert_eq!(
+            nes.run_until(0x0011, |value| value == 0x01, 1),
+            Err(Timeout),
+            "value never written"
+        );
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn breakpoints() {
+        let mut nes = load_program(
+            "
+            LDX #$02
+            DEX
+            BNE $FD
+            LDY #$07
+        ",
+        );
+
+        nes.add_breakpoint(0x0005);
+        assert!(nes.clock_until_break(1000));
+        assert_eq!(
+            nes.disassemble_around_pc(1),
+            vec![(0x0005, String::from("$0005: LDY #$07"))]
+        );
+
+        let trace = nes.run_with_trace(2);
+        assert!(
+            trace[0].starts_with("0005  A0  A:00 X:00 Y:00"),
+            "stopped before LDY"
+        );
+        assert!(trace[1].starts_with("0007  00  A:00 X:00 Y:07"), "LDY ran");
+
+        nes.remove_breakpoint(0x0005);
+        assert!(!nes.clock_until_break(10), "no breakpoints left");
+    }
+
+    fn load_nestest() -> Nes {
+        load_nestest_into(Nes::new())
+    }
+
+    fn load_nestest_into(mut nes: Nes) -> Nes {
+        let current_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let nes_test = current_dir.parent().unwrap().join("nestest.nes");
+        let cartridge = Cartridge::new(fs::read(nes_test).unwrap()).unwrap();
+
+        // Tests use flat RAM instead of the cartridge, so copy the PRG ROM in.
+        for (offset, byte) in cartridge.prg_rom().iter().enumerate() {
+            nes.cpu.cpu_write(0x8000 + offset as u16, *byte);
+            nes.cpu.cpu_write(0xC000 + offset as u16, *byte);
+        }
+        nes.load_cartridge(cartridge);
+        nes.reset();
+        nes
+    }
+
+    #[test]
+    fn mapper_irq() {
+        let irq = Rc::new(Cell::new(false));
+        let mut nes = Nes::new();
+        nes.load_cartridge(Cartridge::with_mapper(Box::new(IrqMapper {
+            irq: Rc::clone(&irq),
+        })));
+
+        // $8000: CLI, then spin on JMP $8001.
+        for (offset, byte) in [0x58, 0x4C, 0x01, 0x80].iter().enumerate() {
+            nes.poke(0x8000 + offset as u16, *byte);
+        }
+        // $9000: INC $10, then spin on JMP $9002 with IRQs masked.
+        for (offset, byte) in [0xE6, 0x10, 0x4C, 0x02, 0x90].iter().enumerate() {
+            nes.poke(0x9000 + offset as u16, *byte);
+        }
+        nes.poke(0xFFFC, 0x00);
+        nes.poke(0xFFFD, 0x80);
+        nes.poke(0xFFFE, 0x00);
+        nes.poke(0xFFFF, 0x90);
+        nes.reset();
+
+        for _ in 0..300 {
+            nes.clock();
+        }
+        assert_eq!(nes.peek(0x10), 0, "no IRQ while the line is high");
+
+        irq.set(true);
+        for _ in 0..300 {
+            nes.clock();
+        }
+        assert_eq!(
+            nes.peek(0x10),
+            1,
+            "IRQ taken once asserted, then masked in the handler"
+        );
+    }
+
+    /// Everything a test can see of the machine.
+    fn machine_state(nes: &Nes) -> (u64, u64, u32, Vec<u8>) {
+        (
+            nes.frame_count(),
+            nes.total_cycles(),
+            nes.framebuffer_hash(),
+            (0..0x800).map(|address| nes.peek(address)).collect(),
+        )
+    }
+
+    #[test]
+    fn unload_cartridge() {
+        let mut nes = load_nestest();
+        nes.run_frame();
+        assert!(nes.has_cartridge());
+
+        nes.unload_cartridge();
+        assert!(!nes.has_cartridge());
+        assert_eq!(nes.cartridge_info(), None);
+
+        let mut nes = load_nestest_into(nes);
+        assert!(nes.has_cartridge());
+        let frames = nes.frame_count();
+        nes.run_frame();
+        assert_eq!(nes.frame_count(), frames + 1, "runs after reloading");
+    }
+
+    #[test]
+    fn host_settings_survive_reset() {
+        let mut nes = load_nestest();
+        nes.set_rendering_enabled(false);
+        nes.enable_ppu_write_log(true);
+
+        nes.reset_ppu();
+        assert!(
+            !nes.get_rendering_enabled(),
+            "rendering disabled after reset"
+        );
+        nes.cpu.get_bus_mut().get_ppu_mut().cpu_write(0x2001, 0x1E);
+        assert_eq!(nes.take_ppu_write_log().len(), 1, "still logging");
+
+        nes.unload_cartridge();
+        assert!(
+            !nes.get_rendering_enabled(),
+            "rendering disabled after unload"
+        );
+    }
+
+    #[test]
+    fn cartridge_info() {
+        assert_eq!(Nes::new().cartridge_info(), None);
+
+        let info = load_nestest().cartridge_info().expect("cartridge loaded");
+        assert_eq!(info.format, CartridgeFormat::INes);
+        assert_eq!(info.mapper, 0);
+        assert_eq!(info.prg_rom_size, 0x4000);
+        assert_eq!(info.chr_rom_size, 0x2000);
+        assert!(!info.has_battery);
+    }
+
+    #[test]
+    fn overscan() {
+        let mut nes = Nes::new();
+        nes.run_frames(2);
+        let full = nes.get_framebuffer_rgba().to_vec();
+        assert_eq!(
+            nes.get_visible_framebuffer_rgba().len(),
+            full.len(),
+            "no crop"
+        );
+
+        nes.set_overscan(8, 8, 0, 0);
+        assert_eq!(nes.overscan().visible_size(), (256, 224));
+        let visible = nes.get_visible_framebuffer_rgba();
+        assert_eq!(visible.len(), 256 * 224 * 4);
+        assert_eq!(&visible[..], &full[256 * 8 * 4..256 * 232 * 4]);
+
+        nes.set_overscan(0, 0, 8, 16);
+        assert_eq!(nes.overscan().visible_size(), (232, 240));
+        let visible = nes.get_visible_framebuffer_rgba();
+        assert_eq!(&visible[..232 * 4], &full[8 * 4..240 * 4], "first row");
+
+        nes.set_overscan(300, 300, 300, 300);
+        assert_eq!(nes.overscan().visible_size(), (1, 1), "clamped");
+    }
+
+    #[test]
+    fn trace() {
+        let mut nes = load_nestest();
+        nes.run_frames(1);
+        assert!(nes.recent_trace().is_empty(), "off by default");
+
+        nes.enable_trace(true);
+        nes.run_frames(3);
+        let trace = nes.recent_trace();
+        assert_eq!(trace.len(), TRACE_CAPACITY, "only the latest kept");
+
+        for pair in trace.windows(2) {
+            let (before, after) = (pair[0], pair[1]);
+            assert!(after.cpu_cycle > before.cpu_cycle, "CPU cycles increase");
+
+            // The PPU runs three dots per CPU cycle, wrapping each frame.
+            let dot = |entry: TraceEntry| entry.ppu_scanline * 341 + entry.ppu_cycle;
+            let elapsed = (after.cpu_cycle - before.cpu_cycle) as u32 * 3;
+            let frame = 262 * 341;
+            assert_eq!((dot(before) + elapsed) % frame, dot(after) % frame);
+        }
+
+        let last = trace.last().unwrap();
+        assert_eq!(last.opcode, nes.peek(last.pc), "opcode at PC");
+
+        nes.enable_trace(false);
+        assert!(nes.recent_trace().is_empty());
+    }
+
+    #[test]
+    fn cheats() {
+        let mut nes = Nes::new();
+        nes.cpu.cpu_write(0x91D9, 0x12);
+        nes.cpu.cpu_write(0x0075, 0x01);
+
+        nes.add_cheat("SXIOPO").expect("valid code");
+        nes.add_cheat("0075:09").expect("valid code");
+        assert_eq!(nes.cpu.cpu_read(0x91D9), 0xAD, "Game Genie value read");
+        assert_eq!(nes.cpu.cpu_read(0x0075), 0x09, "raw value read");
+        assert_eq!(nes.peek(0x91D9), 0x12, "memory itself unchanged");
+
+        assert!(nes.remove_cheat("SXIOPO"));
+        assert!(!nes.remove_cheat("SXIOPO"), "already removed");
+        assert_eq!(nes.cpu.cpu_read(0x91D9), 0x12);
+        assert!(nes.add_cheat("SXIOPB").is_err());
+    }
+
+    #[test]
+    fn rewind() {
+        let mut nes = load_nestest();
+        nes.enable_rewind(10);
+        nes.run_frames(90);
+        let frame_90 = machine_state(&nes);
+        nes.run_frames(10);
+        let frame_100 = machine_state(&nes);
+
+        for _ in 0..10 {
+            assert!(nes.rewind_frame());
+        }
+        assert_eq!(machine_state(&nes), frame_90);
+
+        nes.run_frames(10);
+        assert_eq!(machine_state(&nes), frame_100, "replays the same frames");
+
+        // Only the newest snapshot holds all of memory. The rest only hold
+        // changes, which after the first frame's picture are tiny.
+        let sizes: Vec<usize> = nes
+            .rewind_snapshots
+            .iter()
+            .map(|snapshot| snapshot.memory.len())
+            .collect();
+        let (older, newest) = sizes.split_at(sizes.len() - 1);
+        assert_eq!(older.len(), 100);
+        assert!(older.iter().sum::<usize>() < newest[0] * 2);
+
+        nes.set_rendering_enabled(false);
+        assert!(nes.rewind_frame());
+        assert!(!nes.get_rendering_enabled(), "host settings kept");
+
+        let mut nes = load_nestest();
+        nes.enable_rewind(1);
+        nes.run_frames(100);
+        let rewound = (0..).take_while(|_| nes.rewind_frame()).count();
+        assert_eq!(rewound, 59, "only a second of snapshots is kept");
+        assert_eq!(nes.frame_count(), 41);
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut nes = Nes::new();
+        // $8000: LDA #$02, STA $4014, STA $10, STA $4014, then spin.
+        let program = [
+            0xA9, 0x02, 0x8D, 0x14, 0x40, 0x85, 0x10, 0x8D, 0x14, 0x40, 0x4C, 0x0A, 0x80,
+        ];
+        for (offset, byte) in program.iter().enumerate() {
+            nes.poke(0x8000 + offset as u16, *byte);
+        }
+        for offset in 0..0x100 {
+            nes.poke(0x0200 + offset, (offset as u8).wrapping_mul(3));
+        }
+        nes.poke(0xFFFC, 0x00);
+        nes.poke(0xFFFD, 0x80);
+        nes.reset();
+
+        // Time each transfer from the $4014 write, which is the first of the
+        // STA's four cycles here, to the end of the DMA.
+        let mut durations = vec![];
+        let mut start = None;
+        while durations.len() < 2 {
+            nes.clock();
+            let dma = nes.cpu.get_bus().get_dma_transfer().is_some();
+            match start {
+                None if dma => start = Some(nes.total_cycles()),
+                Some(cycles) if !dma => {
+                    durations.push(nes.total_cycles() - cycles - 3);
+                    start = None;
+                }
+                _ => (),
+            }
+        }
+        durations.sort_unstable();
+        assert_eq!(durations, vec![513, 514], "one of each alignment");
+
+        let oam: Vec<u8> = nes
+            .oam_snapshot()
+            .iter()
+            .flat_map(|&(y, tile, attributes, x)| vec![y, tile, attributes, x])
+            .collect();
+        let expected: Vec<u8> = (0..0x100).map(|i| (i as u8).wrapping_mul(3)).collect();
+        assert_eq!(oam, expected);
+    }
+
+    #[test]
+    fn run_frames() {
+        let mut nes = load_nestest();
+        nes.run_frames(10);
+        assert_eq!(nes.frame_count(), 10);
+
+        let mut other = load_nestest();
+        other.run_frame();
+        other.run_frames(9);
+        assert_eq!(other.frame_count(), 10);
+
+        assert_eq!(
+            nes.framebuffer_hash(),
+            other.framebuffer_hash(),
+            "same frames produce the same picture"
+        );
+    }
+
+    #[test]
+    fn deterministic() {
+        // Two machines run side by side, so every frame can be compared
+        // directly rather than through a hash.
+        let mut machines = [
+            load_nestest_into(Nes::new_deterministic()),
+            load_nestest_into(Nes::new_deterministic()),
+        ];
+
+        // Walk down nestest's menu and start a test now and then.
+        for frame in 0..300 {
+            for nes in machines.iter_mut() {
+                let controller = nes.controller(0);
+                match frame % 40 {
+                    0 => controller.press(Button::Down),
+                    20 => controller.press(Button::Start),
+                    _ => {
+                        controller.lift(Button::Down);
+                        controller.lift(Button::Start);
+                    }
+                }
+                nes.run_frame();
+            }
+
+            let [first, second] = &machines;
+            assert!(
+                first.get_framebuffer_rgba() == second.get_framebuffer_rgba(),
+                "frame {} differs",
+                frame
+            );
+        }
+    }
+
+    #[test]
+    fn movie() {
+        let mut nes = load_nestest();
+        nes.start_recording();
+        for frame in 0..60 {
+            let controller = nes.controller(0);
+            match frame {
+                10 | 20 => controller.press(Button::Down),
+                30 => controller.press(Button::Start),
+                _ => {
+                    controller.lift(Button::Down);
+                    controller.lift(Button::Start);
+                }
+            }
+            nes.run_frame();
+        }
+        let movie = nes.stop_recording();
+        assert_eq!(movie.start, MovieStart::PowerOn);
+        assert_eq!(movie.frames.len(), 60);
+        assert_eq!(movie.frames[20], (Button::Down as u8, 0));
+
+        // The test bus doesn't wire up the controllers, so check what they
+        // would hand the console as well as the picture.
+        let mut other = load_nestest();
+        other.play_movie(movie.clone());
+        for &(p1, p2) in &movie.frames {
+            // Live input is ignored while the movie plays.
+            other.controller(0).press(Button::Select);
+            assert_eq!(other.controller(0).buttons(), p1);
+            assert_eq!(other.controller(1).buttons(), p2);
+            other.run_frame();
+        }
+        assert_eq!(other.framebuffer_hash(), nes.framebuffer_hash());
+        assert_eq!(other.controller(0).buttons(), Button::Select as u8, "over");
+
+        // Recording after frames have run starts from a reset.
+        nes.start_recording();
+        nes.run_frame();
+        assert_eq!(nes.stop_recording().start, MovieStart::Reset);
+    }
+
+    #[test]
+    fn framebuffer_hash() {
+        assert_eq!(
+            crc32(b"123456789"),
+            0xCBF43926,
+            "standard CRC-32 check value"
+        );
+
+        let mut nes = load_nestest();
+        nes.run_frames(3);
+        let hash = nes.framebuffer_hash();
+        assert_eq!(hash, 0xEAA6_BD97, "hash is stable");
+
+        // Change the backdrop color, as a game would in response to input.
+        let ppu = nes.cpu.get_bus_mut().get_ppu_mut();
+        ppu.cpu_write(0x2006, 0x3F);
+        ppu.cpu_write(0x2006, 0x00);
+        ppu.cpu_write(0x2007, 0x16);
+        nes.run_frame();
+
+        assert_ne!(nes.framebuffer_hash(), hash, "new picture, new hash");
+    }
+
+    #[cfg(feature = "assembler")]
+    fn load_nmi_counter(rendering_enabled: bool) -> Nes {
+        let mut nes = load_program(
+            "
+            JMP $0000 // Loop forever
+        ",
+        );
+        let handler = assembler::assemble_program(
+            "
+            INC $10
+            RTI
+        ",
+        )
+        .expect("Encountered assembler error");
+
+        for (offset, byte) in handler.iter().flatten().enumerate() {
+            nes.cpu.cpu_write(0x0200 + offset as u16, *byte);
+        }
+
+        // NMI vector points to the handler.
+        nes.cpu.cpu_write(0xFFFA, 0x00);
+        nes.cpu.cpu_write(0xFFFB, 0x02);
+
+        nes.set_rendering_enabled(rendering_enabled);
+        let ppu = nes.cpu.get_bus_mut().get_ppu_mut();
+        ppu.cpu_write(0x2000, 0x80);
+        ppu.cpu_write(0x2001, 0x18);
+        nes
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn callbacks() {
+        let mut nes = load_nmi_counter(true);
+        let frames = Rc::new(Cell::new(0));
+        let nmis = Rc::new(Cell::new(0));
+
+        let frame_counter = Rc::clone(&frames);
+        nes.set_frame_callback(Box::new(move |framebuffer| {
+            assert_eq!(framebuffer.len(), 256 * 240 * 4);
+            frame_counter.set(frame_counter.get() + 1);
+        }));
+        let nmi_counter = Rc::clone(&nmis);
+        nes.set_nmi_callback(Box::new(move || nmi_counter.set(nmi_counter.get() + 1)));
+
+        nes.run_frames(5);
+        assert_eq!(frames.get(), 5);
+        // The PPU powers up on the pre-render line, so the first frame ends
+        // before any vertical blank.
+        assert_eq!(nmis.get(), 4);
+        assert_eq!(nes.peek(0x10), 4, "every NMI was reported");
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn nmi_without_rendering() {
+        let mut nes = load_nmi_counter(false);
+        assert_eq!(nes.run_until(0x0010, |value| value == 2, 3), Ok(()));
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn reset() {
+        let mut nes = load_program(
+            "
+            JMP $0000 // Loop forever
+        ",
+        );
+        let reset_handler = assembler::assemble_program(
+            "
+            INC $10
+            JMP $0000
+        ",
+        )
+        .expect("Encountered assembler error");
+
+        for (offset, byte) in reset_handler.iter().flatten().enumerate() {
+            nes.cpu.cpu_write(0x0300 + offset as u16, *byte);
+        }
+
+        // Reset vector points to the handler.
+        nes.cpu.cpu_write(0xFFFC, 0x00);
+        nes.cpu.cpu_write(0xFFFD, 0x03);
+
+        nes.reset();
+        assert_eq!(nes.run_until(0x0010, |value| value == 1, 1), Ok(()));
+
+        // Resetting again runs the reset vector again, keeping RAM intact.
+        nes.reset();
+        assert_eq!(nes.run_until(0x0010, |value| value == 2, 1), Ok(()));
+    }
+
+    #[test]
+    fn ram_init() {
+        let nes = Nes::new_with_ram_init(RamInit::Zeroed);
+        assert!((0..0x800).all(|address| nes.peek(address) == 0x00));
+
+        let nes = Nes::new_with_ram_init(RamInit::AllOnes);
+        assert!((0..0x800).all(|address| nes.peek(address) == 0xFF));
+
+        let nes = Nes::new_with_ram_init(RamInit::Pattern(vec![0x00, 0xFF]));
+        assert_eq!(nes.peek(0x0000), 0x00);
+        assert_eq!(nes.peek(0x0001), 0xFF);
+        assert_eq!(nes.peek(0x07FF), 0xFF);
+    }
+
+    #[cfg(feature = "assembler")]
+    #[test]
+    fn reset_ppu() {
+        let mut nes = load_program(
+            "
+            LDX #$05
+            LDA $20
+            BEQ $FC  // Wait until $20 is set
+            STX $10
+        ",
+        );
+
+        for _ in 0..1000 {
+            nes.clock();
+        }
+
+        let ppu = nes.cpu.get_bus_mut().get_ppu_mut();
+        ppu.cpu_write(0x2001, 0x18);
+        assert_ne!(ppu.get_cycle(), 0, "PPU has been clocked");
+
+        nes.reset_ppu();
+
+        let ppu = nes.cpu.get_bus().get_ppu();
+        assert_eq!(ppu.get_scanline(), 261, "scanline reset");
+        assert_eq!(ppu.get_cycle(), 0, "cycle reset");
+
+        // The CPU should pick up right where it left off, with X intact.
+        nes.cpu.cpu_write(0x0020, 0x01);
+        assert_eq!(nes.run_until(0x0010, |value| value == 0x05, 1), Ok(()));
+    }
 }
 
 // #[cfg(test)]
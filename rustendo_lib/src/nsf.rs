@@ -0,0 +1,381 @@
+use crate::cartridge::{Cartridge, MirroringType};
+use crate::mappers::Mapper;
+use crate::mos6502::Mos6502;
+
+// INIT and PLAY are called as if from just before here. Nothing is mapped
+// at this address, so a tune can't end up here any other way.
+const RETURN_ADDRESS: u16 = 0x4100;
+
+const NTSC_CPU_CLOCK: u64 = 1_789_773;
+const PAL_CPU_CLOCK: u64 = 1_662_607;
+
+#[derive(Debug, PartialEq)]
+pub enum NsfError {
+    /// The file doesn't start with `NESM\x1A`.
+    BadMagic,
+    /// The file is shorter than the 128 byte header.
+    TruncatedHeader,
+    /// A tune without bank switching must load at $8000 or above.
+    BadLoadAddress(u16),
+    /// The tune has fewer songs than the one asked for.
+    NoSuchTrack(u8),
+}
+
+impl std::fmt::Display for NsfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            NsfError::BadMagic => write!(f, "not an NSF file"),
+            NsfError::TruncatedHeader => write!(f, "header is truncated"),
+            NsfError::BadLoadAddress(address) => {
+                write!(f, "can't load at ${:04X} without bank switching", address)
+            }
+            NsfError::NoSuchTrack(track) => write!(f, "there is no track {}", track),
+        }
+    }
+}
+
+impl std::error::Error for NsfError {}
+
+/// An NSF music rip, played by calling its INIT routine once for a track
+/// and then its PLAY routine at the rate the header asks for. Only the CPU
+/// runs; there's no PPU or cartridge mapper involved.
+pub struct Nsf {
+    cpu: Mos6502,
+    total_songs: u8,
+    starting_song: u8,
+    init_address: u16,
+    play_address: u16,
+    name: String,
+    artist: String,
+    copyright: String,
+    initial_banks: [u8; 8],
+    bank_switching: bool,
+    pal: bool,
+    // CPU cycles between calls to PLAY.
+    play_period: u64,
+    cycles_until_play: u64,
+    in_routine: bool,
+    play_pending: bool,
+    track: Option<u8>,
+}
+
+impl Nsf {
+    /// Parses an NSF file and maps its data into the CPU's address space.
+    /// Nothing plays until `play_track` is called.
+    pub fn new(raw: &[u8]) -> Result<Self, NsfError> {
+        if raw.len() < 0x80 {
+            return Err(NsfError::TruncatedHeader);
+        }
+
+        if raw[0..5] != *b"NESM\x1A" {
+            return Err(NsfError::BadMagic);
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([raw[offset], raw[offset + 1]]);
+        let load_address = word(0x08);
+
+        let mut initial_banks = [0; 8];
+        initial_banks.copy_from_slice(&raw[0x70..0x78]);
+        let bank_switching = initial_banks.iter().any(|&bank| bank != 0);
+
+        // Without bank switching the data sits at its load address in a
+        // flat 32K. With it, the data is split into 4K banks, the first
+        // padded so that it starts at the load address's offset in a bank.
+        let padding = if bank_switching {
+            (load_address & 0x0FFF) as usize
+        } else if load_address >= 0x8000 {
+            initial_banks = [0, 1, 2, 3, 4, 5, 6, 7];
+            (load_address - 0x8000) as usize
+        } else {
+            return Err(NsfError::BadLoadAddress(load_address));
+        };
+
+        let mut rom = vec![0; padding];
+        rom.extend_from_slice(&raw[0x80..]);
+        rom.resize(rom.len().max(0x8000).div_ceil(0x1000) * 0x1000, 0);
+
+        // Bit 0 is set for PAL tunes, and bit 1 for tunes that play on
+        // either, which are played as NTSC.
+        let pal = raw[0x7A] & 0x3 == 0x1;
+        let (speed, cpu_clock) = if pal {
+            (word(0x78), PAL_CPU_CLOCK)
+        } else {
+            (word(0x6E), NTSC_CPU_CLOCK)
+        };
+        let play_period = (speed as u64 * cpu_clock / 1_000_000).max(1);
+
+        let mut cpu = Mos6502::new();
+        cpu.load_cartridge(Cartridge::with_mapper(Box::new(NsfMapper::new(
+            rom,
+            initial_banks,
+        ))));
+
+        Ok(Nsf {
+            cpu,
+            total_songs: raw[0x06],
+            starting_song: raw[0x07].saturating_sub(1),
+            init_address: word(0x0A),
+            play_address: word(0x0C),
+            name: Self::text(&raw[0x0E..0x2E]),
+            artist: Self::text(&raw[0x2E..0x4E]),
+            copyright: Self::text(&raw[0x4E..0x6E]),
+            initial_banks,
+            bank_switching,
+            pal,
+            play_period,
+            cycles_until_play: play_period,
+            in_routine: false,
+            play_pending: false,
+            track: None,
+        })
+    }
+
+    /// Reads a zero-padded header string.
+    fn text(bytes: &[u8]) -> String {
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn artist(&self) -> &str {
+        &self.artist
+    }
+
+    pub fn copyright(&self) -> &str {
+        &self.copyright
+    }
+
+    pub fn total_songs(&self) -> u8 {
+        self.total_songs
+    }
+
+    /// The track to play first, counting from 0.
+    pub fn starting_song(&self) -> u8 {
+        self.starting_song
+    }
+
+    /// The track being played, counting from 0.
+    pub fn track(&self) -> Option<u8> {
+        self.track
+    }
+
+    /// Starts track `track`, counting from 0, by clearing RAM and the
+    /// sound registers and calling INIT with the track number in A and
+    /// the region in X.
+    pub fn play_track(&mut self, track: u8) -> Result<(), NsfError> {
+        if track >= self.total_songs {
+            return Err(NsfError::NoSuchTrack(track));
+        }
+
+        for address in (0x0000..0x0800).chain(0x6000..0x8000) {
+            self.cpu.cpu_write(address, 0);
+        }
+        for address in 0x4000..0x4014 {
+            self.cpu.cpu_write(address, 0);
+        }
+        self.cpu.cpu_write(0x4015, 0x00);
+        self.cpu.cpu_write(0x4015, 0x0F);
+        self.cpu.cpu_write(0x4017, 0x40);
+
+        if self.bank_switching {
+            for (register, bank) in self.initial_banks.iter().enumerate() {
+                self.cpu.cpu_write(0x5FF8 + register as u16, *bank);
+            }
+        }
+
+        // Drop whatever the previous track was in the middle of.
+        while !self.cpu.instruction_complete() {
+            self.cpu.clock();
+        }
+        self.cpu.set_stack_pointer(0xFD);
+        self.cpu
+            .call_subroutine(self.init_address, track, self.pal as u8, RETURN_ADDRESS);
+
+        self.in_routine = true;
+        self.play_pending = false;
+        self.cycles_until_play = self.play_period;
+        self.track = Some(track);
+        Ok(())
+    }
+
+    /// Reads memory without side effects, for debuggers.
+    pub fn peek(&self, address: u16) -> u8 {
+        self.cpu.cpu_peek(address)
+    }
+
+    /// Runs a single CPU cycle. PLAY is called every play period, or as
+    /// soon as INIT or the last PLAY returns if it's still running then.
+    pub fn clock(&mut self) {
+        if self.track.is_none() {
+            return;
+        }
+
+        self.cycles_until_play -= 1;
+        if self.cycles_until_play == 0 {
+            self.cycles_until_play = self.play_period;
+            self.play_pending = true;
+        }
+
+        if !self.in_routine && self.play_pending {
+            self.play_pending = false;
+            self.in_routine = true;
+            self.cpu
+                .call_subroutine(self.play_address, 0, 0, RETURN_ADDRESS);
+        }
+
+        if self.in_routine && self.cpu.clock() && self.cpu.get_program_counter() == RETURN_ADDRESS {
+            self.in_routine = false;
+        }
+    }
+}
+
+/// Maps an NSF's data into $8000-$FFFF in 4K banks, switched by writing
+/// to $5FF8-$5FFF, with 8K of RAM at $6000-$7FFF.
+#[derive(Clone)]
+struct NsfMapper {
+    rom: Vec<u8>,
+    banks: [u8; 8],
+    prg_ram: Vec<u8>,
+}
+
+impl NsfMapper {
+    fn new(rom: Vec<u8>, banks: [u8; 8]) -> Self {
+        NsfMapper {
+            rom,
+            banks,
+            prg_ram: vec![0; 0x2000],
+        }
+    }
+}
+
+impl Mapper for NsfMapper {
+    fn cpu_read(&self, address: u16) -> (Option<usize>, Option<u8>) {
+        match address {
+            0x6000..=0x7FFF => (None, Some(self.prg_ram[(address & 0x1FFF) as usize])),
+            0x8000..=0xFFFF => {
+                let bank = self.banks[((address - 0x8000) >> 12) as usize] as usize;
+                let offset = bank * 0x1000 + (address & 0x0FFF) as usize;
+                (None, Some(self.rom.get(offset).copied().unwrap_or(0)))
+            }
+            _ => (None, None),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, data: u8) -> Option<usize> {
+        match address {
+            0x5FF8..=0x5FFF => self.banks[(address - 0x5FF8) as usize] = data,
+            0x6000..=0x7FFF => self.prg_ram[(address & 0x1FFF) as usize] = data,
+            _ => (),
+        }
+        None
+    }
+
+    fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+        (None, None)
+    }
+
+    fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+        None
+    }
+
+    fn mirroring_type(&self) -> Option<MirroringType> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Nsf, NsfError, NsfMapper};
+    use crate::mappers::Mapper;
+
+    /// An NSF header for a three song tune loaded at $8000, with INIT at
+    /// $8000 and PLAY at $8005, followed by `data`.
+    fn nsf_file(data: &[u8]) -> Vec<u8> {
+        let mut raw = vec![0; 0x80];
+        raw[0..5].copy_from_slice(b"NESM\x1A");
+        raw[0x05] = 1;
+        raw[0x06] = 3;
+        raw[0x07] = 1;
+        raw[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0A..0x0C].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0C..0x0E].copy_from_slice(&0x8005u16.to_le_bytes());
+        raw[0x0E..0x13].copy_from_slice(b"Title");
+        // 16639us, the usual 60.1Hz.
+        raw[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+        raw.extend_from_slice(data);
+        raw
+    }
+
+    #[test]
+    fn header() {
+        let nsf = Nsf::new(&nsf_file(&[])).expect("valid NSF");
+        assert_eq!(nsf.name(), "Title");
+        assert_eq!(nsf.artist(), "");
+        assert_eq!(nsf.total_songs(), 3);
+        assert_eq!(nsf.starting_song(), 0, "song 1 counts from 0");
+        assert_eq!(nsf.play_period, 29780, "16639us of NTSC CPU cycles");
+
+        let mut raw = nsf_file(&[]);
+        raw[0] = b'X';
+        assert_eq!(Nsf::new(&raw).err(), Some(NsfError::BadMagic));
+        assert_eq!(
+            Nsf::new(&raw[..0x40]).err(),
+            Some(NsfError::TruncatedHeader)
+        );
+
+        let mut raw = nsf_file(&[]);
+        raw[0x08..0x0A].copy_from_slice(&0x6000u16.to_le_bytes());
+        assert_eq!(Nsf::new(&raw).err(), Some(NsfError::BadLoadAddress(0x6000)));
+
+        let mut nsf = Nsf::new(&nsf_file(&[])).expect("valid NSF");
+        assert_eq!(nsf.play_track(3), Err(NsfError::NoSuchTrack(3)));
+    }
+
+    #[test]
+    fn init_and_play() {
+        let tune = [
+            0x85, 0x00, // INIT: STA $00
+            0xE6, 0x02, // INC $02
+            0x60, // RTS
+            0xE6, 0x01, // PLAY: INC $01
+            0x60, // RTS
+        ];
+        let mut nsf = Nsf::new(&nsf_file(&tune)).expect("valid NSF");
+        // The test bus is flat RAM, so put the tune where the mapper would.
+        for (offset, byte) in tune.iter().enumerate() {
+            nsf.cpu.cpu_write(0x8000 + offset as u16, *byte);
+        }
+
+        nsf.play_track(2).expect("track exists");
+        for _ in 0..100 {
+            nsf.clock();
+        }
+        assert_eq!(nsf.cpu.cpu_read(0x0000), 2, "INIT gets the track in A");
+        assert_eq!(nsf.cpu.cpu_read(0x0002), 1, "INIT called once");
+        assert_eq!(nsf.cpu.cpu_read(0x0001), 0, "PLAY not called yet");
+
+        for _ in 100..nsf.play_period * 3 + 100 {
+            nsf.clock();
+        }
+        assert_eq!(nsf.cpu.cpu_read(0x0001), 3, "PLAY called once a period");
+        assert_eq!(nsf.cpu.cpu_read(0x0002), 1, "INIT not called again");
+    }
+
+    #[test]
+    fn bank_switching() {
+        // Four 4K banks, each filled with its own number.
+        let rom: Vec<u8> = (0..4).flat_map(|bank| vec![bank; 0x1000]).collect();
+        let mut mapper = NsfMapper::new(rom, [3, 2, 1, 0, 0, 0, 0, 0]);
+        assert_eq!(mapper.cpu_read(0x8000), (None, Some(3)));
+        assert_eq!(mapper.cpu_read(0x9FFF), (None, Some(2)));
+
+        mapper.cpu_write(0x5FF8, 1);
+        assert_eq!(mapper.cpu_read(0x8123), (None, Some(1)));
+
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_read(0x6000), (None, Some(0x42)));
+    }
+}
@@ -0,0 +1,114 @@
+//! The 6502's opcodes, as the CPU decodes them, for tools like assemblers
+//! and disassemblers.
+
+pub use crate::mos6502::AddressingMode;
+use crate::mos6502::Instruction;
+
+/// What an opcode decodes to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OpcodeInfo {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    /// The instruction's length, including the opcode.
+    pub bytes: u32,
+    /// Clock cycles, not counting page crossings or taken branches.
+    pub cycles: u32,
+    /// Whether the opcode is in the official documentation.
+    pub documented: bool,
+}
+
+/// Decodes `opcode`, or returns `None` if it jams the CPU.
+pub fn opcode_info(opcode: u8) -> Option<OpcodeInfo> {
+    let instruction = Instruction::decode(opcode);
+    let (mode, bytes, cycles) = instruction.parts()?;
+
+    Some(OpcodeInfo {
+        mnemonic: instruction.mnemonic(),
+        mode,
+        bytes,
+        cycles,
+        // The only undocumented opcodes the CPU runs are the extra NOPs.
+        documented: instruction.mnemonic() != "NOP" || opcode == 0xEA,
+    })
+}
+
+/// Every opcode's `opcode_info`, indexed by opcode.
+pub fn opcode_table() -> Vec<Option<OpcodeInfo>> {
+    (0..=0xFF).map(opcode_info).collect()
+}
+
+/// The documented opcode for `mnemonic` in addressing mode `mode`, if there
+/// is one.
+pub fn find_opcode(mnemonic: &str, mode: AddressingMode) -> Option<u8> {
+    (0..=0xFF).find(|&opcode| match opcode_info(opcode) {
+        Some(info) => info.documented && info.mnemonic == mnemonic && info.mode == mode,
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "assembler")]
+    use super::opcode_table;
+    use super::{find_opcode, opcode_info, AddressingMode};
+    #[cfg(feature = "assembler")]
+    use crate::assembler::assemble_program;
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn round_trip() {
+        let table = opcode_table();
+        let documented = table.iter().flatten().filter(|info| info.documented);
+        assert_eq!(documented.count(), 151, "the documented opcodes");
+
+        for (opcode, info) in table.iter().enumerate() {
+            let info = match info {
+                Some(info) if info.documented => info,
+                _ => continue,
+            };
+            // Operands whose bytes differ, so the byte order shows.
+            let (operand, operand_bytes): (&str, &[u8]) = match info.mode {
+                AddressingMode::Accumulator | AddressingMode::Implied => ("", &[]),
+                AddressingMode::Immediate => (" #$12", &[0x12]),
+                AddressingMode::ZeroPage | AddressingMode::Relative => (" $12", &[0x12]),
+                AddressingMode::ZeroPageX => (" $12,X", &[0x12]),
+                AddressingMode::ZeroPageY => (" $12,Y", &[0x12]),
+                AddressingMode::Absolute => (" $1234", &[0x34, 0x12]),
+                AddressingMode::AbsoluteX => (" $1234,X", &[0x34, 0x12]),
+                AddressingMode::AbsoluteY => (" $1234,Y", &[0x34, 0x12]),
+                AddressingMode::Indirect => (" ($1234)", &[0x34, 0x12]),
+                AddressingMode::IndirectX => (" ($12,X)", &[0x12]),
+                AddressingMode::IndirectY => (" ($12),Y", &[0x12]),
+            };
+            let source = format!("{}{}", info.mnemonic, operand);
+
+            let program = assemble_program(&source)
+                .unwrap_or_else(|error| panic!("{} failed: {:?}", source, error));
+            let mut expected = vec![opcode as u8];
+            expected.extend_from_slice(operand_bytes);
+            assert_eq!(program, vec![expected], "{}", source);
+
+            let decoded = opcode_info(program[0][0]).unwrap();
+            assert_eq!((decoded.mnemonic, decoded.mode), (info.mnemonic, info.mode));
+            // BRK skips a padding byte, which the assembler leaves out.
+            if info.mnemonic != "BRK" {
+                assert_eq!(program[0].len() as u32, info.bytes, "{} length", source);
+            }
+        }
+    }
+
+    #[test]
+    fn lookups() {
+        assert_eq!(find_opcode("LDA", AddressingMode::Immediate), Some(0xA9));
+        assert_eq!(find_opcode("NOP", AddressingMode::Implied), Some(0xEA));
+        assert_eq!(find_opcode("NOP", AddressingMode::ZeroPage), None);
+        assert_eq!(find_opcode("STA", AddressingMode::Immediate), None);
+
+        let info = opcode_info(0x7D).unwrap();
+        assert_eq!(
+            (info.mnemonic, info.mode, info.bytes, info.cycles),
+            ("ADC", AddressingMode::AbsoluteX, 3, 4)
+        );
+        assert_eq!(opcode_info(0x02), None, "KIL");
+    }
+}
@@ -0,0 +1,62 @@
+/// The number of colors the PPU can output.
+pub const PALETTE_SIZE: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum PaletteError {
+    /// A palette needs exactly 64 colors.
+    WrongColorCount(usize),
+    /// A `.pal` file needs exactly 192 bytes.
+    WrongFileSize(usize),
+}
+
+impl std::fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PaletteError::WrongColorCount(count) => write!(
+                f,
+                "palette has {} colors instead of {}",
+                count, PALETTE_SIZE
+            ),
+            PaletteError::WrongFileSize(size) => write!(
+                f,
+                "palette file is {} bytes instead of {}",
+                size,
+                PALETTE_SIZE * 3
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+/// Parses a `.pal` file, which is 64 colors stored as RGB byte triples.
+pub fn parse_pal(bytes: &[u8]) -> Result<Vec<(u8, u8, u8)>, PaletteError> {
+    if bytes.len() != PALETTE_SIZE * 3 {
+        return Err(PaletteError::WrongFileSize(bytes.len()));
+    }
+
+    Ok(bytes
+        .chunks(3)
+        .map(|color| (color[0], color[1], color[2]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_pal, PaletteError};
+
+    #[test]
+    fn pal_file() {
+        let bytes: Vec<u8> = (0..192).map(|i| i as u8).collect();
+        let palette = parse_pal(&bytes).unwrap();
+
+        assert_eq!(palette.len(), 64);
+        assert_eq!(palette[0], (0, 1, 2));
+        assert_eq!(palette[63], (189, 190, 191));
+
+        assert_eq!(
+            parse_pal(&bytes[..191]),
+            Err(PaletteError::WrongFileSize(191))
+        );
+    }
+}
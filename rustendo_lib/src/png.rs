@@ -0,0 +1,162 @@
+use crate::nes::crc32;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+// The most a stored (uncompressed) deflate block can hold.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// Encodes an 8-bit RGBA image as a PNG.
+///
+/// The image data is stored uncompressed, which keeps the encoder tiny at
+/// the cost of file size: a 256x240 screenshot is about 240K.
+pub fn encode_rgba(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    assert_eq!(
+        rgba.len(),
+        width as usize * height as usize * 4,
+        "image data doesn't match its size"
+    );
+
+    let mut png = SIGNATURE.to_vec();
+
+    let mut header = vec![];
+    header.extend(&width.to_be_bytes());
+    header.extend(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, standard filters, not interlaced.
+    header.extend(&[8, 6, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+
+    // Each row starts with its filter type, which is always 0 (none).
+    let mut scanlines = vec![];
+    for row in rgba.chunks(width as usize * 4) {
+        scanlines.push(0);
+        scanlines.extend(row);
+    }
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&scanlines));
+
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend(chunk_type);
+    png.extend(data);
+    // The CRC covers the type and the data, but not the length.
+    let crc = crc32(&png[start..]);
+    png.extend(&crc.to_be_bytes());
+}
+
+/// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window, no preset dictionary, fastest compression.
+    let mut zlib = vec![0x78, 0x01];
+
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        // An empty stream still needs one final block.
+        zlib.extend(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        zlib.push(last as u8);
+        zlib.extend(&length.to_le_bytes());
+        zlib.extend(&(!length).to_le_bytes());
+        zlib.extend(block);
+    }
+
+    zlib.extend(&adler32(data).to_be_bytes());
+    zlib
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let mut a = 1u32;
+    let mut b = 0u32;
+
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, encode_rgba, SIGNATURE};
+    use crate::nes::crc32;
+    use std::convert::TryInto;
+
+    fn be_u32(bytes: &[u8]) -> u32 {
+        u32::from_be_bytes(bytes[..4].try_into().unwrap())
+    }
+
+    /// Decodes the PNGs written by `encode_rgba`, i.e. only stored deflate
+    /// blocks and unfiltered rows, checking every checksum on the way.
+    fn decode(png: &[u8]) -> (u32, u32, Vec<u8>) {
+        assert_eq!(&png[..8], &SIGNATURE, "PNG signature");
+
+        let mut header = vec![];
+        let mut zlib = vec![];
+        let mut rest = &png[8..];
+        loop {
+            let length = be_u32(rest) as usize;
+            let chunk = &rest[4..8 + length];
+            assert_eq!(be_u32(&rest[8 + length..]), crc32(chunk), "chunk CRC");
+
+            match &chunk[..4] {
+                b"IHDR" => header.extend(&chunk[4..]),
+                b"IDAT" => zlib.extend(&chunk[4..]),
+                b"IEND" => break,
+                chunk_type => panic!("unexpected chunk {:?}", chunk_type),
+            }
+            rest = &rest[12 + length..];
+        }
+
+        assert_eq!(&header[8..], &[8, 6, 0, 0, 0], "8-bit RGBA");
+        let (width, height) = (be_u32(&header), be_u32(&header[4..]));
+
+        assert_eq!(&zlib[..2], &[0x78, 0x01], "zlib header");
+        let mut scanlines = vec![];
+        let mut blocks = &zlib[2..];
+        loop {
+            let length = u16::from_le_bytes([blocks[1], blocks[2]]);
+            assert_eq!(
+                !length,
+                u16::from_le_bytes([blocks[3], blocks[4]]),
+                "block length complement"
+            );
+            scanlines.extend(&blocks[5..5 + length as usize]);
+
+            let last = blocks[0] & 1 == 1;
+            blocks = &blocks[5 + length as usize..];
+            if last {
+                break;
+            }
+        }
+        assert_eq!(be_u32(blocks), adler32(&scanlines), "Adler-32");
+
+        let mut rgba = vec![];
+        for row in scanlines.chunks(width as usize * 4 + 1) {
+            assert_eq!(row[0], 0, "unfiltered row");
+            rgba.extend(&row[1..]);
+        }
+
+        (width, height, rgba)
+    }
+
+    #[test]
+    fn adler32_check_value() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+    }
+
+    #[test]
+    fn round_trip() {
+        let rgba: Vec<u8> = (0..256 * 240 * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let png = encode_rgba(256, 240, &rgba);
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(decode(&png), (256, 240, rgba));
+    }
+}
@@ -1,5 +1,6 @@
 use crate::cartridge::MirroringType;
 
+#[derive(Clone, Default)]
 pub struct Ram {
     nametables: Vec<Vec<u8>>
 }
@@ -33,6 +34,33 @@ impl Ram {
         }
     }
 
+    /// Appends both nametables to `image`.
+    pub fn save(&self, image: &mut Vec<u8>) {
+        for nametable in &self.nametables {
+            image.extend_from_slice(nametable);
+        }
+    }
+
+    /// Reads back both nametables as `save` wrote them, advancing `image`
+    /// past them.
+    pub fn load(image: &mut &[u8]) -> Self {
+        let (nametables, rest) = image.split_at(0x800);
+        *image = rest;
+
+        Ram {
+            nametables: nametables.chunks(0x400).map(|n| n.to_vec()).collect(),
+        }
+    }
+
+    /// Reads from `nametable`, 0 or 1, ignoring mirroring.
+    pub fn read_nametable(&self, nametable: usize, address: u16) -> u8 {
+        self.nametables[nametable][(address & 0x3FF) as usize]
+    }
+
+    pub fn write_nametable(&mut self, nametable: usize, address: u16, data: u8) {
+        self.nametables[nametable][(address & 0x3FF) as usize] = data;
+    }
+
     pub fn read(&self, mirroring: MirroringType, address: u16) -> u8 {
         let (nametable, address) = self.map_address(mirroring, address);
         self.nametables[nametable][address]
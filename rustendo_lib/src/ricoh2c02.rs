@@ -1,4 +1,5 @@
-use crate::cartridge::{Cartridge, MirroringType};
+use crate::cartridge::{Cartridge, MirroringType, NametableSource};
+use crate::palette::{PaletteError, PALETTE_SIZE};
 use crate::ppu_ram::Ram;
 use std::ops::{Index, IndexMut};
 
@@ -20,6 +21,7 @@ enum PpuSelect {
     OutputColor = 1,
 }
 
+#[derive(Clone)]
 struct PpuCtrl {
     nametable_select: u8,
     increment_mode: IncrementMode,
@@ -79,6 +81,7 @@ impl PpuCtrl {
     }
 }
 
+#[derive(Clone)]
 struct PpuMask {
     greyscale: bool,
     background_left_column_enable: bool,
@@ -116,6 +119,7 @@ impl PpuMask {
     }
 }
 
+#[derive(Clone)]
 struct PpuStatus {
     sprite_overflow: bool,
     sprite_zero_hit: bool,
@@ -188,11 +192,11 @@ impl Register {
     pub fn copy_vertical_address(&mut self, register: &Register) {
         self.set_field(
             RegisterBits::CoarseY,
-            register.get_field(RegisterBits::CoarseX),
+            register.get_field(RegisterBits::CoarseY),
         );
         self.set_field(
             RegisterBits::NametableSelectY,
-            register.get_field(RegisterBits::NametableSelectX),
+            register.get_field(RegisterBits::NametableSelectY),
         );
         self.set_field(RegisterBits::FineY, register.get_field(RegisterBits::FineY));
     }
@@ -220,7 +224,7 @@ impl Register {
         let coarse_x = (self.get_field(RegisterBits::CoarseX) as u16) >> 2;
         let coarse_y = (self.get_field(RegisterBits::CoarseY) as u16) >> 2;
 
-        nametable_select << 11 | coarse_y << 3 | coarse_x
+        nametable_select << 10 | coarse_y << 3 | coarse_x
     }
 
     pub fn get_nametable_offset(&self) -> u16 {
@@ -241,6 +245,7 @@ impl Register {
     }
 }
 
+#[derive(Clone)]
 struct Sprite {
     top_y_position: u8,
     tile_id: u8,
@@ -249,7 +254,8 @@ struct Sprite {
 }
 
 impl Sprite {
-    fn _in_range(scanline: u32, height: u8, byte: u8) -> bool {
+    /// Whether a sprite at Y position `byte` is on the line after `scanline`.
+    pub fn y_in_range(scanline: u32, height: u8, byte: u8) -> bool {
         // We're looking at the next scanline
         let scanline = if scanline == 261 { 0 } else { scanline + 1 };
         let byte: u32 = byte.into();
@@ -258,16 +264,16 @@ impl Sprite {
         scanline >= byte && scanline < byte + height
     }
     pub fn in_range(&self, scanline: u32, height: u8) -> bool {
-        Self::_in_range(scanline, height, self.top_y_position)
+        Self::y_in_range(scanline, height, self.top_y_position)
     }
 
-    pub fn in_range_with_sprite_overflow_bug(
-        &self,
-        scanline: u32,
-        height: u8,
-        byte: usize,
-    ) -> bool {
-        Self::_in_range(scanline, height, self[byte])
+    pub fn as_tuple(&self) -> (u8, u8, u8, u8) {
+        (
+            self.top_y_position,
+            self.tile_id,
+            self.attributes,
+            self.left_x_position,
+        )
     }
 
     pub fn flipped_vertically(&self) -> bool {
@@ -305,8 +311,10 @@ impl IndexMut<usize> for Sprite {
     }
 }
 
+#[derive(Clone)]
 struct Oam {
     oam: Vec<u8>,
+    // The number of sprites copied in with `copy_sprite`, not a byte offset.
     num_sprites: usize,
 }
 
@@ -376,6 +384,52 @@ impl IndexMut<usize> for Oam {
     }
 }
 
+/// A CPU write to one of the PPU's registers, and where the PPU was in
+/// the frame when it happened.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PpuWrite {
+    pub scanline: u32,
+    pub cycle: u32,
+    /// $2000 to $2007.
+    pub register: u16,
+    pub value: u8,
+}
+
+/// The PPU's nametable RAM, OAM and framebuffer, moved out of it so the
+/// rest can be copied cheaply. The write log comes along so it isn't
+/// copied either, but it isn't saved.
+pub struct PpuMemory {
+    ram: Ram,
+    oam: Vec<u8>,
+    framebuffer: Vec<u8>,
+    write_log: Option<Vec<PpuWrite>>,
+}
+
+impl PpuMemory {
+    /// Appends everything but the write log to `image`.
+    pub fn save(&self, image: &mut Vec<u8>) {
+        self.ram.save(image);
+        image.extend_from_slice(&self.oam);
+        image.extend_from_slice(&self.framebuffer);
+    }
+
+    /// Reads back what `save` wrote, advancing `image` past it.
+    pub fn load(image: &mut &[u8]) -> Self {
+        let ram = Ram::load(image);
+        let (oam, rest) = image.split_at(0x100);
+        let (framebuffer, rest) = rest.split_at(0x100 * 0xF0 * 4);
+        *image = rest;
+
+        PpuMemory {
+            ram,
+            oam: oam.to_vec(),
+            framebuffer: framebuffer.to_vec(),
+            write_log: None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Ricoh2c02 {
     ram: Ram,
     cartridge: Option<Cartridge>,
@@ -402,16 +456,24 @@ pub struct Ricoh2c02 {
     address_latch: bool,
     odd_frame: bool,
     palette: Vec<(u8, u8, u8)>,
-    screen: Vec<Vec<(u8, u8, u8)>>,
+    framebuffer: Vec<u8>,
     palette_ram: [u8; 0x20],
     rendering_sprite_zero: bool,
     scanline_sprites: Vec<Sprite>,
     fg_sprite_lsb_shifters: [u8; 8],
     fg_sprite_msb_shifters: [u8; 8],
+    output_enabled: bool,
+    a12: bool,
+    a12_low_cycles: u32,
+    write_log: Option<Vec<PpuWrite>>,
 }
 
 const CYCLES_PER_SCANLINE: u32 = 341;
 const SCANLINES_PER_FRAME: u32 = 262;
+// The MMC3 ignores A12 rising again until it has been low for a few CPU
+// cycles, so the toggling between nametable and pattern fetches within a
+// tile doesn't clock it.
+const A12_FILTER_CYCLES: u32 = 10;
 
 impl Ricoh2c02 {
     pub fn new() -> Self {
@@ -441,12 +503,16 @@ impl Ricoh2c02 {
             bg_attr_lsb_shifter: 0,
             fine_x_scroll: 0,
             palette: Self::get_palette(),
-            screen: vec![vec![(0, 0, 0); 0x100]; 0xF0],
+            framebuffer: [0, 0, 0, 0xFF].repeat(0x100 * 0xF0),
             palette_ram: [0; 0x20],
             rendering_sprite_zero: false,
             scanline_sprites: vec![],
             fg_sprite_lsb_shifters: [0; 8],
             fg_sprite_msb_shifters: [0; 8],
+            output_enabled: true,
+            a12: false,
+            a12_low_cycles: 0,
+            write_log: None,
         }
     }
 
@@ -454,6 +520,79 @@ impl Ricoh2c02 {
         self.cartridge = Some(cartridge);
     }
 
+    pub fn unload_cartridge(&mut self) -> Option<Cartridge> {
+        self.cartridge.take()
+    }
+
+    /// Puts the PPU back into its power-up state. The cartridge and the
+    /// host's settings, like the palette and whether output is enabled,
+    /// are kept.
+    pub fn reset(&mut self) {
+        let mut ppu = Ricoh2c02::new();
+        ppu.cartridge = self.cartridge.take();
+        ppu.take_host_settings(self);
+        *self = ppu;
+    }
+
+    /// Moves over the settings that belong to the host rather than the
+    /// emulated PPU: the palette, whether output is enabled and the write
+    /// log.
+    pub fn take_host_settings(&mut self, other: &mut Ricoh2c02) {
+        self.palette = std::mem::take(&mut other.palette);
+        self.output_enabled = other.output_enabled;
+        self.write_log = other.write_log.take();
+    }
+
+    /// Moves out nametable RAM, OAM, the framebuffer and the write log,
+    /// leaving them empty until `put_memory` puts them back.
+    pub fn take_memory(&mut self) -> PpuMemory {
+        PpuMemory {
+            ram: std::mem::take(&mut self.ram),
+            oam: std::mem::take(&mut self.primary_oam.oam),
+            framebuffer: std::mem::take(&mut self.framebuffer),
+            write_log: self.write_log.take(),
+        }
+    }
+
+    pub fn put_memory(&mut self, memory: PpuMemory) {
+        self.ram = memory.ram;
+        self.primary_oam.oam = memory.oam;
+        self.framebuffer = memory.framebuffer;
+        self.write_log = memory.write_log;
+    }
+
+    /// When disabled, pixels are no longer calculated or written to the screen,
+    /// but the PPU keeps its timing, so vertical blank and NMIs still happen.
+    pub fn set_output_enabled(&mut self, enabled: bool) {
+        self.output_enabled = enabled;
+    }
+
+    pub fn get_output_enabled(&self) -> bool {
+        self.output_enabled
+    }
+
+    /// Starts or stops logging register writes, for debugging the mid-frame
+    /// scroll and control writes behind split screens.
+    pub fn enable_write_log(&mut self, enabled: bool) {
+        self.write_log = if enabled { Some(vec![]) } else { None };
+    }
+
+    /// The register writes logged since the last call, oldest first.
+    pub fn take_write_log(&mut self) -> Vec<PpuWrite> {
+        match &mut self.write_log {
+            Some(log) => std::mem::take(log),
+            None => vec![],
+        }
+    }
+
+    pub fn get_scanline(&self) -> u32 {
+        self.scanline
+    }
+
+    pub fn get_cycle(&self) -> u32 {
+        self.cycle
+    }
+
     pub fn has_cartridge(&self) -> bool {
         match &self.cartridge {
             Some(_) => true,
@@ -461,8 +600,150 @@ impl Ricoh2c02 {
         }
     }
 
-    pub fn get_screen(&self) -> &Vec<Vec<(u8, u8, u8)>> {
-        &self.screen
+    pub fn get_cartridge(&self) -> Option<&Cartridge> {
+        self.cartridge.as_ref()
+    }
+
+    pub fn get_cartridge_mut(&mut self) -> Option<&mut Cartridge> {
+        self.cartridge.as_mut()
+    }
+
+    /// The color of the pixel last drawn at (`x`, `y`).
+    pub fn get_pixel(&self, x: u32, y: u32) -> (u8, u8, u8) {
+        let index = (y as usize * 0x100 + x as usize) * 4;
+        (
+            self.framebuffer[index],
+            self.framebuffer[index + 1],
+            self.framebuffer[index + 2],
+        )
+    }
+
+    /// The screen as rows of RGB pixels, copied out of the framebuffer.
+    #[deprecated(note = "use get_framebuffer_rgba or get_pixel")]
+    pub fn get_screen(&self) -> Vec<Vec<(u8, u8, u8)>> {
+        self.framebuffer
+            .chunks(0x100 * 4)
+            .map(|row| {
+                row.chunks(4)
+                    .map(|pixel| (pixel[0], pixel[1], pixel[2]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The screen as packed RGBA bytes, row by row, ready to hand to a canvas.
+    pub fn get_framebuffer_rgba(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// The brightness, out of 255, of the pixel last drawn at (`x`, `y`).
+    pub fn get_luminance(&self, x: u32, y: u32) -> u8 {
+        let (red, green, blue) = self.get_pixel(x, y);
+        // ITU-R BT.601 luma weights, scaled to sum to 1000.
+        ((u32::from(red) * 299 + u32::from(green) * 587 + u32::from(blue) * 114) / 1000) as u8
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: (u8, u8, u8)) {
+        let index = (y * 0x100 + x) * 4;
+        self.framebuffer[index] = color.0;
+        self.framebuffer[index + 1] = color.1;
+        self.framebuffer[index + 2] = color.2;
+    }
+
+    /// The 64 colors of the master palette, indexed by palette RAM values.
+    pub fn palette_colors(&self) -> &[(u8, u8, u8)] {
+        &self.palette
+    }
+
+    /// Replaces the master palette with 64 other colors.
+    pub fn set_palette(&mut self, palette: &[(u8, u8, u8)]) -> Result<(), PaletteError> {
+        if palette.len() != PALETTE_SIZE {
+            return Err(PaletteError::WrongColorCount(palette.len()));
+        }
+
+        self.palette = palette.to_vec();
+        Ok(())
+    }
+
+    /// All 64 sprites in OAM as (y, tile, attributes, x), for debugging.
+    pub fn get_oam_sprites(&self) -> Vec<(u8, u8, u8, u8)> {
+        (0..64)
+            .map(|sprite| self.primary_oam.get_sprite(sprite).as_tuple())
+            .collect()
+    }
+
+    /// The sprites found by the last sprite evaluation, which are drawn on
+    /// the next scanline, as (y, tile, attributes, x).
+    pub fn get_secondary_oam_sprites(&self) -> Vec<(u8, u8, u8, u8)> {
+        self.secondary_oam
+            .get_sprites()
+            .iter()
+            .map(Sprite::as_tuple)
+            .collect()
+    }
+
+    /// Draws all 256 tiles of pattern table `table` (0 or 1) as a 16x16
+    /// grid, 128x128 pixels row by row, colored with palette `palette` (0-3
+    /// for the background palettes, 4-7 for the sprite palettes).
+    pub fn render_pattern_table(&self, table: u8, palette: u8) -> Vec<(u8, u8, u8)> {
+        let pattern_table = (table as u16 & 0x01) << 12;
+        let palette = palette as u16 & 0x07;
+
+        (0..128 * 128)
+            .map(|index| {
+                let (x, y) = (index % 128, index / 128);
+                let tile_id = (y / 8 * 16 + x / 8) as u8;
+                let pixel = self.tile_pixel(pattern_table, tile_id, x % 8, y % 8);
+                self.palette_color(palette, pixel)
+            })
+            .collect()
+    }
+
+    /// Draws the nametable at $2000 + `index` * $400 with its attributes and
+    /// the current background pattern table, 256x240 pixels row by row.
+    pub fn render_nametable(&self, index: u8) -> Vec<(u8, u8, u8)> {
+        let nametable = 0x2000 | (index as u16 & 0x03) << 10;
+
+        (0..256 * 240)
+            .map(|pixel_index| {
+                let (x, y) = (pixel_index % 256, pixel_index / 256);
+                let (coarse_x, coarse_y) = (x / 8, y / 8);
+
+                let tile_id = self.ppu_read(nametable | coarse_y << 5 | coarse_x);
+                let attribute =
+                    self.ppu_read(nametable | 0x3C0 | (coarse_y >> 2) << 3 | coarse_x >> 2);
+                // See update_next_bg_tile_attr for the layout of the attribute byte.
+                let shift = (coarse_y & 0x02) << 1 | (coarse_x & 0x02);
+                let palette = (attribute >> shift) as u16 & 0x03;
+
+                let pixel = self.tile_pixel(
+                    self.ppu_ctrl.background_pattern_table_address,
+                    tile_id,
+                    x % 8,
+                    y % 8,
+                );
+                self.palette_color(palette, pixel)
+            })
+            .collect()
+    }
+
+    /// The 2 bit color of pixel (`x`, `y`) of a tile.
+    fn tile_pixel(&self, pattern_table: u16, tile_id: u8, x: u16, y: u16) -> u16 {
+        let address = pattern_table | (tile_id as u16) << 4 | y;
+        let lsb = self.ppu_read(address) >> (7 - x) & 0x01;
+        let msb = self.ppu_read(address | 1 << 3) >> (7 - x) & 0x01;
+        (msb as u16) << 1 | lsb as u16
+    }
+
+    fn palette_color(&self, palette: u16, pixel: u16) -> (u8, u8, u8) {
+        // Transparent pixels show the backdrop color, as they do on screen.
+        let address = if pixel == 0 {
+            0x3F00
+        } else {
+            0x3F00 | palette << 2 | pixel
+        };
+
+        self.palette[(self.ppu_read(address) & 0x3F) as usize]
     }
 
     fn get_palette() -> Vec<(u8, u8, u8)> {
@@ -540,8 +821,6 @@ impl Ricoh2c02 {
 
     pub fn cpu_read(&mut self, address: u16) -> u8 {
         match address {
-            0x2000 => 0,
-            0x2001 => 0,
             0x2002 => {
                 let data = self.ppu_status.get();
                 // Clear bit 7
@@ -552,10 +831,6 @@ impl Ricoh2c02 {
                 // set to lower 5 bits of data buffer
                 data & 0xE0 | self.ppu_data & 0x1F
             }
-            0x2003 => 0,
-            0x2004 => self.primary_oam[self.oam_addr as usize],
-            0x2005 => 0,
-            0x2006 => 0,
             0x2007 => {
                 let address = *self.vram_address;
                 self.vram_address.increment(self.ppu_ctrl.increment_mode);
@@ -566,24 +841,67 @@ impl Ricoh2c02 {
                     0x3F00..=0x3FFF => {
                         // Reading from palette RAM still puts data from VRAM
                         // into the buffer. 
-                        self.ppu_data = match &self.cartridge {
-                            Some(cartridge) => self.ram.read(cartridge.mirroring_type(), address),
-                            None => self.ram.read(MirroringType::Vertical, address),
-                        };
+                        self.ppu_data = self.nametable_read(address & 0x2FFF);
                         self.ppu_read(address)
                     }
                     _ => {
                         let ppu_data = self.ppu_data;
+                        self.update_a12(address);
                         self.ppu_data = self.ppu_read(address);
                         ppu_data
                     }
                 }
             }
+            _ => self.cpu_peek(address),
+        }
+    }
+
+    /// Returns what `cpu_read` would, without clearing the vertical blank
+    /// flag or address latch, incrementing the VRAM address or filling the
+    /// read buffer.
+    pub fn cpu_peek(&self, address: u16) -> u8 {
+        match address {
+            0x2002 => self.ppu_status.get() & 0xE0 | self.ppu_data & 0x1F,
+            0x2004 => self.read_oam_data(),
+            0x2007 => match *self.vram_address {
+                address @ 0x3F00..=0x3FFF => self.ppu_read(address),
+                _ => self.ppu_data,
+            },
             _ => 0,
         }
     }
 
+    // Reading OAMDATA never increments OAMADDR. While sprites are being
+    // evaluated and fetched, it returns whatever the PPU is reading from OAM
+    // itself rather than the byte at OAMADDR.
+    fn read_oam_data(&self) -> u8 {
+        if !self.rendering_enabled() || self.scanline >= 240 {
+            return self.primary_oam[self.oam_addr as usize];
+        }
+
+        match self.cycle {
+            // Secondary OAM is being cleared, which reads 0xFF.
+            1..=64 => 0xFF,
+            // Sprite tiles are being fetched: Y, tile, attributes, then X
+            // for the remaining 5 cycles of each 8 cycle fetch.
+            257..=320 => {
+                let cycle = (self.cycle - 257) as usize;
+                self.secondary_oam[cycle / 8 * 4 + (cycle % 8).min(3)]
+            }
+            _ => self.primary_oam[self.oam_addr as usize],
+        }
+    }
+
     pub fn cpu_write(&mut self, address: u16, data: u8) {
+        if let Some(log) = &mut self.write_log {
+            log.push(PpuWrite {
+                scanline: self.scanline,
+                cycle: self.cycle,
+                register: address,
+                value: data,
+            });
+        }
+
         match address {
             0x2000 => {
                 self.ppu_ctrl.set(data);
@@ -603,7 +921,7 @@ impl Ricoh2c02 {
                     return;
                 }
 
-                self.oam_addr += 1;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
                 self.primary_oam[address as usize] = data;
             }
             0x2005 => {
@@ -629,11 +947,14 @@ impl Ricoh2c02 {
                         .set_field(RegisterBits::AddressLow, data);
                     self.vram_address.copy(&self.temp_vram_address);
                     self.address_latch = false;
+                    // The new address goes out on the PPU's address bus.
+                    self.update_a12(*self.vram_address);
                 }
             }
             0x2007 => {
                 let address = *self.vram_address;
                 self.vram_address.increment(self.ppu_ctrl.increment_mode);
+                self.update_a12(address);
                 self.ppu_write(address, data);
             }
             _ => (),
@@ -651,10 +972,7 @@ impl Ricoh2c02 {
                 Some(cartridge) => cartridge.ppu_read(address),
                 None => 0,
             },
-            0x2000..=0x3EFF => match &self.cartridge {
-                Some(cartridge) => self.ram.read(cartridge.mirroring_type(), address),
-                None => self.ram.read(MirroringType::Vertical, address),
-            },
+            0x2000..=0x3EFF => self.nametable_read(address),
             0x3F00..=0x3FFF => match address & 0x1F {
                 0x10 | 0x14 | 0x18 | 0x1C => {
                     self.palette_ram[(address & 0x0F) as usize] & palette_mask
@@ -665,16 +983,77 @@ impl Ricoh2c02 {
         }
     }
 
+    // The mapper can send each nametable page anywhere, otherwise the
+    // mirroring decides which of the two it is.
+    fn nametable_read(&self, address: u16) -> u8 {
+        let cartridge = match &self.cartridge {
+            Some(cartridge) => cartridge,
+            None => return self.ram.read(MirroringType::Vertical, address),
+        };
+
+        match cartridge.map_nametable(address) {
+            Some(NametableSource::CiramA) => self.ram.read_nametable(0, address),
+            Some(NametableSource::CiramB) => self.ram.read_nametable(1, address),
+            Some(source) => cartridge.nametable_read(source, address),
+            None => self.ram.read(cartridge.mirroring_type(), address),
+        }
+    }
+
+    fn nametable_write(&mut self, address: u16, data: u8) {
+        let cartridge = match &mut self.cartridge {
+            Some(cartridge) => cartridge,
+            None => return self.ram.write(MirroringType::Vertical, address, data),
+        };
+
+        match cartridge.map_nametable(address) {
+            Some(NametableSource::CiramA) => self.ram.write_nametable(0, address, data),
+            Some(NametableSource::CiramB) => self.ram.write_nametable(1, address, data),
+            Some(source) => cartridge.nametable_write(source, address, data),
+            None => self.ram.write(cartridge.mirroring_type(), address, data),
+        }
+    }
+
+    // A rendering fetch. The PPU only drives the address bus with these
+    // while rendering is enabled, so only then can the mapper see them.
+    fn fetch(&mut self, address: u16) -> u8 {
+        if self.rendering_enabled() {
+            self.update_a12(address);
+        }
+        let data = self.ppu_read(address);
+        self.notify_fetch(address);
+        data
+    }
+
+    // Lets the mapper see which pattern bytes are being fetched.
+    fn notify_fetch(&mut self, address: u16) {
+        if let (0x0000..=0x1FFF, Some(cartridge)) = (address, &mut self.cartridge) {
+            cartridge.ppu_fetch_notify(address);
+        }
+    }
+
+    // Tells the mapper when A12 rises, unless it has only been low briefly.
+    fn update_a12(&mut self, address: u16) {
+        let a12 = address & 0x1000 == 0x1000;
+
+        if a12 && !self.a12 && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            if let Some(cartridge) = &mut self.cartridge {
+                cartridge.on_a12_rising();
+            }
+        }
+
+        if a12 {
+            self.a12_low_cycles = 0;
+        }
+        self.a12 = a12;
+    }
+
     pub fn ppu_write(&mut self, address: u16, data: u8) {
         match address {
             0x0000..=0x1FFF => match &mut self.cartridge {
                 Some(cartridge) => cartridge.ppu_write(address, data),
                 None => (),
             },
-            0x2000..=0x3EFF => match &self.cartridge {
-                Some(cartridge) => self.ram.write(cartridge.mirroring_type(), address, data),
-                None => self.ram.write(MirroringType::Vertical, address, data),
-            },
+            0x2000..=0x3EFF => self.nametable_write(address, data),
             0x3F00..=0x3FFF => match address & 0x1F {
                 0x10 | 0x14 | 0x18 | 0x1C => self.palette_ram[(address & 0x0F) as usize] = data,
                 address => self.palette_ram[address as usize] = data,
@@ -697,6 +1076,19 @@ impl Ricoh2c02 {
         };
     }
 
+    pub fn cartridge_cpu_clock(&mut self) {
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.cpu_clock();
+        }
+    }
+
+    pub fn cartridge_irq_pending(&self) -> bool {
+        match &self.cartridge {
+            Some(cartridge) => cartridge.irq_pending(),
+            None => false,
+        }
+    }
+
     pub fn oam_dma(&mut self, address: u16, data: u8) {
         let address = (address as u8).wrapping_add(self.oam_addr);
         self.primary_oam[address as usize] = data;
@@ -707,12 +1099,12 @@ impl Ricoh2c02 {
     }
 
     fn update_next_bg_tile_id(&mut self) {
-        self.next_bg_tile_id = self.ppu_read(0x2000 | self.vram_address.get_nametable_offset());
+        self.next_bg_tile_id = self.fetch(0x2000 | self.vram_address.get_nametable_offset());
     }
 
     fn update_next_bg_tile_attr(&mut self) {
         self.next_bg_tile_attr =
-            self.ppu_read(0x23C0 | self.vram_address.get_attribute_memory_offset());
+            self.fetch(0x23C0 | self.vram_address.get_attribute_memory_offset());
         // The attribute tile is 1 byte and applies
         // to a 4-byte by 4-byte region of the nametable.
         //
@@ -763,7 +1155,7 @@ impl Ricoh2c02 {
     // +--------------- 0: Pattern table is at $0000-$1FFF
     //
     fn update_next_bg_tile_lsb(&mut self) {
-        self.next_bg_tile_lsb = self.ppu_read(
+        self.next_bg_tile_lsb = self.fetch(
             self.ppu_ctrl.background_pattern_table_address
                 | (self.next_bg_tile_id as u16) << 4
                 | 0 << 3
@@ -772,7 +1164,7 @@ impl Ricoh2c02 {
     }
 
     fn update_next_bg_tile_msb(&mut self) {
-        self.next_bg_tile_msb = self.ppu_read(
+        self.next_bg_tile_msb = self.fetch(
             self.ppu_ctrl.background_pattern_table_address
                 | (self.next_bg_tile_id as u16) << 4
                 | 1 << 3
@@ -946,7 +1338,9 @@ impl Ricoh2c02 {
             let sprite_pattern_addr_hi = sprite_pattern_addr_lo | 0x08;
 
             let mut sprite_pattern_lo = self.ppu_read(sprite_pattern_addr_lo);
+            self.notify_fetch(sprite_pattern_addr_lo);
             let mut sprite_pattern_hi = self.ppu_read(sprite_pattern_addr_hi);
+            self.notify_fetch(sprite_pattern_addr_hi);
 
             // If the sprite is flipped horizontally, just reverse the order of the bits.
             if sprite.flipped_horizontally() {
@@ -962,6 +1356,29 @@ impl Ricoh2c02 {
         }
     }
 
+    // The sprite patterns are all read at once by load_foreground_shifters,
+    // but the mapper sees A12 as the real fetches would drive it: each of
+    // the 8 slots takes 8 cycles, reading two garbage nametable bytes and
+    // then the two pattern bytes. Empty slots fetch tile $FF.
+    fn update_sprite_fetch_a12(&mut self) {
+        let cycle = (self.cycle - 257) as usize;
+        let slot = cycle / 8;
+
+        match cycle % 8 {
+            0 | 2 => self.update_a12(0x2000),
+            4 | 6 => {
+                let pattern_table = match self.ppu_ctrl.sprite_size {
+                    SpriteSize::EightByEight => self.ppu_ctrl.sprite_pattern_table_address,
+                    SpriteSize::EightBySixteen => {
+                        (self.secondary_oam[slot * 4 + 1] as u16 & 0x01) << 12
+                    }
+                };
+                self.update_a12(pattern_table);
+            }
+            _ => (),
+        }
+    }
+
     fn calculate_pixel(&mut self) -> (u8, u8, u8) {
         let (bg_pixel, bg_palette) = if self.ppu_mask.background_enable {
             let mask = 0x8000 >> self.fine_x_scroll;
@@ -978,6 +1395,8 @@ impl Ricoh2c02 {
             (0, 0)
         };
 
+        let mut sprite_zero_rendered = false;
+
         let (fg_pixel, fg_palette, fg_priority) = if self.ppu_mask.sprite_enable {
             let mut pixel = 0;
             let mut palette = 0;
@@ -1016,7 +1435,7 @@ impl Ricoh2c02 {
                 priority = sprite.attributes & 0x20 == 0;
 
                 if self.rendering_sprite_zero && sprite_num == 0 {
-                    self.ppu_status.sprite_zero_hit = true;
+                    sprite_zero_rendered = true;
                 }
             }
 
@@ -1025,6 +1444,25 @@ impl Ricoh2c02 {
             (0, 0, false)
         };
 
+        // Sprite zero hit requires an opaque sprite zero pixel over an opaque
+        // background pixel, with both background and sprite rendering enabled.
+        // It never happens at x=255, nor in the left 8 pixels if either layer
+        // is clipped there.
+        let x = self.cycle - 1;
+        let left_column_clipped = x < 8
+            && !(self.ppu_mask.background_left_column_enable
+                && self.ppu_mask.sprite_left_column_enable);
+
+        if sprite_zero_rendered
+            && bg_pixel != 0
+            && self.ppu_mask.background_enable
+            && self.ppu_mask.sprite_enable
+            && x != 255
+            && !left_column_clipped
+        {
+            self.ppu_status.sprite_zero_hit = true;
+        }
+
         let (pixel, palette) = if bg_pixel == 0 && fg_pixel == 0 {
             (0, 0)
         } else if bg_pixel == 0 && fg_pixel != 0 {
@@ -1037,7 +1475,12 @@ impl Ricoh2c02 {
             (bg_pixel, bg_palette)
         };
 
-        self.palette[(self.ppu_read(0x3F00 | palette << 2 | pixel) & 0x3F) as usize]
+        // Straight from palette RAM rather than through ppu_read, as this runs
+        // for every pixel. Transparent pixels are always palette 0's backdrop,
+        // so the mirrored entries at $3F10, $3F14, $3F18 and $3F1C never come
+        // up.
+        let palette_mask = if self.ppu_mask.greyscale { 0x30 } else { 0x3F };
+        self.palette[(self.palette_ram[(palette << 2 | pixel) as usize] & palette_mask) as usize]
     }
 
     pub fn update_background(&mut self) {
@@ -1061,7 +1504,12 @@ impl Ricoh2c02 {
     }
 
     fn visible_scanline(&mut self) {
-        self.update_background_shifters();
+        // The shifters move on cycles 2-257 and 321-337. The first pixel of the
+        // scanline comes straight out of the prefetched tile, so shifting on cycle 1
+        // would leave the shifters one pixel ahead of the tile reloads.
+        if self.cycle != 1 {
+            self.update_background_shifters();
+        }
         self.update_background();
 
         if self.cycle == 256 {
@@ -1107,40 +1555,43 @@ impl Ricoh2c02 {
                         }
                     }
 
-                    let mut current_sprite_byte: usize = 0;
-
-                    loop {
-                        let sprite = self.primary_oam.get_sprite(current_sprite_number);
-
-                        if sprite.in_range_with_sprite_overflow_bug(
-                            self.scanline,
-                            self.ppu_ctrl.get_sprite_height(),
-                            current_sprite_byte,
-                        ) {
-                            self.ppu_status.sprite_overflow = true;
-                            return;
-                        } else {
-                            // Sprite overflow bug - should not be incrementing byte
-                            current_sprite_byte += 1;
-                            current_sprite_number += 1;
-
-                            if current_sprite_byte == 4 {
-                                current_sprite_byte = 0;
-                            }
+                    self.sprite_overflow_evaluation(current_sprite_number);
+                }
+                // OAMADDR is cleared on every cycle of the sprite tile fetches.
+                257..=320 => {
+                    self.oam_addr = 0;
+                    self.update_sprite_fetch_a12();
 
-                            if current_sprite_number >= 64 {
-                                return;
-                            }
-                        }
+                    if self.cycle == 320 {
+                        self.load_foreground_shifters();
                     }
                 }
-                320 => self.load_foreground_shifters(),
                 _ => (),
             },
             _ => {}
         }
     }
 
+    /// Looks for a ninth sprite on the next line, starting after the eighth
+    /// one found, the way the hardware does.
+    fn sprite_overflow_evaluation(&mut self, first_sprite_number: usize) {
+        let mut current_sprite_byte: usize = 0;
+
+        for current_sprite_number in first_sprite_number..64 {
+            let byte = self.primary_oam[current_sprite_number * 4 + current_sprite_byte];
+
+            if Sprite::y_in_range(self.scanline, self.ppu_ctrl.get_sprite_height(), byte) {
+                self.ppu_status.sprite_overflow = true;
+                return;
+            }
+
+            // Sprite overflow bug: only the sprite number should be incremented,
+            // but the byte within the sprite is too (without carry), so the
+            // tile, attribute and X bytes get compared as if they were Y.
+            current_sprite_byte = (current_sprite_byte + 1) & 0x3;
+        }
+    }
+
     pub fn clock(&mut self, nmi_enable: &mut bool) -> bool {
         if self.scanline == 0 && self.cycle == 0 && self.odd_frame && self.rendering_enabled() {
             // Idle cycle, unless it's an odd frame and rendering is enabled.
@@ -1162,6 +1613,7 @@ impl Ricoh2c02 {
             0..=239 | 261 => match self.cycle {
                 1..=256 | 321..=337 => self.visible_scanline(),
                 257 => {
+                    self.update_background_shifters();
                     self.load_background_shifters();
                     if self.rendering_enabled() {
                         self.vram_address
@@ -1194,8 +1646,14 @@ impl Ricoh2c02 {
 
         self.sprite_evaluation();
 
-        if self.cycle < 256 && self.scanline < 240 {
-            self.screen[self.scanline as usize][self.cycle as usize] = self.calculate_pixel();
+        if !self.a12 {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+
+        // Cycle 0 is idle; the visible pixels are output on cycles 1 through 256.
+        if self.output_enabled && (1..=256).contains(&self.cycle) && self.scanline < 240 {
+            let color = self.calculate_pixel();
+            self.set_pixel((self.cycle - 1) as usize, self.scanline as usize, color);
         }
 
         self.cycle += 1;
@@ -1219,7 +1677,117 @@ impl Ricoh2c02 {
 
 #[cfg(test)]
 mod tests {
-    use super::Ricoh2c02;
+    use super::{Oam, PpuWrite, Register, RegisterBits, Ricoh2c02, Sprite};
+    use crate::cartridge::{Cartridge, MirroringType, NametableSource};
+    use crate::mappers::Mapper;
+    use crate::palette::{parse_pal, PaletteError};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A mapper that only counts A12 rising edges.
+    #[derive(Clone)]
+    struct A12Counter {
+        edges: Rc<Cell<u32>>,
+    }
+
+    impl Mapper for A12Counter {
+        fn cpu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn cpu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, Some(0))
+        }
+
+        fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn mirroring_type(&self) -> Option<MirroringType> {
+            Some(MirroringType::Vertical)
+        }
+
+        fn on_a12_rising(&mut self) {
+            self.edges.set(self.edges.get() + 1);
+        }
+    }
+
+    /// A vertically mirrored mapper that sends $2400 to CIRAM A instead.
+    #[derive(Clone)]
+    struct NametableMapper;
+
+    impl Mapper for NametableMapper {
+        fn cpu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, None)
+        }
+
+        fn cpu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn ppu_read(&self, _address: u16) -> (Option<usize>, Option<u8>) {
+            (None, Some(0))
+        }
+
+        fn ppu_write(&mut self, _address: u16, _data: u8) -> Option<usize> {
+            None
+        }
+
+        fn mirroring_type(&self) -> Option<MirroringType> {
+            Some(MirroringType::Vertical)
+        }
+
+        fn map_nametable(&self, address: u16) -> Option<NametableSource> {
+            match address & 0x2C00 {
+                0x2400 => Some(NametableSource::CiramA),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn mapper_nametables() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.load_cartridge(Cartridge::with_mapper(Box::new(NametableMapper)));
+
+        ppu.ppu_write(0x2010, 0x42);
+        assert_eq!(ppu.ppu_read(0x2410), 0x42, "$2400 reads nametable 0");
+        ppu.ppu_write(0x2420, 0x17);
+        assert_eq!(ppu.ppu_read(0x2020), 0x17, "$2400 writes nametable 0");
+
+        ppu.ppu_write(0x2C10, 0x99);
+        assert_eq!(ppu.ppu_read(0x2010), 0x42, "$2C00 is still mirrored");
+        assert_eq!(ppu.ram.read_nametable(1, 0x2C10), 0x99);
+    }
+
+    fn chr_ram_cartridge() -> Cartridge {
+        // iNES header for mapper 0 with a single PRG bank and no CHR ROM (CHR RAM).
+        let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00];
+        raw.resize(0x10 + 0x4000, 0);
+        Cartridge::new(raw).unwrap()
+    }
+
+    fn overlap_sprite_zero(ppu: &mut Ricoh2c02, cycle: u32, mask: u8) {
+        ppu.ppu_mask.set(mask);
+        ppu.cycle = cycle;
+
+        // An opaque background pixel under an opaque sprite zero pixel.
+        ppu.bg_tile_lsb_shifter = 0xFFFF;
+        ppu.rendering_sprite_zero = true;
+        ppu.scanline_sprites = vec![Sprite {
+            top_y_position: 0,
+            tile_id: 0,
+            attributes: 0,
+            left_x_position: 0,
+        }];
+        ppu.fg_sprite_lsb_shifters[0] = 0xFF;
+
+        ppu.calculate_pixel();
+    }
     #[test]
     fn it_works() {
         // Right now, this test does nothing - it just silences warnings.
@@ -1230,4 +1798,783 @@ mod tests {
         ppu.cartridge_cpu_read(0);
         ppu.cartridge_cpu_write(0, 0);
     }
+
+    #[test]
+    fn peek_status() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.ppu_status.set(0x80);
+
+        assert_eq!(ppu.cpu_peek(0x2002) & 0x80, 0x80, "vblank set");
+        assert_eq!(
+            ppu.cpu_peek(0x2002) & 0x80,
+            0x80,
+            "peek doesn't clear vblank"
+        );
+        assert_eq!(ppu.cpu_read(0x2002) & 0x80, 0x80, "vblank still set");
+        assert_eq!(ppu.cpu_read(0x2002) & 0x80, 0x00, "read clears vblank");
+    }
+
+    #[test]
+    fn peek_has_no_side_effects() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.ppu_status.set(0x80);
+        ppu.cpu_write(0x2006, 0x21);
+        ppu.ppu_write(0x2100, 0x42);
+        ppu.ppu_data = 0x17;
+
+        for _ in 0..2 {
+            for address in 0x2000..=0x2007 {
+                ppu.cpu_peek(address);
+            }
+        }
+
+        assert_eq!(ppu.ppu_status.get() & 0x80, 0x80, "vblank still set");
+        assert!(ppu.address_latch, "address latch untouched");
+        assert_eq!(ppu.ppu_data, 0x17, "read buffer untouched");
+
+        ppu.cpu_write(0x2006, 0x00);
+        assert_eq!(*ppu.vram_address, 0x2100);
+        assert_eq!(ppu.cpu_read(0x2007), 0x17, "buffered read");
+        assert_eq!(ppu.cpu_read(0x2007), 0x42);
+    }
+
+    #[test]
+    fn palette_read_buffers_nametable() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.ppu_write(0x2F05, 0x42);
+        ppu.ppu_write(0x3F05, 0x16);
+
+        ppu.cpu_write(0x2006, 0x3F);
+        ppu.cpu_write(0x2006, 0x05);
+        assert_eq!(ppu.cpu_read(0x2007), 0x16, "palette read directly");
+        assert_eq!(ppu.ppu_data, 0x42, "nametable byte underneath buffered");
+
+        // The next read of VRAM returns the buffered byte.
+        ppu.cpu_write(0x2006, 0x20);
+        ppu.cpu_write(0x2006, 0x00);
+        assert_eq!(ppu.cpu_read(0x2007), 0x42);
+    }
+
+    #[test]
+    fn copy_sprite_into_secondary_oam() {
+        let mut primary_oam = Oam::new(64);
+        for byte in 0..0x100 {
+            primary_oam[byte] = byte as u8;
+        }
+
+        let mut secondary_oam = Oam::new(8);
+        secondary_oam.reset();
+        for &sprite in &[3, 10, 63] {
+            secondary_oam.copy_sprite(&primary_oam, sprite);
+        }
+
+        let sprites = secondary_oam.get_sprites();
+        assert_eq!(sprites.len(), 3);
+        for (sprite, &number) in sprites.iter().zip(&[3u8, 10, 63]) {
+            let start = number * 4;
+            assert_eq!(
+                [
+                    sprite.top_y_position,
+                    sprite.tile_id,
+                    sprite.attributes,
+                    sprite.left_x_position
+                ],
+                [start, start + 1, start + 2, start + 3],
+                "sprite {} copied intact",
+                number
+            );
+        }
+        assert_eq!(secondary_oam[12], 0xFF, "later slots untouched");
+    }
+
+    #[test]
+    fn secondary_oam_full() {
+        let mut primary_oam = Oam::new(64);
+        for byte in 0..0x100 {
+            primary_oam[byte] = byte as u8;
+        }
+
+        let mut secondary_oam = Oam::new(8);
+        secondary_oam.reset();
+        for sprite in 0..8 {
+            assert!(
+                !secondary_oam.is_full(),
+                "not full after {} sprites",
+                sprite
+            );
+            secondary_oam.copy_sprite(&primary_oam, sprite);
+        }
+        assert!(secondary_oam.is_full(), "full after 8 sprites");
+        assert_eq!(secondary_oam.get_sprites().len(), 8);
+
+        let before = secondary_oam.oam.clone();
+        secondary_oam.copy_sprite(&primary_oam, 8);
+        assert_eq!(secondary_oam.num_sprites, 8, "9th sprite not counted");
+        assert_eq!(secondary_oam.oam, before, "9th sprite not copied");
+
+        secondary_oam.reset();
+        assert!(!secondary_oam.is_full(), "empty after reset");
+        assert_eq!(secondary_oam.get_sprites().len(), 0);
+    }
+
+    fn evaluate_sprites(sprites: &[(usize, [u8; 4])]) -> Ricoh2c02 {
+        let mut ppu = Ricoh2c02::new();
+        ppu.cpu_write(0x2001, 0x18);
+        ppu.primary_oam.reset();
+        for &(sprite, bytes) in sprites {
+            for (byte, &data) in bytes.iter().enumerate() {
+                ppu.primary_oam[sprite * 4 + byte] = data;
+            }
+        }
+
+        // Evaluate sprites for scanline 10.
+        ppu.scanline = 9;
+        ppu.cycle = 256;
+        ppu.secondary_oam.reset();
+        ppu.sprite_evaluation();
+        ppu
+    }
+
+    #[test]
+    fn sprite_overflow() {
+        let on_line = [10, 0xFF, 0xFF, 0xFF];
+        let eight: Vec<(usize, [u8; 4])> = (0..8).map(|sprite| (sprite, on_line)).collect();
+
+        let ppu = evaluate_sprites(&eight);
+        assert_eq!(ppu.secondary_oam.num_sprites, 8);
+        assert!(
+            !ppu.ppu_status.sprite_overflow,
+            "no overflow with 8 sprites"
+        );
+
+        let mut nine = eight.clone();
+        nine.push((8, on_line));
+        let ppu = evaluate_sprites(&nine);
+        assert!(ppu.ppu_status.sprite_overflow, "overflow with 9 sprites");
+
+        let mut late = eight.clone();
+        late.push((63, on_line));
+        let ppu = evaluate_sprites(&late);
+        assert!(
+            !ppu.ppu_status.sprite_overflow,
+            "sprite 63 is checked against its X byte and missed"
+        );
+
+        let mut missed = eight.clone();
+        missed.push((9, on_line));
+        let ppu = evaluate_sprites(&missed);
+        assert!(
+            !ppu.ppu_status.sprite_overflow,
+            "sprite 9 is checked against its tile byte and missed"
+        );
+
+        let mut false_positive = eight;
+        false_positive.push((9, [0xFF, 10, 0xFF, 0xFF]));
+        let ppu = evaluate_sprites(&false_positive);
+        assert!(
+            ppu.ppu_status.sprite_overflow,
+            "tile byte of sprite 9 is checked as a Y position"
+        );
+    }
+
+    #[test]
+    fn read_oam_data_while_rendering() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.cpu_write(0x2003, 0x05);
+        ppu.cpu_write(0x2004, 0x42);
+        ppu.cpu_write(0x2003, 0x05);
+
+        assert_eq!(ppu.cpu_read(0x2004), 0x42, "read while not rendering");
+        assert_eq!(ppu.cpu_read(0x2004), 0x42, "OAMADDR not incremented");
+        assert_eq!(ppu.oam_addr, 0x05);
+
+        ppu.cpu_write(0x2001, 0x18);
+        ppu.scanline = 10;
+        for cycle in 1..=64 {
+            ppu.cycle = cycle;
+            assert_eq!(
+                ppu.cpu_read(0x2004),
+                0xFF,
+                "clearing secondary OAM on cycle {}",
+                cycle
+            );
+        }
+        assert_eq!(ppu.oam_addr, 0x05, "OAMADDR untouched");
+
+        ppu.scanline = 241;
+        ppu.cycle = 30;
+        assert_eq!(ppu.cpu_read(0x2004), 0x42, "read during vertical blank");
+
+        ppu.scanline = 10;
+        ppu.secondary_oam.reset();
+        for (byte, &data) in [0x10, 0x20, 0x30, 0x40].iter().enumerate() {
+            ppu.secondary_oam[4 + byte] = data;
+        }
+        let fetched: Vec<u8> = (265..273)
+            .map(|cycle| {
+                ppu.cycle = cycle;
+                ppu.cpu_read(0x2004)
+            })
+            .collect();
+        assert_eq!(
+            fetched,
+            vec![0x10, 0x20, 0x30, 0x40, 0x40, 0x40, 0x40, 0x40],
+            "second sprite fetched"
+        );
+    }
+
+    #[test]
+    fn oam_addr_reset_during_sprite_fetches() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+        ppu.scanline = 10;
+        ppu.cycle = 250;
+        ppu.cpu_write(0x2001, 0x18);
+        ppu.cpu_write(0x2003, 0x42);
+
+        while ppu.cycle < 257 {
+            ppu.clock(&mut nmi_enable);
+        }
+        assert_eq!(ppu.oam_addr, 0x42, "untouched before cycle 257");
+
+        ppu.clock(&mut nmi_enable);
+        assert_eq!(ppu.oam_addr, 0x00, "cleared on cycle 257");
+
+        // Not cleared when rendering is disabled.
+        let mut ppu = Ricoh2c02::new();
+        ppu.scanline = 10;
+        ppu.cycle = 257;
+        ppu.cpu_write(0x2003, 0x42);
+        ppu.clock(&mut nmi_enable);
+        assert_eq!(ppu.oam_addr, 0x42, "kept with rendering disabled");
+    }
+
+    #[test]
+    fn pixels_output_on_cycles_1_to_256() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+
+        ppu.ppu_write(0x3F00, 0x16);
+        let backdrop = ppu.palette[0x16];
+
+        // The first frame starts on the pre-render line, so run a second, full one.
+        while !ppu.clock(&mut nmi_enable) {}
+        while !ppu.clock(&mut nmi_enable) {}
+
+        for y in 0..0xF0 {
+            assert_eq!(
+                ppu.get_pixel(0, y),
+                backdrop,
+                "leftmost pixel on line {} drawn",
+                y
+            );
+            assert_eq!(
+                ppu.get_pixel(0xFF, y),
+                backdrop,
+                "rightmost pixel on line {} drawn",
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn sprite_zero_hit() {
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 101, 0x1E);
+        assert!(ppu.ppu_status.sprite_zero_hit, "hit at x=100");
+
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 256, 0x1E);
+        assert!(!ppu.ppu_status.sprite_zero_hit, "no hit at x=255");
+
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 101, 0x0E);
+        assert!(
+            !ppu.ppu_status.sprite_zero_hit,
+            "no hit with sprites disabled"
+        );
+
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 101, 0x16);
+        assert!(
+            !ppu.ppu_status.sprite_zero_hit,
+            "no hit with background disabled"
+        );
+
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 1, 0x1E);
+        assert!(
+            ppu.ppu_status.sprite_zero_hit,
+            "hit at x=0 without clipping"
+        );
+
+        let mut ppu = Ricoh2c02::new();
+        overlap_sprite_zero(&mut ppu, 1, 0x18);
+        assert!(
+            !ppu.ppu_status.sprite_zero_hit,
+            "no hit at x=0 with clipping"
+        );
+    }
+
+    #[test]
+    fn attribute_follows_fine_x_scroll() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+        ppu.load_cartridge(chr_ram_cartridge());
+
+        // Tile 1 is solid color 1.
+        for row in 0..8 {
+            ppu.ppu_write(0x0010 | row, 0xFF);
+        }
+        for address in 0x2000..0x23C0 {
+            ppu.ppu_write(address, 0x01);
+        }
+        // Palette 0 for the top left 16x16 pixels, palette 1 for the top right.
+        ppu.ppu_write(0x23C0, 0b0000_0100);
+        ppu.ppu_write(0x3F01, 0x16);
+        ppu.ppu_write(0x3F05, 0x2A);
+
+        // Scroll 3 pixels right and enable the background.
+        ppu.cpu_write(0x2005, 0x03);
+        ppu.cpu_write(0x2005, 0x00);
+        ppu.cpu_write(0x2001, 0x0A);
+
+        while !ppu.clock(&mut nmi_enable) {}
+        while !ppu.clock(&mut nmi_enable) {}
+
+        assert_eq!(
+            ppu.get_pixel(0, 0),
+            ppu.palette[0x16],
+            "palette 0 at left edge"
+        );
+        assert_eq!(
+            ppu.get_pixel(12, 0),
+            ppu.palette[0x16],
+            "palette 0 up to the boundary"
+        );
+        assert_eq!(
+            ppu.get_pixel(13, 0),
+            ppu.palette[0x2A],
+            "palette 1 from 16 - fine x"
+        );
+    }
+
+    #[test]
+    fn copy_vertical_address() {
+        let mut t = Register::new();
+        t.set_field(RegisterBits::CoarseX, 0x05);
+        t.set_field(RegisterBits::NametableSelectX, 1);
+        t.set_field(RegisterBits::CoarseY, 0x1A);
+        t.set_field(RegisterBits::NametableSelectY, 1);
+        t.set_field(RegisterBits::FineY, 0x06);
+
+        let mut v = Register::new();
+        v.set_field(RegisterBits::CoarseX, 0x11);
+        v.copy_vertical_address(&t);
+
+        assert_eq!(v.get_field(RegisterBits::CoarseY), 0x1A);
+        assert_eq!(v.get_field(RegisterBits::NametableSelectY), 1);
+        assert_eq!(v.get_field(RegisterBits::FineY), 0x06);
+        assert_eq!(v.get_field(RegisterBits::CoarseX), 0x11, "horizontal kept");
+        assert_eq!(v.get_field(RegisterBits::NametableSelectX), 0);
+    }
+
+    #[test]
+    fn vertical_scroll_copied_before_rendering() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+
+        // The bottom left nametable, scrolled to x=40 and y=83.
+        ppu.cpu_write(0x2000, 0x02);
+        ppu.cpu_write(0x2005, 40);
+        ppu.cpu_write(0x2005, 83);
+        ppu.cpu_write(0x2001, 0x0A);
+
+        // The vertical bits are copied during cycles 280 to 304 of the
+        // pre-render scanline, which the PPU starts on.
+        while !(ppu.scanline == 261 && ppu.cycle == 305) {
+            ppu.clock(&mut nmi_enable);
+        }
+
+        let v = &ppu.vram_address;
+        assert_eq!(v.get_field(RegisterBits::CoarseY), 83 >> 3);
+        assert_eq!(v.get_field(RegisterBits::FineY), 83 & 0x07);
+        assert_eq!(v.get_field(RegisterBits::NametableSelectY), 1);
+        assert_eq!(v.get_field(RegisterBits::CoarseX), 40 >> 3, "copied at 257");
+        assert_eq!(v.get_field(RegisterBits::NametableSelectX), 0);
+    }
+
+    #[test]
+    fn attribute_fetch_address() {
+        // Two physical nametables can't tell all four apart, so each
+        // mirroring checks a different pair.
+        for &mirroring in [MirroringType::Vertical, MirroringType::Horizontal].iter() {
+            let mut ppu = Ricoh2c02::new();
+            let cartridge = Cartridge::from_parts(vec![0; 0x4000], vec![], 0, mirroring);
+            ppu.load_cartridge(cartridge.unwrap());
+
+            for nametable in 0..4 {
+                ppu.ppu_write(0x23C0 + 0x400 * nametable, nametable as u8);
+            }
+
+            for nametable in 1..4 {
+                ppu.vram_address
+                    .set_field(RegisterBits::NametableSelect, nametable as u8);
+                let address = 0x23C0 | ppu.vram_address.get_attribute_memory_offset();
+                assert_eq!(
+                    ppu.fetch(address),
+                    ppu.ppu_read(0x23C0 + 0x400 * nametable),
+                    "nametable {} with {:?} mirroring",
+                    nametable,
+                    mirroring
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sprite_zero_hit_with_fine_x_scroll() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+        ppu.load_cartridge(chr_ram_cartridge());
+
+        // Tile 1 is solid color 1. The only one in the nametable covers
+        // x=80 to 87 of scanlines 40 to 47.
+        for row in 0..8 {
+            ppu.ppu_write(0x0010 | row, 0xFF);
+        }
+        ppu.ppu_write(0x2000 + 5 * 32 + 10, 0x01);
+
+        // Sprite zero is also tile 1, at x=76 to 83 of the same scanlines.
+        ppu.cpu_write(0x2003, 0x00);
+        for byte in [39, 0x01, 0x00, 76].iter() {
+            ppu.cpu_write(0x2004, *byte);
+        }
+
+        // Scrolling 3 pixels right moves the tile to x=77 to 84.
+        ppu.cpu_write(0x2005, 0x03);
+        ppu.cpu_write(0x2005, 0x00);
+        ppu.cpu_write(0x2001, 0x1E);
+
+        while !ppu.clock(&mut nmi_enable) {}
+        while !ppu.ppu_status.sprite_zero_hit {
+            assert!(!ppu.clock(&mut nmi_enable), "no hit in the frame");
+        }
+
+        // The pixel was output on the cycle before the one just clocked.
+        assert_eq!(ppu.scanline, 40);
+        assert_eq!(ppu.cycle - 2, 77, "hit at the scrolled tile's left edge");
+    }
+
+    #[test]
+    fn write_log() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+        ppu.cpu_write(0x2005, 0x10);
+        assert_eq!(ppu.take_write_log(), vec![], "off by default");
+
+        ppu.enable_write_log(true);
+        while !(ppu.scanline == 100 && ppu.cycle == 128) {
+            ppu.clock(&mut nmi_enable);
+        }
+        ppu.cpu_write(0x2005, 0x20);
+        ppu.cpu_write(0x2005, 0x30);
+
+        assert_eq!(
+            ppu.take_write_log(),
+            vec![
+                PpuWrite {
+                    scanline: 100,
+                    cycle: 128,
+                    register: 0x2005,
+                    value: 0x20,
+                },
+                PpuWrite {
+                    scanline: 100,
+                    cycle: 128,
+                    register: 0x2005,
+                    value: 0x30,
+                },
+            ]
+        );
+        assert_eq!(ppu.take_write_log(), vec![], "taken");
+    }
+
+    #[test]
+    fn palette_colors() {
+        let ppu = Ricoh2c02::new();
+        let colors = ppu.palette_colors();
+
+        assert_eq!(colors.len(), 64, "64 colors in the palette");
+        assert_eq!(colors[0x01], (0x00, 0x3D, 0xA6), "default palette color");
+    }
+
+    #[test]
+    fn custom_palette() {
+        let mut ppu = Ricoh2c02::new();
+        let bytes: Vec<u8> = (0..192).map(|i| (i * 3) as u8).collect();
+        ppu.set_palette(&parse_pal(&bytes).unwrap()).unwrap();
+
+        ppu.cycle = 1;
+        ppu.ppu_write(0x3F00, 0x16);
+        assert_eq!(ppu.calculate_pixel(), (0xC6, 0xC9, 0xCC), "backdrop 0x16");
+
+        ppu.reset();
+        ppu.cycle = 1;
+        ppu.ppu_write(0x3F00, 0x01);
+        assert_eq!(ppu.calculate_pixel(), (0x09, 0x0C, 0x0F), "kept on reset");
+
+        assert_eq!(
+            ppu.set_palette(&[(0, 0, 0); 63]),
+            Err(PaletteError::WrongColorCount(63))
+        );
+        assert_eq!(ppu.palette_colors()[1], (0x09, 0x0C, 0x0F), "unchanged");
+    }
+
+    #[test]
+    fn palette_mirrors() {
+        let mut ppu = Ricoh2c02::new();
+
+        // Each sprite palette's first entry is the background palette's.
+        let mirrors = [0x3F10, 0x3F14, 0x3F18, 0x3F1C];
+        for (mirror, entry) in mirrors.iter().zip([0x3F00, 0x3F04, 0x3F08, 0x3F0C].iter()) {
+            ppu.ppu_write(*mirror, 0x21);
+            assert_eq!(ppu.ppu_read(*mirror), 0x21);
+            assert_eq!(ppu.ppu_read(*entry), 0x21, "${:04X} aliases it", entry);
+
+            ppu.ppu_write(*entry, 0x12);
+            assert_eq!(ppu.ppu_read(*mirror), 0x12, "and the other way round");
+        }
+
+        // The other background entries are separate from the backdrop.
+        ppu.ppu_write(0x3F00, 0x0F);
+        for address in [0x3F04, 0x3F08, 0x3F0C].iter() {
+            ppu.ppu_write(*address, 0x30);
+            assert_eq!(ppu.ppu_read(0x3F00), 0x0F, "${:04X} not aliased", address);
+        }
+
+        // So are the other sprite entries.
+        ppu.ppu_write(0x3F11, 0x25);
+        assert_eq!(ppu.ppu_read(0x3F01), 0x00);
+
+        // The 32 bytes repeat up to $3FFF.
+        assert_eq!(ppu.ppu_read(0x3F31), 0x25);
+        assert_eq!(ppu.ppu_read(0x3FF0), 0x0F);
+    }
+
+    #[test]
+    fn pattern_table_viewer() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.load_cartridge(chr_ram_cartridge());
+
+        // Tile 0x11 of the second pattern table is color 1 on top, color 2
+        // on the bottom row, and color 3 in the bottom right pixel.
+        for row in 0..7 {
+            ppu.ppu_write(0x1110 | row, 0xFF);
+        }
+        ppu.ppu_write(0x1117, 0x01);
+        ppu.ppu_write(0x111F, 0xFF);
+        ppu.ppu_write(0x3F00, 0x0F);
+        ppu.ppu_write(0x3F19, 0x16);
+        ppu.ppu_write(0x3F1A, 0x2A);
+        ppu.ppu_write(0x3F1B, 0x30);
+
+        let image = ppu.render_pattern_table(1, 6);
+        assert_eq!(image.len(), 128 * 128);
+
+        // Tile 0x11 is at (8, 8) in the grid.
+        let pixel = |x: usize, y: usize| image[y * 128 + x];
+        assert_eq!(pixel(8, 8), ppu.palette[0x16], "color 1");
+        assert_eq!(pixel(15, 14), ppu.palette[0x16], "color 1");
+        assert_eq!(pixel(8, 15), ppu.palette[0x2A], "color 2");
+        assert_eq!(pixel(15, 15), ppu.palette[0x30], "color 3");
+        assert_eq!(pixel(7, 8), ppu.palette[0x0F], "neighboring tile blank");
+
+        let first_table = ppu.render_pattern_table(0, 6);
+        assert!(
+            first_table.iter().all(|&color| color == ppu.palette[0x0F]),
+            "first pattern table is blank"
+        );
+    }
+
+    #[test]
+    fn nametable_viewer() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.load_cartridge(chr_ram_cartridge());
+
+        // Tile 1 is solid color 1.
+        for row in 0..8 {
+            ppu.ppu_write(0x0010 | row, 0xFF);
+        }
+        ppu.ppu_write(0x3F00, 0x0F);
+        ppu.ppu_write(0x3F01, 0x16);
+        ppu.ppu_write(0x3F0D, 0x2A);
+
+        // Put tile 1 at (3, 2) and (31, 29) of the third nametable, with
+        // palette 3 for the bottom right corner. The cartridge mirrors
+        // horizontally, so this doesn't show up in the first nametable.
+        ppu.ppu_write(0x2800 + 2 * 32 + 3, 0x01);
+        ppu.ppu_write(0x2800 + 29 * 32 + 31, 0x01);
+        ppu.ppu_write(0x2BFF, 0b0000_1100);
+
+        let image = ppu.render_nametable(2);
+        assert_eq!(image.len(), 256 * 240);
+
+        let pixel = |x: usize, y: usize| image[y * 256 + x];
+        assert_eq!(pixel(24, 16), ppu.palette[0x16], "tile at (3, 2)");
+        assert_eq!(pixel(31, 23), ppu.palette[0x16], "tile at (3, 2)");
+        assert_eq!(pixel(32, 16), ppu.palette[0x0F], "backdrop");
+        assert_eq!(pixel(255, 239), ppu.palette[0x2A], "tile at (31, 29)");
+
+        let colored = image
+            .iter()
+            .filter(|&&color| color != ppu.palette[0x0F])
+            .count();
+        assert_eq!(colored, 2 * 64, "only the two tiles are drawn");
+
+        assert!(
+            ppu.render_nametable(0)
+                .iter()
+                .all(|&color| color == ppu.palette[0x0F]),
+            "first nametable is blank"
+        );
+    }
+
+    #[test]
+    fn oam_sprites() {
+        let mut ppu = Ricoh2c02::new();
+        ppu.cpu_write(0x2003, 0x00);
+        for byte in 0..=0xFF {
+            ppu.cpu_write(0x2004, byte);
+        }
+
+        let sprites = ppu.get_oam_sprites();
+        assert_eq!(sprites.len(), 64);
+        assert_eq!(sprites[0], (0x00, 0x01, 0x02, 0x03));
+        assert_eq!(sprites[63], (0xFC, 0xFD, 0xFE, 0xFF));
+        assert_eq!(ppu.oam_addr, 0x00, "OAMADDR wrapped around");
+
+        // Sprites 1 and 2 are on scanline 10.
+        for sprite in 0..64 {
+            let y = if sprite == 1 || sprite == 2 { 10 } else { 0xFF };
+            ppu.cpu_write(0x2003, sprite * 4);
+            ppu.cpu_write(0x2004, y);
+        }
+        ppu.cpu_write(0x2001, 0x18);
+        ppu.scanline = 9;
+        ppu.cycle = 256;
+        ppu.secondary_oam.reset();
+        ppu.sprite_evaluation();
+
+        let secondary = ppu.get_secondary_oam_sprites();
+        assert_eq!(
+            secondary,
+            vec![(10, 0x05, 0x06, 0x07), (10, 0x09, 0x0A, 0x0B)]
+        );
+    }
+
+    /// Counts the A12 rising edges on each scanline of a frame rendered with
+    /// `ppu_ctrl` selecting the pattern tables.
+    fn a12_edges_per_scanline(ppu_ctrl: u8) -> Vec<u32> {
+        let edges = Rc::new(Cell::new(0));
+        let mut ppu = Ricoh2c02::new();
+        ppu.load_cartridge(Cartridge::with_mapper(Box::new(A12Counter {
+            edges: Rc::clone(&edges),
+        })));
+        ppu.cpu_write(0x2000, ppu_ctrl);
+        ppu.cpu_write(0x2001, 0x18);
+
+        let mut nmi_enable = false;
+        let mut run_to = |ppu: &mut Ricoh2c02, scanline: u32| {
+            while ppu.get_scanline() != scanline || ppu.get_cycle() != 0 {
+                ppu.clock(&mut nmi_enable);
+            }
+        };
+
+        // Skip the short first frame.
+        run_to(&mut ppu, 0);
+        (0..262)
+            .map(|scanline| {
+                edges.set(0);
+                run_to(&mut ppu, (scanline + 1) % 262);
+                edges.get()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a12_rising_edges() {
+        let rendered_lines: Vec<u32> = (0..262)
+            .map(|scanline| (scanline < 240 || scanline == 261) as u32)
+            .collect();
+
+        // Background at $0000 and sprites at $1000: the sprite fetches raise A12.
+        assert_eq!(a12_edges_per_scanline(0x08), rendered_lines);
+        // Background at $1000 and sprites at $0000: the first background fetch
+        // after the sprite fetches raises A12, and the nametable fetches in
+        // between background tiles are too short to count. The pre-render
+        // line also sees the first fetch after vertical blank.
+        let mut background_lines = rendered_lines.clone();
+        background_lines[261] = 2;
+        assert_eq!(a12_edges_per_scanline(0x10), background_lines);
+        // Everything at $1000: A12 never stays low long enough, except
+        // through vertical blank.
+        let mut vblank_only = vec![0; 262];
+        vblank_only[261] = 1;
+        assert_eq!(a12_edges_per_scanline(0x18), vblank_only);
+        // Everything at $0000: A12 never rises.
+        assert_eq!(a12_edges_per_scanline(0x00), vec![0; 262]);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn framebuffer_matches_screen() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+
+        // Give the backdrop a color so the frame isn't all one value.
+        ppu.ppu_write(0x3F00, 0x16);
+        while !ppu.clock(&mut nmi_enable) {}
+
+        let screen = ppu.get_screen();
+        let framebuffer = ppu.get_framebuffer_rgba();
+        assert_eq!(screen.len(), 0xF0);
+
+        for (y, row) in screen.iter().enumerate() {
+            assert_eq!(row.len(), 0x100);
+            for (x, &(red, green, blue)) in row.iter().enumerate() {
+                let index = (y * 0x100 + x) * 4;
+                assert_eq!(
+                    &framebuffer[index..index + 4],
+                    &[red, green, blue, 0xFF],
+                    "pixel ({}, {}) matches",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn framebuffer_rgba() {
+        let mut ppu = Ricoh2c02::new();
+        let mut nmi_enable = false;
+
+        // Give the backdrop a color so the frame isn't all one value.
+        ppu.ppu_write(0x3F00, 0x16);
+        while !ppu.clock(&mut nmi_enable) {}
+        while !ppu.clock(&mut nmi_enable) {}
+
+        let (red, green, blue) = ppu.palette[0x16];
+        let framebuffer = ppu.get_framebuffer_rgba();
+        assert_eq!(framebuffer.len(), 0x100 * 0xF0 * 4);
+
+        for (index, pixel) in framebuffer.chunks(4).enumerate() {
+            assert_eq!(pixel, &[red, green, blue, 0xFF], "pixel {} is RGBA", index);
+            assert_eq!(
+                ppu.get_pixel(index as u32 % 0x100, index as u32 / 0x100),
+                (red, green, blue)
+            );
+        }
+    }
 }
@@ -0,0 +1,131 @@
+use crate::controller::OPEN_BUS;
+use crate::ricoh2c02::Ricoh2c02;
+
+// How many scanlines a pixel keeps the light sensor lit after the beam
+// passes it.
+const LIGHT_SCANLINES: u32 = 20;
+// The luminance, out of 255, that counts as light.
+const LIGHT_THRESHOLD: u8 = 0x80;
+
+/// The NES Zapper light gun, plugged into the second controller port.
+#[derive(Clone)]
+pub struct Zapper {
+    aim: Option<(u8, u8)>,
+    trigger: bool,
+}
+
+impl Default for Zapper {
+    fn default() -> Self {
+        Zapper::new()
+    }
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Zapper {
+            aim: None,
+            trigger: false,
+        }
+    }
+
+    /// Points the gun at pixel (`x`, `y`) of the screen.
+    pub fn aim(&mut self, x: u8, y: u8) {
+        self.aim = Some((x, y));
+    }
+
+    /// Points the gun away from the screen, so it never sees light.
+    pub fn aim_off_screen(&mut self) {
+        self.aim = None;
+    }
+
+    pub fn set_trigger(&mut self, down: bool) {
+        self.trigger = down;
+    }
+
+    /// The value read from $4017: bit 3 is clear while light is sensed and
+    /// bit 4 is set while the trigger is held, with open bus in bit 6.
+    pub(crate) fn read(&self, ppu: &Ricoh2c02) -> u8 {
+        let mut data = OPEN_BUS;
+
+        if !self.light_sensed(ppu) {
+            data |= 0x08;
+        }
+
+        if self.trigger {
+            data |= 0x10;
+        }
+
+        data
+    }
+
+    // The sensor only sees a pixel for a short while after the beam draws it.
+    fn light_sensed(&self, ppu: &Ricoh2c02) -> bool {
+        let (x, y) = match self.aim {
+            Some((x, y)) if u32::from(y) < 240 => (u32::from(x), u32::from(y)),
+            _ => return false,
+        };
+
+        let scanline = ppu.get_scanline();
+        // Pixel x is drawn on cycle x + 1, and the cycle has moved on since.
+        let drawn = scanline > y || (scanline == y && ppu.get_cycle() > x + 1);
+
+        drawn && scanline - y <= LIGHT_SCANLINES && ppu.get_luminance(x, y) >= LIGHT_THRESHOLD
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Zapper;
+    use crate::ricoh2c02::Ricoh2c02;
+
+    fn run_to(ppu: &mut Ricoh2c02, scanline: u32, cycle: u32) {
+        let mut nmi_enable = false;
+        while ppu.get_scanline() != scanline || ppu.get_cycle() != cycle {
+            ppu.clock(&mut nmi_enable);
+        }
+    }
+
+    fn ppu_with_backdrop(color: u8) -> Ricoh2c02 {
+        let mut ppu = Ricoh2c02::new();
+        ppu.ppu_write(0x3F00, color);
+        // Skip the short first frame.
+        run_to(&mut ppu, 0, 0);
+        ppu
+    }
+
+    #[test]
+    fn light_sensor() {
+        let mut zapper = Zapper::new();
+        zapper.aim(100, 50);
+
+        let mut ppu = ppu_with_backdrop(0x30);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x08, "not drawn yet");
+        run_to(&mut ppu, 50, 100);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x08, "beam not there yet");
+        run_to(&mut ppu, 51, 0);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x00, "white pixel sensed");
+        run_to(&mut ppu, 80, 0);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x08, "faded");
+
+        let mut ppu = ppu_with_backdrop(0x0F);
+        run_to(&mut ppu, 51, 0);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x08, "black pixel not sensed");
+
+        let mut ppu = ppu_with_backdrop(0x30);
+        zapper.aim_off_screen();
+        run_to(&mut ppu, 51, 0);
+        assert_eq!(zapper.read(&ppu) & 0x08, 0x08, "aimed off screen");
+    }
+
+    #[test]
+    fn trigger() {
+        let ppu = Ricoh2c02::new();
+        let mut zapper = Zapper::new();
+
+        assert_eq!(zapper.read(&ppu) & 0x10, 0x00, "released");
+        zapper.set_trigger(true);
+        assert_eq!(zapper.read(&ppu) & 0x10, 0x10, "pulled");
+        zapper.set_trigger(false);
+        assert_eq!(zapper.read(&ppu) & 0x10, 0x00, "released again");
+    }
+}